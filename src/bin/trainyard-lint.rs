@@ -0,0 +1,53 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use trainyard_rust::levels::LevelManager;
+use trainyard_rust::levels::lint::lint_levels;
+
+// Checks level packs before they are shipped:
+//     cargo run --no-default-features --bin trainyard-lint -- assets/levels.txt ~/.local/share/trainyard/user_levels.txt
+// Exits with a non-zero code if any file has a problem. Without the default features the game has no sound,
+// so this builds without the ALSA libraries, e.g. on a CI machine.
+
+const DEFAULT_LEVELS_FILENAME: &str = "assets/levels.txt";
+
+fn lint_file(path: &Path) -> Result<Vec<String>, String> {
+    // returns the problems found in the file, or an error if it couldn't be read at all.
+    let text = std::fs::read_to_string(path).map_err(|err| format!("could not read the file: {err}"))?;
+    let level_manager = match LevelManager::parse(&text) {
+        Ok(level_manager) => level_manager,
+        Err(err) => return Ok(err.problems.iter().map(|problem| problem.to_string()).collect()),
+    };
+    Ok(lint_levels(&level_manager).iter().map(|problem| problem.to_string()).collect())
+}
+
+fn main() -> ExitCode {
+    let mut paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        paths.push(DEFAULT_LEVELS_FILENAME.to_owned());
+    }
+
+    let mut num_problems = 0;
+    for path in &paths {
+        match lint_file(Path::new(path)) {
+            Ok(problems) => {
+                for problem in &problems {
+                    println!("{path}: {problem}");
+                }
+                num_problems += problems.len();
+            }
+            Err(err) => {
+                println!("{path}: {err}");
+                num_problems += 1;
+            }
+        }
+    }
+
+    if num_problems == 0 {
+        println!("no problems found in {} file(s)", paths.len());
+        ExitCode::SUCCESS
+    } else {
+        println!("found {num_problems} problem(s)");
+        ExitCode::FAILURE
+    }
+}
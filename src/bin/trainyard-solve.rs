@@ -0,0 +1,68 @@
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use trainyard_rust::levels::{LevelManager, DEBUG_CITY};
+use trainyard_rust::solver::solve;
+
+// Checks that the solver (and so the hint button) can win every level of the packs:
+//     cargo run --release --no-default-features --bin trainyard-solve -- assets/levels.txt
+// Lists the levels it couldn't solve at the end, and exits with a non-zero code if there are any.
+// The levels of the Debug city are skipped, like in trainyard-lint.
+
+const DEFAULT_LEVELS_FILENAME: &str = "assets/levels.txt";
+
+fn unsolved_levels(path: &Path) -> Result<Vec<String>, String> {
+    // returns the names of the levels of the file that the solver couldn't win. Some levels take a while,
+    // so each level is printed as soon as it is done.
+    let text = std::fs::read_to_string(path).map_err(|err| format!("could not read the file: {err}"))?;
+    let level_manager = LevelManager::parse(&text).map_err(|err| format!("could not parse the file: {err}"))?;
+    let mut unsolved = vec![];
+    for city_name in level_manager.get_city_names() {
+        if city_name == DEBUG_CITY {
+            continue;
+        }
+        for level_name in level_manager.get_names_in_city(&city_name) {
+            let level = level_manager.get_level(&level_name);
+            let start = Instant::now();
+            let solved = solve(level, level_manager.color_rules()).is_some();
+            let result = if solved {"solved"} else {"no solution found"};
+            println!("{}: level `{level_name}`: {result} ({:.1}s)", path.display(), start.elapsed().as_secs_f64());
+            if !solved {
+                unsolved.push(level_name);
+            }
+        }
+    }
+    Ok(unsolved)
+}
+
+fn main() -> ExitCode {
+    let mut paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        paths.push(DEFAULT_LEVELS_FILENAME.to_owned());
+    }
+
+    let mut num_unsolved = 0;
+    for path in &paths {
+        match unsolved_levels(Path::new(path)) {
+            Ok(unsolved) => {
+                if !unsolved.is_empty() {
+                    println!("{path}: no solution found for {}", unsolved.join(", "));
+                }
+                num_unsolved += unsolved.len();
+            }
+            Err(err) => {
+                println!("{path}: {err}");
+                num_unsolved += 1;
+            }
+        }
+    }
+
+    if num_unsolved == 0 {
+        println!("solved every level in {} file(s)", paths.len());
+        ExitCode::SUCCESS
+    } else {
+        println!("found {num_unsolved} level(s) without a solution");
+        ExitCode::FAILURE
+    }
+}
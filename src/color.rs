@@ -1,5 +1,10 @@
 use macroquad::color::Color as macroColor;
 use serde::{Serialize, Deserialize};
+use std::sync::RwLock;
+
+pub mod glyph;
+pub mod rules;
+use rules::CustomColor;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Color {
@@ -10,48 +15,63 @@ pub enum Color {
     Purple,
     Green,
     Orange,
+    // a color declared by the level pack, see rules.rs
+    Custom(u8),
 }
 
-impl Color {
-    pub fn mix_with(self: Color, other: Color) -> Color {
-        if self == other {
-            return other;
-        }
+pub const BUILTIN_COLORS: [Color; 7] = [
+    Color::Brown,
+    Color::Red,
+    Color::Blue,
+    Color::Yellow,
+    Color::Purple,
+    Color::Green,
+    Color::Orange,
+];
 
-        if (self == Color::Blue && other == Color::Red)
-            || (self == Color::Red && other == Color::Blue)
-        {
-            return Color::Purple;
-        }
-        if (self == Color::Yellow && other == Color::Red)
-            || (self == Color::Red && other == Color::Yellow)
-        {
-            return Color::Orange;
-        }
-        if (self == Color::Yellow && other == Color::Blue)
-            || (self == Color::Blue && other == Color::Yellow)
-        {
-            return Color::Green;
-        }
+// the colors declared by the level pack on screen, only used to draw them. The rules trains follow belong
+// to each Simulation, see rules.rs.
+static PALETTE: RwLock<Vec<CustomColor>> = RwLock::new(Vec::new());
 
-        Color::Brown
-    }
-    pub fn mix_many(trains: Vec<Color>) -> Color {
-        match trains.len() {
-            1 => trains[0],
-            2 => trains[1].mix_with(trains[0]),
-            _ => {
-                for i in 0..(trains.len() - 1) {
-                    if trains[i] != trains[i + 1] {
-                        return Color::Brown;
-                    }
-                }
-                trains[0]
-            }
-        }
-    }
+pub fn set_palette(custom_colors: Vec<CustomColor>) {
+    *PALETTE.write().unwrap() = custom_colors;
+}
+
+pub fn palette() -> Vec<CustomColor> {
+    PALETTE.read().unwrap().clone()
+}
+
+pub fn palette_color(index: u8) -> Option<CustomColor> {
+    PALETTE.read().unwrap().get(index as usize).cloned()
+}
 
+// how the colors are shown, for players who can't tell some of them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorblindMode {
+    // draws a shape of its own for each color on trains and colored icons, see glyph.rs
+    pub glyphs: bool,
+    // tints with colors that stay apart for the common kinds of colorblindness
+    pub high_contrast: bool,
+}
+
+static COLORBLIND_MODE: RwLock<ColorblindMode> = RwLock::new(ColorblindMode { glyphs: false, high_contrast: false });
+
+pub fn set_colorblind_mode(mode: ColorblindMode) {
+    *COLORBLIND_MODE.write().unwrap() = mode;
+}
+
+pub fn colorblind_mode() -> ColorblindMode {
+    *COLORBLIND_MODE.read().unwrap()
+}
+
+impl Color {
     pub fn get_color(&self) -> macroColor {
+        if colorblind_mode().high_contrast {
+            if let Some(color) = self.get_high_contrast_color() {
+                return color;
+            }
+        }
         match self {
             Color:: Brown => macroColor::new(0.471, 0.333, 0.231, 1.),
             Color::Blue => macroColor::new(0.165, 0.314, 0.773, 1.),
@@ -60,6 +80,29 @@ impl Color {
             Color::Orange => macroColor::new(0.914, 0.624, 0.220, 1.),
             Color::Green => macroColor::new(0.376, 0.788, 0.231, 1.),
             Color::Purple => macroColor::new(0.631, 0.125, 0.773, 1.),
+            // a color the current pack doesn't declare can still come from an old replay
+            Color::Custom(index) => match palette_color(*index) {
+                Some(custom_color) => {
+                    let [r, g, b] = custom_color.rgb;
+                    macroColor::from_rgba(r, g, b, 255)
+                }
+                None => macroColor::new(0.5, 0.5, 0.5, 1.),
+            },
+        }
+    }
+
+    fn get_high_contrast_color(&self) -> Option<macroColor> {
+        // based on the Okabe-Ito palette, with brown turned into a dark gray since it is the color most
+        // often confused with red and green. The colors declared by a level pack keep their own tint.
+        match self {
+            Color::Brown => Some(macroColor::from_rgba(77, 77, 77, 255)),
+            Color::Blue => Some(macroColor::from_rgba(0, 114, 178, 255)),
+            Color::Red => Some(macroColor::from_rgba(213, 94, 0, 255)),
+            Color::Yellow => Some(macroColor::from_rgba(240, 228, 66, 255)),
+            Color::Orange => Some(macroColor::from_rgba(230, 159, 0, 255)),
+            Color::Green => Some(macroColor::from_rgba(0, 158, 115, 255)),
+            Color::Purple => Some(macroColor::from_rgba(204, 121, 167, 255)),
+            Color::Custom(_) => None,
         }
     }
 }
@@ -0,0 +1,59 @@
+use macroquad::prelude::*;
+use crate::color::{colorblind_mode, palette_color, Color};
+
+// In colorblind mode, everything that shows the color of a train also shows its glyph: a small shape
+// that is different for every color, so that the color can be told without seeing the tint.
+//     brown: square, red: circle, blue: triangle, yellow: bar, purple: diamond, green: plus, orange: cross
+// The colors declared by a level pack show the first letter of their name.
+
+pub fn draw_glyph(color: Color, center_x: f32, center_y: f32, size: f32, alpha: f32) {
+    // size is the width of the glyph. Nothing is drawn unless glyphs are turned on.
+    if !colorblind_mode().glyphs {
+        return;
+    }
+    let ink = glyph_ink(color, alpha);
+    let half = size / 2.;
+    let thickness = size * 0.25;
+    match color {
+        Color::Brown => draw_rectangle(center_x - half * 0.8, center_y - half * 0.8, size * 0.8, size * 0.8, ink),
+        Color::Red => draw_circle(center_x, center_y, half, ink),
+        Color::Blue => draw_triangle(
+            Vec2::new(center_x, center_y - half),
+            Vec2::new(center_x - half, center_y + half * 0.8),
+            Vec2::new(center_x + half, center_y + half * 0.8),
+            ink,
+        ),
+        Color::Yellow => draw_rectangle(center_x - half, center_y - thickness / 2., size, thickness, ink),
+        Color::Purple => {
+            let (top, bottom) = (Vec2::new(center_x, center_y - half), Vec2::new(center_x, center_y + half));
+            draw_triangle(top, bottom, Vec2::new(center_x - half * 0.7, center_y), ink);
+            draw_triangle(top, bottom, Vec2::new(center_x + half * 0.7, center_y), ink);
+        }
+        Color::Green => {
+            draw_rectangle(center_x - half, center_y - thickness / 2., size, thickness, ink);
+            draw_rectangle(center_x - thickness / 2., center_y - half, thickness, size, ink);
+        }
+        Color::Orange => {
+            let arm = half * 0.85;
+            draw_line(center_x - arm, center_y - arm, center_x + arm, center_y + arm, thickness, ink);
+            draw_line(center_x - arm, center_y + arm, center_x + arm, center_y - arm, thickness, ink);
+        }
+        Color::Custom(index) => {
+            let letter = palette_color(index)
+                .and_then(|custom_color| custom_color.name.chars().next())
+                .map_or("?".to_owned(), |letter| letter.to_uppercase().to_string());
+            let font_size = size * 1.4;
+            let text_size = measure_text(&letter, None, font_size as u16, 1.);
+            draw_text(&letter, center_x - text_size.width / 2., center_y + text_size.offset_y / 2., font_size, ink);
+        }
+    }
+}
+
+fn glyph_ink(color: Color, alpha: f32) -> macroquad::color::Color {
+    // black on light tints and white on dark ones
+    let tint = color.get_color();
+    let luminance = 0.299 * tint.r + 0.587 * tint.g + 0.114 * tint.b;
+    let mut ink = if luminance > 0.5 {BLACK} else {WHITE};
+    ink.a = alpha;
+    ink
+}
@@ -0,0 +1,113 @@
+use serde::{Serialize, Deserialize};
+
+use crate::color::{Color, BUILTIN_COLORS};
+
+// The rules that decide what color trains become when they meet, and what a splitter turns them into.
+// A level pack can replace the classic rules with its own, and add its own colors, at the top of its levels
+// file. See the header of assets/levels.txt for the syntax. Each Simulation holds the rules of its pack, and
+// hands them down to the tiles and edges where trains meet.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ManyTrainMix {
+    // three or more trains meeting keep their color if they all share it, and become the fallback otherwise.
+    Same,
+    // the trains are mixed two at a time, in the order they met.
+    Pairwise,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomColor {
+    pub name: String,
+    pub rgb: [u8; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColorRules {
+    // the colors a pack adds to the builtin ones. Color::Custom(i) is the i-th one.
+    pub custom_colors: Vec<CustomColor>,
+    // two trains of different colors meeting become the color of their rule, in whichever order they are
+    // given, or the fallback if there is none. Two trains of the same color always keep it.
+    pub mixes: Vec<([Color; 2], Color)>,
+    pub fallback: Color,
+    pub many_train_mix: ManyTrainMix,
+    // the trains going left and right out of a splitter. Colors without a rule are split into two trains
+    // of their own color.
+    pub splits: Vec<(Color, [Color; 2])>,
+}
+
+impl ColorRules {
+    pub fn classic_mixes() -> Vec<([Color; 2], Color)> {
+        vec![
+            ([Color::Red, Color::Blue], Color::Purple),
+            ([Color::Red, Color::Yellow], Color::Orange),
+            ([Color::Blue, Color::Yellow], Color::Green),
+        ]
+    }
+
+    pub fn classic_splits() -> Vec<(Color, [Color; 2])> {
+        vec![
+            (Color::Orange, [Color::Yellow, Color::Red]),
+            (Color::Purple, [Color::Blue, Color::Red]),
+            (Color::Green, [Color::Blue, Color::Yellow]),
+        ]
+    }
+
+    pub fn mix(&self, a: Color, b: Color) -> Color {
+        if a == b {
+            return a;
+        }
+        self.mixes.iter()
+            .find(|(colors, _)| *colors == [a, b] || *colors == [b, a])
+            .map_or(self.fallback, |(_, result)| *result)
+    }
+
+    pub fn mix_many(&self, trains: &[Color]) -> Color {
+        match (trains.len(), self.many_train_mix) {
+            (1, _) => trains[0],
+            (2, _) => self.mix(trains[1], trains[0]),
+            (_, ManyTrainMix::Same) => {
+                if trains.iter().all(|color| *color == trains[0]) {
+                    trains[0]
+                } else {
+                    self.fallback
+                }
+            }
+            (_, ManyTrainMix::Pairwise) => trains[1..].iter().fold(trains[0], |mixed, color| self.mix(mixed, *color)),
+        }
+    }
+
+    // the colors of the trains going left and right out of a splitter.
+    pub fn split(&self, color: Color) -> [Color; 2] {
+        self.splits.iter()
+            .find(|(split_color, _)| *split_color == color)
+            .map_or([color, color], |(_, result)| *result)
+    }
+
+    // the builtin colors, followed by the ones the pack declares.
+    pub fn all_colors(&self) -> Vec<Color> {
+        let custom_colors = (0..self.custom_colors.len()).map(|index| Color::Custom(index as u8));
+        BUILTIN_COLORS.into_iter().chain(custom_colors).collect()
+    }
+
+    pub fn custom_color(&self, index: u8) -> Option<&CustomColor> {
+        self.custom_colors.get(index as usize)
+    }
+
+    pub fn find_custom_color(&self, name: &str) -> Option<Color> {
+        self.custom_colors.iter()
+            .position(|custom_color| custom_color.name == name)
+            .map(|index| Color::Custom(index as u8))
+    }
+}
+
+impl Default for ColorRules {
+    fn default() -> Self {
+        ColorRules {
+            custom_colors: vec![],
+            mixes: ColorRules::classic_mixes(),
+            fallback: Color::Brown,
+            many_train_mix: ManyTrainMix::Same,
+            splits: ColorRules::classic_splits(),
+        }
+    }
+}
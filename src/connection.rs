@@ -33,3 +33,16 @@ impl Connection {
         self.dir1 == dir || self.dir2 == dir
     }
 }
+
+// Unlike a Connection, a DirectedConnection can only be traversed one way: from the side `from` to the side `to`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectedConnection {
+    pub from: u8,
+    pub to: u8,
+}
+
+impl DirectedConnection {
+    pub fn undirected(&self) -> Connection {
+        Connection { dir1: self.from, dir2: self.to }
+    }
+}
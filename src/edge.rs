@@ -1,45 +1,32 @@
+use serde::{Serialize, Deserialize};
 use crate::color::Color;
-use crate::GameSprites;
-use crate::particle::ParticleList;
-use crate::particle::fire::Fire;
-use crate::utils::Position;
+use crate::color::rules::ColorRules;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Edge {
     pub train_to_a: Option<Color>,
     pub train_to_b: Option<Color>,
-    position: Position,
 }
 
 impl Edge {
-    pub fn new(x:f32, y:f32) -> Edge {
+    pub fn new() -> Edge {
         Edge {
             train_to_a: None,
             train_to_b: None,
-            position: Position{x, y},
         }
     }
 
-    pub fn interact_trains(&mut self, gs: &mut GameSprites, p: &mut ParticleList, scale: f32) {
+    pub fn interact_trains(&mut self, rules: &ColorRules) -> Option<Color> {
+        // returns the new color if two trains met on this edge and were mixed.
         if let (Some(t1), Some(t2)) = (self.train_to_a, self.train_to_b) {
-            let new_color = Color::mix_many(vec![t1, t2]);
+            let new_color = rules.mix_many(&[t1, t2]);
             self.train_to_a = Some(new_color);
             self.train_to_b = Some(new_color);
-            gs.play_train_sound(new_color);
-            p.push(Box::new(Fire::new(
-                self.position.x, self.position.y, new_color, scale
-            )));
+            return Some(new_color);
         }
+        None
     }
 
-    pub fn get_char(&self) -> char {
-        if self.train_to_a.is_some() && self.train_to_b.is_some() {
-            return '2';
-        }
-        if self.train_to_a.is_some() || self.train_to_b.is_some() {
-            return '1';
-        }
-        return '0';
-    }
     pub fn is_empty(&self) -> bool {
         self.train_to_a.is_none() && self.train_to_b.is_none()
     }
@@ -48,8 +35,10 @@ impl Edge {
         self.train_to_a = None;
         self.train_to_b = None;
     }
+}
 
-    pub fn set_pos(&mut self, x:f32, y:f32) {
-        self.position = Position{x, y};
+impl Default for Edge {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,510 @@
+use macroquad::prelude::*;
+use std::path::PathBuf;
+
+use crate::GameState;
+use crate::color::Color as TrainColor;
+use crate::color::rules::ColorRules;
+use crate::connection::{Connection, DirectedConnection};
+use crate::gameplay::Gameplay;
+use crate::levels::lint::lint_tunnels;
+use crate::levels::writer::color_name;
+use crate::levels::{is_valid_level_id, level_id_from_name, Level, LevelManager, PositionedTile};
+use crate::score::Par;
+use crate::sprites::GameSprites;
+use crate::sprites::SoundType::{ButtonPress, DrawTrack, EraseTrack};
+use crate::tile::bridge::Bridge;
+use crate::tile::filter::Filter;
+use crate::tile::oneway::OneWay;
+use crate::tile::painter::Painter;
+use crate::tile::rock::Rock;
+use crate::tile::splitter::Splitter;
+use crate::tile::station::{Station, MAX_DELAY};
+use crate::tile::trainsink::Trainsink;
+use crate::tile::trainsource::Trainsource;
+use crate::tile::tunnel::Tunnel;
+use crate::tile::turntable::Turntable;
+use crate::tile::Tile;
+use crate::utils::{draw_text_button, point_in_rect};
+use crate::yard::{Yard, DEFAULT_NUM_COLS, DEFAULT_NUM_ROWS, MAX_NUM_COLS, MAX_NUM_ROWS};
+
+// a trainsink can't display more desires than this
+const MAX_TRAINS: usize = 16;
+const MAX_STARS: u32 = 10;
+const MAX_TURNTABLE_EXITS: usize = 8;
+const HELP_TEXT: &str = "Arrows: sides. Digits: station delay, tunnel id. Backspace: remove the last train or exit.";
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum EditorTool {
+    Trainsource,
+    Trainsink,
+    Painter,
+    Splitter,
+    Filter,
+    Bridge,
+    Station,
+    Turntable,
+    OneWay,
+    Tunnel,
+    Rock,
+    Select,
+    Erase,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum EditorAction {
+    Tool(EditorTool),
+    Color(TrainColor),
+    ChangeWidth(i32),
+    ChangeHeight(i32),
+    ChangeStars(i32),
+    EditName,
+    Test,
+    Save,
+    Back,
+}
+
+struct EditorButton {
+    rect: Rect,
+    action: EditorAction,
+}
+
+// The editor works on a Level directly. Every time the level changes, the yard is rebuilt from it,
+// so that the level is drawn exactly as it will look when played.
+pub struct Editor {
+    level: Level,
+    // the rules of the level pack, which the levels made here are played and saved with
+    color_rules: ColorRules,
+    // where Save adds the level, see Profiles::user_levels_path
+    user_levels_path: PathBuf,
+    yard: Yard,
+    yard_area: Rect,
+    buttons: Vec<EditorButton>,
+    message_rect: Rect,
+    tool: EditorTool,
+    color: TrainColor,
+    selected: Option<(usize, usize)>,
+    editing_name: bool,
+    message: String,
+}
+
+impl Editor {
+    pub fn new(rect: Rect, color_rules: &ColorRules, user_levels_path: PathBuf, gs: &GameSprites) -> Editor {
+        let level = Level {
+            level_info: vec![],
+            current_progress: (vec![], false),
+            hints_used: 0,
+            // set from the name when saving
+            id: String::new(),
+            name: "New Level".to_owned(),
+            num_stars: 1,
+            num_rows: DEFAULT_NUM_ROWS,
+            num_cols: DEFAULT_NUM_COLS,
+            par: Par::default(),
+            best_score: None,
+        };
+        let yard_area = Rect::new(rect.x, rect.y, rect.w, rect.w);
+        let yard = Yard::new(&level, color_rules, yard_area, gs).expect("an empty level always fits its yard");
+        let mut editor = Editor {
+            level,
+            color_rules: color_rules.clone(),
+            user_levels_path,
+            yard,
+            yard_area,
+            buttons: vec![],
+            message_rect: Rect::new(0., 0., 0., 0.),
+            tool: EditorTool::Trainsource,
+            color: TrainColor::Red,
+            selected: None,
+            editing_name: false,
+            message: HELP_TEXT.to_owned(),
+        };
+        editor.set_rect(rect, gs);
+        editor
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.yard_area = Rect::new(rect.x, rect.y, rect.w, rect.w);
+        let (x, y) = (self.yard_area.x, self.yard_area.y + self.yard_area.h);
+        let scale = self.yard_area.w / 672.;
+
+        let rows: [Vec<EditorAction>; 4] = [
+            vec![
+                EditorAction::Tool(EditorTool::Trainsource),
+                EditorAction::Tool(EditorTool::Trainsink),
+                EditorAction::Tool(EditorTool::Painter),
+                EditorAction::Tool(EditorTool::Splitter),
+                EditorAction::Tool(EditorTool::Filter),
+                EditorAction::Tool(EditorTool::Bridge),
+                EditorAction::Tool(EditorTool::Station),
+                EditorAction::Tool(EditorTool::Turntable),
+                EditorAction::Tool(EditorTool::OneWay),
+                EditorAction::Tool(EditorTool::Tunnel),
+                EditorAction::Tool(EditorTool::Rock),
+                EditorAction::Tool(EditorTool::Select),
+                EditorAction::Tool(EditorTool::Erase),
+            ],
+            // the colors declared by the level pack can be used too
+            self.color_rules.all_colors().into_iter().map(EditorAction::Color).collect(),
+            vec![
+                EditorAction::ChangeWidth(-1),
+                EditorAction::ChangeWidth(1),
+                EditorAction::ChangeHeight(-1),
+                EditorAction::ChangeHeight(1),
+                EditorAction::ChangeStars(-1),
+                EditorAction::ChangeStars(1),
+            ],
+            vec![EditorAction::EditName, EditorAction::Test, EditorAction::Save, EditorAction::Back],
+        ];
+        self.buttons = vec![];
+        for (row_index, row) in rows.into_iter().enumerate() {
+            // the name takes as much space as the three buttons next to it
+            let widths: Vec<f32> = row.iter()
+                .map(|action| if *action == EditorAction::EditName {3.} else {1.})
+                .collect();
+            let unit = (652. - 8. * (row.len() - 1) as f32) / widths.iter().sum::<f32>();
+            let mut button_x = x + 10. * scale;
+            for (action, width) in row.into_iter().zip(widths) {
+                let rect = Rect::new(button_x, y + (8. + 56. * row_index as f32) * scale, width * unit * scale, 48. * scale);
+                button_x += (width * unit + 8.) * scale;
+                self.buttons.push(EditorButton { rect, action });
+            }
+        }
+        self.message_rect = Rect::new(x + 10. * scale, y + 232. * scale, 652. * scale, 28. * scale);
+        self.yard.set_rect(self.yard_area, gs);
+    }
+
+    fn rebuild_yard(&mut self, gs: &GameSprites) {
+        // the level has no drawn tracks, and tiles outside of the yard are removed when it shrinks
+        self.yard = Yard::new(&self.level, &self.color_rules, self.yard_area, gs)
+            .expect("the level of the editor always fits its yard");
+    }
+
+    fn button_label(&self, action: EditorAction) -> String {
+        match action {
+            EditorAction::Tool(tool) => match tool {
+                EditorTool::Trainsource => "Source",
+                EditorTool::Trainsink => "Sink",
+                EditorTool::Painter => "Painter",
+                EditorTool::Splitter => "Splitter",
+                EditorTool::Filter => "Filter",
+                EditorTool::Bridge => "Bridge",
+                EditorTool::Station => "Station",
+                EditorTool::Turntable => "Turntable",
+                EditorTool::OneWay => "One-way",
+                EditorTool::Tunnel => "Tunnel",
+                EditorTool::Rock => "Rock",
+                EditorTool::Select => "Select",
+                EditorTool::Erase => "Erase",
+            }.to_owned(),
+            EditorAction::Color(color) => color_name(&self.color_rules, color),
+            EditorAction::ChangeWidth(diff) => format!("W{} ({})", if diff < 0 {"-"} else {"+"}, self.level.num_cols),
+            EditorAction::ChangeHeight(diff) => format!("H{} ({})", if diff < 0 {"-"} else {"+"}, self.level.num_rows),
+            EditorAction::ChangeStars(diff) => format!("Stars{} ({})", if diff < 0 {"-"} else {"+"}, self.level.num_stars),
+            EditorAction::EditName if self.editing_name => format!("{}_", self.level.name),
+            EditorAction::EditName => self.level.name.clone(),
+            EditorAction::Test => "Test".to_owned(),
+            EditorAction::Save => "Save".to_owned(),
+            EditorAction::Back => "Back".to_owned(),
+        }
+    }
+
+    pub fn render(&self, gs: &GameSprites) {
+        self.yard.render(gs);
+
+        if let Some((r, c)) = self.selected {
+            let rect = self.yard.tile_rect(r, c);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 0.06 * rect.w, GOLD);
+        }
+
+        for button in &self.buttons {
+            let bg_color = match button.action {
+                EditorAction::Color(color) if color == self.color => color.get_color(),
+                EditorAction::Color(color) => {
+                    let mut color = color.get_color();
+                    color.a = 0.4;
+                    color
+                }
+                EditorAction::Tool(tool) if tool == self.tool => GOLD,
+                EditorAction::EditName if self.editing_name => GOLD,
+                _ => WHITE,
+            };
+            draw_text_button(&self.button_label(button.action), button.rect, bg_color);
+        }
+
+        let font_size = self.message_rect.h * 0.8;
+        draw_text(&self.message, self.message_rect.x, self.message_rect.y + font_size, font_size, DARKGRAY);
+    }
+
+    pub fn update(&mut self, gs: &mut GameSprites, game_state: &mut GameState, gameplay: &mut Gameplay) {
+        if self.editing_name {
+            self.update_name();
+        } else {
+            self.update_selected_tile(gs);
+        }
+
+        if !is_mouse_button_pressed(MouseButton::Left) {
+            return;
+        }
+        let (x, y) = mouse_position();
+        self.editing_name = false;
+
+        if point_in_rect(x, y, self.yard.rect) {
+            let c = ((x - self.yard.rect.x) / self.yard.rect.w * self.level.num_cols as f32) as usize;
+            let r = ((y - self.yard.rect.y) / self.yard.rect.h * self.level.num_rows as f32) as usize;
+            self.click_tile(r.min(self.level.num_rows - 1), c.min(self.level.num_cols - 1), gs);
+            return;
+        }
+
+        let Some(action) = self.buttons.iter().find(|button| point_in_rect(x, y, button.rect)).map(|button| button.action) else {
+            return;
+        };
+        gs.add_sound(ButtonPress);
+        match action {
+            EditorAction::Tool(tool) => {
+                self.tool = tool;
+            }
+            EditorAction::Color(color) => {
+                self.color = color;
+                self.edit_selected_tile(gs, |tile| match tile {
+                    Tile::Trainsource(trainsource) if trainsource.trains.len() < MAX_TRAINS => {
+                        let mut trains: Vec<TrainColor> = trainsource.trains.iter().flatten().copied().collect();
+                        trains.push(color);
+                        *tile = Tile::Trainsource(Trainsource::new(trains, trainsource.dir));
+                    }
+                    Tile::Trainsink(trainsink) if trainsink.desires.len() < MAX_TRAINS => {
+                        let mut desires: Vec<TrainColor> = trainsink.desires.iter().flatten().copied().collect();
+                        desires.push(color);
+                        *tile = Tile::Trainsink(Trainsink::new(desires, trainsink.border_state));
+                    }
+                    Tile::Painter(painter) => {
+                        *tile = Tile::Painter(Painter::new(painter.connection, color));
+                    }
+                    Tile::Filter(filter) => {
+                        *tile = Tile::Filter(Filter::new(filter.connection, color));
+                    }
+                    _ => {}
+                });
+            }
+            EditorAction::ChangeWidth(diff) => {
+                self.level.num_cols = (self.level.num_cols as i32 + diff).clamp(1, MAX_NUM_COLS as i32) as usize;
+                self.remove_tiles_outside_of_yard(gs);
+            }
+            EditorAction::ChangeHeight(diff) => {
+                self.level.num_rows = (self.level.num_rows as i32 + diff).clamp(1, MAX_NUM_ROWS as i32) as usize;
+                self.remove_tiles_outside_of_yard(gs);
+            }
+            EditorAction::ChangeStars(diff) => {
+                self.level.num_stars = (self.level.num_stars as i32 + diff).clamp(1, MAX_STARS as i32) as u32;
+            }
+            EditorAction::EditName => {
+                self.editing_name = true;
+            }
+            EditorAction::Test => {
+                if !self.check_tunnels() {
+                    return;
+                }
+                self.selected = None;
+                match gameplay.reset_yard_from_level(&self.level, &self.color_rules, gs) {
+                    Ok(()) => *game_state = GameState::EditorTest,
+                    Err(err) => self.message = err.to_string(),
+                }
+            }
+            EditorAction::Save => {
+                // the editor only makes new levels, so their id comes from their name
+                self.level.id = level_id_from_name(&self.level.name);
+                if !is_valid_level_id(&self.level.id) {
+                    self.message = "The name needs at least one letter or digit".to_owned();
+                    return;
+                }
+                if !self.check_tunnels() {
+                    return;
+                }
+                self.message = match LevelManager::save_user_level(&self.user_levels_path, &self.level, &self.color_rules) {
+                    Ok(()) => format!("Saved `{}` to {}", self.level.name, self.user_levels_path.display()),
+                    Err(err) => err,
+                };
+            }
+            EditorAction::Back => {
+                *game_state = GameState::Menu;
+            }
+        }
+    }
+
+    fn update_name(&mut self) {
+        while let Some(ch) = get_char_pressed() {
+            // `:` separates the name from the number of stars in levels.txt
+            if !ch.is_control() && ch != ':' {
+                self.level.name.push(ch);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.level.name.pop();
+        }
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Escape) {
+            self.editing_name = false;
+        }
+    }
+
+    fn update_selected_tile(&mut self, gs: &mut GameSprites) {
+        // the characters typed while not editing the name must not end up in the name later on
+        while let Some(ch) = get_char_pressed() {
+            if let Some(digit) = ch.to_digit(10) {
+                self.edit_selected_tile(gs, |tile| match tile {
+                    Tile::Station(station) if digit <= MAX_DELAY => {
+                        *tile = Tile::Station(Station::new(station.entry_dir, station.exit_dir, digit));
+                    }
+                    Tile::Tunnel(tunnel) => tunnel.id = digit as u8,
+                    _ => {}
+                });
+            }
+        }
+
+        for (key, dir) in [(KeyCode::Up, 0), (KeyCode::Right, 1), (KeyCode::Down, 2), (KeyCode::Left, 3)] {
+            if !is_key_pressed(key) {
+                continue;
+            }
+            self.edit_selected_tile(gs, |tile| match tile {
+                Tile::Trainsource(trainsource) => trainsource.dir = dir,
+                Tile::Tunnel(tunnel) => tunnel.dir = dir,
+                Tile::Splitter(splitter) => splitter.incoming_dir = dir,
+                // the turntable gets a new exit at the end of its cycle
+                Tile::Turntable(turntable) if turntable.exits.len() < MAX_TURNTABLE_EXITS => {
+                    let mut exits = turntable.exits.clone();
+                    exits.push(dir);
+                    *tile = Tile::Turntable(Turntable::new(exits));
+                }
+                // the track on the bridge goes in the direction that was pressed
+                Tile::Bridge(_) => *tile = Tile::Bridge(Bridge::new(dir)),
+                Tile::Trainsink(trainsink) => {
+                    // toggles the side, but a trainsink always keeps at least one side to enter from
+                    let mut border_state = trainsink.border_state;
+                    border_state[dir as usize] = !border_state[dir as usize];
+                    if border_state.contains(&true) {
+                        let desires = trainsink.desires.iter().flatten().copied().collect();
+                        *tile = Tile::Trainsink(Trainsink::new(desires, border_state));
+                    }
+                }
+                // the painter connects the last two directions that were pressed
+                Tile::Painter(painter) if painter.connection.dir2 != dir => {
+                    let connection = Connection { dir1: painter.connection.dir2, dir2: dir };
+                    *tile = Tile::Painter(Painter::new(connection, painter.color));
+                }
+                // and so does the filter
+                Tile::Filter(filter) if filter.connection.dir2 != dir => {
+                    let connection = Connection { dir1: filter.connection.dir2, dir2: dir };
+                    *tile = Tile::Filter(Filter::new(connection, filter.color));
+                }
+                // the one-way track goes from the side that was pressed before to the one that was just pressed
+                Tile::OneWay(oneway) if oneway.connection.to != dir => {
+                    *tile = Tile::OneWay(OneWay::new(DirectedConnection { from: oneway.connection.to, to: dir }));
+                }
+                // the station enters from the side that was pressed before the one it exits to
+                Tile::Station(station) if station.exit_dir != dir => {
+                    *tile = Tile::Station(Station::new(station.exit_dir, dir, station.delay));
+                }
+                _ => {}
+            });
+        }
+
+        if is_key_pressed(KeyCode::Backspace) {
+            self.edit_selected_tile(gs, |tile| match tile {
+                Tile::Trainsource(trainsource) if trainsource.trains.len() > 1 => {
+                    let mut trains: Vec<TrainColor> = trainsource.trains.iter().flatten().copied().collect();
+                    trains.pop();
+                    *tile = Tile::Trainsource(Trainsource::new(trains, trainsource.dir));
+                }
+                Tile::Trainsink(trainsink) if trainsink.desires.len() > 1 => {
+                    let mut desires: Vec<TrainColor> = trainsink.desires.iter().flatten().copied().collect();
+                    desires.pop();
+                    *tile = Tile::Trainsink(Trainsink::new(desires, trainsink.border_state));
+                }
+                Tile::Turntable(turntable) if turntable.exits.len() > 1 => {
+                    let mut exits = turntable.exits.clone();
+                    exits.pop();
+                    *tile = Tile::Turntable(Turntable::new(exits));
+                }
+                _ => {}
+            });
+        }
+    }
+
+    fn edit_selected_tile(&mut self, gs: &GameSprites, edit: impl Fn(&mut Tile)) {
+        if let Some((r, c)) = self.selected {
+            if let Some(tile) = self.level.level_info.iter_mut().find(|tile| (tile.y as usize, tile.x as usize) == (r, c)) {
+                edit(&mut tile.tile);
+                self.rebuild_yard(gs);
+            }
+        }
+    }
+
+    fn click_tile(&mut self, r: usize, c: usize, gs: &mut GameSprites) {
+        let index = self.level.level_info.iter().position(|tile| (tile.y as usize, tile.x as usize) == (r, c));
+        let new_tile = match self.tool {
+            EditorTool::Trainsource => Tile::Trainsource(Trainsource::new(vec![self.color], 1)),
+            EditorTool::Trainsink => Tile::Trainsink(Trainsink::new(vec![self.color], [false, false, false, true])),
+            EditorTool::Painter => Tile::Painter(Painter::new(Connection { dir1: 3, dir2: 1 }, self.color)),
+            EditorTool::Splitter => Tile::Splitter(Splitter::new(0)),
+            EditorTool::Bridge => Tile::Bridge(Bridge::new(0)),
+            EditorTool::Station => Tile::Station(Station::new(3, 1, 1)),
+            EditorTool::Turntable => Tile::Turntable(Turntable::new(vec![0, 1, 2, 3])),
+            EditorTool::OneWay => Tile::OneWay(OneWay::new(DirectedConnection { from: 3, to: 1 })),
+            EditorTool::Filter => Tile::Filter(Filter::new(Connection { dir1: 3, dir2: 1 }, self.color)),
+            EditorTool::Tunnel => Tile::Tunnel(Tunnel::new(self.next_tunnel_id(r, c), 1)),
+            EditorTool::Rock => Tile::Rock(Rock::new()),
+            EditorTool::Select => {
+                self.selected = index.map(|_| (r, c));
+                return;
+            }
+            EditorTool::Erase => {
+                if let Some(index) = index {
+                    self.level.level_info.remove(index);
+                    gs.add_sound(EraseTrack);
+                    self.selected = None;
+                    self.rebuild_yard(gs);
+                }
+                return;
+            }
+        };
+        if let Some(index) = index {
+            self.level.level_info.remove(index);
+        }
+        self.level.level_info.push(PositionedTile { tile: new_tile, x: c as u8, y: r as u8 });
+        self.selected = Some((r, c));
+        gs.add_sound(DrawTrack);
+        self.rebuild_yard(gs);
+    }
+
+    fn next_tunnel_id(&self, r: usize, c: usize) -> u8 {
+        // the id of a tunnel that is still missing its other end, so that two tunnels placed in a row are
+        // linked. Otherwise, the smallest id that isn't used yet. The tile being replaced doesn't count.
+        let ids: Vec<u8> = self.level.level_info.iter()
+            .filter(|tile| (tile.y as usize, tile.x as usize) != (r, c))
+            .filter_map(|tile| match &tile.tile {
+                Tile::Tunnel(tunnel) => Some(tunnel.id),
+                _ => None,
+            })
+            .collect();
+        let unlinked = ids.iter().copied().find(|id| ids.iter().filter(|other| *other == id).count() == 1);
+        unlinked.or_else(|| (0..=u8::MAX).find(|id| !ids.contains(id))).unwrap_or(0)
+    }
+
+    fn check_tunnels(&mut self) -> bool {
+        // a tunnel without exactly one other end can't be played, nor parsed back from the user level file.
+        match lint_tunnels(&self.level).into_iter().next() {
+            Some(problem) => {
+                self.message = format!("Can't test or save the level: {problem}");
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn remove_tiles_outside_of_yard(&mut self, gs: &GameSprites) {
+        let (num_rows, num_cols) = (self.level.num_rows, self.level.num_cols);
+        self.level.level_info.retain(|tile| (tile.y as usize) < num_rows && (tile.x as usize) < num_cols);
+        if matches!(self.selected, Some((r, c)) if r >= num_rows || c >= num_cols) {
+            self.selected = None;
+        }
+        self.rebuild_yard(gs);
+    }
+}
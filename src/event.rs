@@ -0,0 +1,33 @@
+use crate::color::Color;
+use crate::connection::Connection;
+
+// Events are how the simulation reports what happened during a tick without knowing anything about
+// textures, sounds or particles. Tiles emit an EventKind, and the simulation tags it with the
+// position of the tile it came from. The renderer then decides which particles and sounds to play.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EventKind {
+    // two trains met on the edge at side `dir` of the tile and mixed into `color`
+    EdgeMix { dir: u8, color: Color },
+    // trains met halfway through a tracktile along `conn` and mixed into `color`
+    TrackMix { conn: Connection, color: Color },
+    // two trains left a tracktile through the same side `dir` and merged into one train
+    TrackMerge { dir: u8, color: Color },
+    Painted { color: Color },
+    Split { dir: u8 },
+    // the trainsource released the train at position `index` of its list
+    TrainReleased { index: usize, color: Color },
+    // the trainsink desire at position `index` was fulfilled
+    SinkSatisfied { index: usize, color: Color },
+    // a train of `color` crashed on side `dir` of the tile
+    Crash { dir: u8, color: Color },
+    SwitchFlipped,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SimEvent {
+    pub r: usize,
+    pub c: usize,
+    pub kind: EventKind,
+}
+
+pub type EventList = Vec<EventKind>;
@@ -1,23 +1,33 @@
 use macroquad::prelude::*;
 use std::i32;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
 use crate::GameState;
+use crate::color::rules::ColorRules;
 use crate::connection::Connection;
-use crate::levels::{Level, LevelProgress};
+use crate::history::History;
+use crate::levels::{Level, LevelInfo, LevelProgress};
 use crate::particle::ParticleList;
+use crate::replay::Replay;
+use crate::score::{Par, Score, MAX_EARNED_STARS};
+use crate::settings::Settings;
+use crate::simulation::SimulationError;
 use crate::yard::{YardState, NextAction};
-use crate::yard::{NUM_COLS, NUM_ROWS};
 use crate::{levels::LevelManager, sprites::GameSprites, yard::Yard};
-use crate::sprites::SoundType::ButtonPress;
-use crate::utils::{point_in_rect, draw_texture_to_rect, find_min_f32};
+use crate::solver::{solve_with_limit, MAX_BRANCHES};
+use crate::tile::tracktile::Tracktile;
+use crate::sprites::SoundType::{ButtonPress, DrawTrack, EraseTrack};
+use crate::utils::{point_in_rect, draw_texture_to_rect, draw_text_button, find_min_f32};
 
 const MAX_SPEED:f32 = 0.3;
-const DOUBLE_CLICK_THRESHOLD:u32 = 34;
 pub struct Gameplay {
     yard_rect: Rect,
     start_trains_rect: Rect,
     erase_rect: Rect,
     back_rect: Rect,
+    hint_rect: Rect,
     status_rect: Rect,
     speed_slider_space_rect: Rect,
     speed_slider_rect: Rect,
@@ -28,13 +38,31 @@ pub struct Gameplay {
     prev_min_dir: i32,
     speed: f32,
     is_erasing: bool,
-    frame_count: u32,
-    last_click_time: u32,
+    // in seconds, see get_time
+    last_click_time: f64,
+    double_click_time: f64,
     speed_btn_drag_offset: Option<f32>,
+    // None until the user asks for the first hint on this level, then whatever the solver returned.
+    hint_solution: Option<Option<LevelInfo>>,
+    // the solver running on a worker thread, until it sends back its solution. See start_hint_search.
+    hint_search: Option<Receiver<Option<LevelInfo>>>,
+    hint_message: Option<&'static str>,
+    hints_used: u32,
+    history: History,
+    level_name: String,
+    // the run being played right now, and the last finished run, which can be watched or saved.
+    recording: Option<Replay>,
+    last_replay: Option<Replay>,
+    // where Ctrl+S saves the last replay, see Profiles::replay_dir
+    replay_dir: PathBuf,
+    show_debug_overlay: bool,
+    par: Par,
+    // the score of the current run, once it is won.
+    score: Option<Score>,
 }
 
 impl Gameplay {
-    pub fn new(rect: Rect, level_manager: &LevelManager, gs: &GameSprites) -> Gameplay {
+    pub fn new(rect: Rect, level_manager: &LevelManager, replay_dir: PathBuf, gs: &GameSprites) -> Gameplay {
         let yard_rect = Rect::new(rect.x, rect.y, rect.w, rect.w);
 
         let (x, y) = (yard_rect.x, yard_rect.y + yard_rect.h);
@@ -43,9 +71,11 @@ impl Gameplay {
         let start_trains_rect =  Rect::new(x + 238. * scale, y + 10. * scale, 424. * scale, 104. * scale);
         let erase_rect = Rect::new(x+10. * scale,y+10.*scale,208.*scale,88.*scale);
         let back_rect = Rect::new(x+10. * scale,y+110.*scale,208.*scale,88.*scale);
+        let hint_rect = Rect::new(x+10.*scale,y+210.*scale,652.*scale,52.*scale);
         let status_rect = Rect::new(x+10.*scale,y+10.*scale,208.*scale,168.*scale);
         let speed_slider_space_rect = Rect::new(x+238.*scale,y+134.*scale,424.*scale,68.*scale);
-        let initial_speed = 0.5*MAX_SPEED;
+        let settings = Settings::default();
+        let initial_speed = settings.default_speed * MAX_SPEED;
 
         // the speed button can move 424 - 136 = 288 pixels
         let speed_btn_offset = (initial_speed/MAX_SPEED) * 288.0 * scale;
@@ -56,19 +86,34 @@ impl Gameplay {
             start_trains_rect,
             erase_rect,
             back_rect,
+            hint_rect,
             status_rect,
             speed_slider_space_rect,
             speed_slider_rect,
-            yard: Yard::new(&level_manager.get_level("Red Line"), yard_rect, gs),
+            // LevelManager only keeps progress that fits its level, see LevelManager::apply_save
+            yard: Yard::new(level_manager.get_level("Red Line"), level_manager.color_rules(), yard_rect, gs)
+                .expect("the progress of a level always fits the level"),
             prev_mouse_c: -1,
             prev_mouse_r: -1,
             prev_min_dir: -1,
             speed: initial_speed,
             is_erasing: false,
-            frame_count: 0,
-            last_click_time: 0,
+            last_click_time: f64::NEG_INFINITY,
+            double_click_time: settings.double_click_time,
             speed_btn_drag_offset: None,
             particles: vec![],
+            hint_solution: None,
+            hint_search: None,
+            hint_message: None,
+            hints_used: 0,
+            history: History::default(),
+            level_name: "Red Line".to_owned(),
+            recording: None,
+            last_replay: None,
+            replay_dir,
+            show_debug_overlay: false,
+            par: Par::default(),
+            score: None,
         }
     }
 
@@ -94,9 +139,13 @@ impl Gameplay {
                 draw_texture_to_rect(gs.btn_back_to_drawing, self.start_trains_rect);
             }
         }
+        self.render_hint_button();
         for particle in &self.particles {
             particle.render(gs);
         }
+        if self.show_debug_overlay {
+            self.yard.render_debug_overlay();
+        }
 
         draw_texture_to_rect(gs.space_for_speed_slider, self.speed_slider_space_rect);
         draw_texture_to_rect(gs.btn_speed, self.speed_slider_rect);
@@ -104,8 +153,8 @@ impl Gameplay {
 
     pub fn update(&mut self, gs: &mut GameSprites, game_state: &mut GameState) -> bool {
         // returns true if we need to end the program (break out of the main loop)
-        let grid_width = self.yard_rect.w / NUM_COLS as f32;
-        let grid_height = self.yard_rect.h / NUM_ROWS as f32;
+        let grid_width = self.yard.rect.w / self.yard.num_cols as f32;
+        let grid_height = self.yard.rect.h / self.yard.num_rows as f32;
 
                 // Event::Quit { .. } => return true,
                  
@@ -118,7 +167,35 @@ impl Gameplay {
 
         let (x, y) = mouse_position();
 
+        if self.yard.state == YardState::Drawing {
+            let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            let yard = &mut self.yard;
+            let set_tracktile = |r, c, tracktile: &Tracktile| yard.set_tracktile(r, c, tracktile);
+            if ctrl && (is_key_pressed(KeyCode::Y) || (shift && is_key_pressed(KeyCode::Z))) {
+                if self.history.redo(set_tracktile) {
+                    gs.add_sound(DrawTrack);
+                }
+            } else if ctrl && is_key_pressed(KeyCode::Z) && self.history.undo(set_tracktile) {
+                gs.add_sound(EraseTrack);
+            }
+        }
+
+        self.update_debugger(gs);
+        self.poll_hint_search(gs);
+
+        if self.recording.is_none() {
+            let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+            if ctrl && is_key_pressed(KeyCode::S) {
+                self.save_last_replay();
+            } else if is_key_pressed(KeyCode::V) && self.last_replay.is_some() {
+                *game_state = GameState::Replay;
+            }
+        }
+
         if is_mouse_button_pressed(MouseButton::Left) {            
+            // everything that happens until the button is released is undone at once
+            self.history.begin_stroke(self.yard.get_drawn_tiles());
             let mut finished_double_click = false;
             if point_in_rect(x, y, self.start_trains_rect){
                 match self.yard.state {
@@ -128,11 +205,19 @@ impl Gameplay {
                     },
                     YardState::Drawing => {
                         self.is_erasing = false;
+                        self.hint_message = None;
                         self.yard.state = YardState::Playing {
                             num_ticks_elapsed: 1,
                             progress: 0.0,
                             next_step: NextAction::ProcessTick,
                         };
+                        self.recording = Some(Replay::new(
+                            &self.level_name,
+                            self.yard.get_level_info(),
+                            &self.yard.get_current_progress().0,
+                            self.yard.get_sim(),
+                            self.speed,
+                        ));
                     },
                     YardState::Playing {..} => {
                         self.yard.reset_self(gs);
@@ -151,18 +236,24 @@ impl Gameplay {
                     },
                     _ => {},
                 }
+            } else if point_in_rect(x, y, self.hint_rect) {
+                if self.yard.state == YardState::Drawing {
+                    self.is_erasing = false;
+                    self.show_hint(gs);
+                    gs.add_sound(ButtonPress);
+                }
             } else if point_in_rect(x, y, self.back_rect){
                 if self.yard.state == YardState::Drawing {
                     *game_state = GameState::Menu;
                     gs.add_sound(ButtonPress);
                 }
-            } else if point_in_rect(x, y, self.yard_rect) {
-                if self.frame_count - self.last_click_time < DOUBLE_CLICK_THRESHOLD {
+            } else if point_in_rect(x, y, self.yard.rect) {
+                if get_time() - self.last_click_time < self.double_click_time {
                     match self.yard.state {
                         YardState::Drawing => {
                             let (x, y) = (
-                                x - self.yard_rect.x,
-                                y - self.yard_rect.y,
+                                x - self.yard.rect.x,
+                                y - self.yard.rect.y,
                             );
                             let (c, r) = (x / grid_width, y / grid_height);
                             self.yard.switch_connections(r as usize, c as usize, gs);
@@ -173,7 +264,7 @@ impl Gameplay {
                 }
             }
             if !finished_double_click {
-                self.last_click_time = self.frame_count;
+                self.last_click_time = get_time();
             }
 
         }
@@ -191,6 +282,9 @@ impl Gameplay {
                 }
                 self.speed_slider_rect.x = new_x;
                 self.speed = (new_x - self.speed_slider_space_rect.x) / (self.speed_slider_space_rect.w - self.speed_slider_rect.w)  * MAX_SPEED;
+                if let Some(recording) = &mut self.recording {
+                    recording.record_speed(self.speed);
+                }
             }
             else  {
                 self.speed_btn_drag_offset = Some(x - self.speed_slider_rect.x);
@@ -202,12 +296,12 @@ impl Gameplay {
     
 
         if self.yard.state == YardState::Drawing && !self.is_erasing {
-            if is_mouse_button_down(MouseButton::Left) && point_in_rect(x, y, self.yard_rect) 
+            if is_mouse_button_down(MouseButton::Left) && point_in_rect(x, y, self.yard.rect) 
             {
                 // handle adding a connection to the yard when the user is drawing:
                 let (x, y) = (
-                    x - self.yard_rect.x,
-                    y - self.yard_rect.y,
+                    x - self.yard.rect.x,
+                    y - self.yard.rect.y,
                 );
                 let (c, r) = ((x / grid_width )as i32, (y / grid_height) as i32);
 
@@ -252,10 +346,10 @@ impl Gameplay {
                 self.prev_min_dir = -1;
             }
         } else if self.yard.state == YardState::Drawing && self.is_erasing {
-            if is_mouse_button_down(MouseButton::Left) && point_in_rect(x, y, self.yard_rect) {
+            if is_mouse_button_down(MouseButton::Left) && point_in_rect(x, y, self.yard.rect) {
                 let (x, y) = (
-                    x - self.yard_rect.x,
-                    y - self.yard_rect.y,
+                    x - self.yard.rect.x,
+                    y - self.yard.rect.y,
                 );
                 let (c, r) = ((x / grid_width )as i32, (y / grid_height) as i32);
 
@@ -263,9 +357,25 @@ impl Gameplay {
             }
         }
 
+        if is_mouse_button_released(MouseButton::Left) {
+            self.history.end_stroke(self.yard.get_drawn_tiles());
+        }
+
         // Update
-        self.yard.update(self.speed, gs, &mut self.particles);
-        self.frame_count += 1;
+        if self.yard.update(self.speed, gs, &mut self.particles) {
+            if let Some(recording) = &mut self.recording {
+                recording.record_step();
+            }
+        }
+        if self.yard.state != YardState::Won {
+            self.score = None;
+        } else if self.score.is_none() {
+            self.score = Some(self.yard.get_score());
+        }
+        if !matches!(self.yard.state, YardState::Playing {..}) && self.recording.is_some() {
+            // the run ended: it crashed, was won, or the user went back to drawing.
+            self.last_replay = self.recording.take();
+        }
 
         for particle in &mut self.particles {
             particle.pass_one_frame();
@@ -289,6 +399,7 @@ impl Gameplay {
         self.start_trains_rect =  Rect::new(x + 238. * scale, y + 10. * scale, 424. * scale, 104. * scale);
         self.erase_rect = Rect::new(x+10. * scale,y+10.*scale,208.*scale,88.*scale);
         self.back_rect = Rect::new(x+10. * scale,y+110.*scale,208.*scale,88.*scale);
+        self.hint_rect = Rect::new(x+10.*scale,y+210.*scale,652.*scale,52.*scale);
         self.status_rect = Rect::new(x+10.*scale,y+10.*scale,208.*scale,168.*scale);
         self.speed_slider_space_rect = Rect::new(x+238.*scale,y+134.*scale,424.*scale,68.*scale);
 
@@ -299,10 +410,168 @@ impl Gameplay {
         self.yard.set_rect(yard_rect, gs);
     }
 
-    pub fn reset_yard_from_level(&mut self, level: &Level, gs: &GameSprites) {
-        self.yard = Yard::new(level, self.yard_rect, gs);
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.double_click_time = settings.double_click_time;
+        self.speed = settings.default_speed * MAX_SPEED;
+        let scale = self.yard_rect.w / 672.;
+        self.speed_slider_rect.x = self.speed_slider_space_rect.x + settings.default_speed * 288.0 * scale;
+    }
+
+    pub fn set_replay_dir(&mut self, replay_dir: PathBuf) {
+        self.replay_dir = replay_dir;
     }
+
+    pub fn reset_yard_from_level(&mut self, level: &Level, color_rules: &ColorRules, gs: &GameSprites) -> Result<(), SimulationError> {
+        self.yard = Yard::new(level, color_rules, self.yard_rect, gs)?;
+        self.hint_solution = None;
+        // the solver of the previous level finishes on its own, and its solution is dropped
+        self.hint_search = None;
+        self.hint_message = None;
+        self.hints_used = level.hints_used;
+        self.history = History::default();
+        self.level_name = level.name.clone();
+        self.par = level.par;
+        self.recording = None;
+        self.last_replay = None;
+        Ok(())
+    }
+
+    fn update_debugger(&mut self, gs: &mut GameSprites) {
+        // P pauses the trains, the arrow keys then step through the run one ProcessTick or ProcessEdges
+        // at a time, and D shows what every edge and tracktile holds.
+        if is_key_pressed(KeyCode::D) {
+            self.show_debug_overlay = !self.show_debug_overlay;
+        }
+        if self.yard.state == YardState::Drawing {
+            return;
+        }
+        if is_key_pressed(KeyCode::P) && matches!(self.yard.state, YardState::Playing {..}) {
+            self.yard.is_paused = !self.yard.is_paused;
+        }
+        if is_key_pressed(KeyCode::Right) && self.yard.is_paused && self.yard.step(gs, &mut self.particles) {
+            if let Some(recording) = &mut self.recording {
+                recording.record_step();
+            }
+        }
+        if is_key_pressed(KeyCode::Left) && self.yard.step_back(gs) {
+            // after a crash or a win the run was already moved to last_replay, so it is picked up again.
+            if self.recording.is_none() {
+                self.recording = self.last_replay.take();
+            }
+            if let Some(recording) = &mut self.recording {
+                recording.remove_last_step();
+            }
+        }
+    }
+
+    fn save_last_replay(&mut self) {
+        let Some(replay) = &self.last_replay else {
+            return;
+        };
+        self.hint_message = match replay.save(&self.replay_dir) {
+            Ok(path) => {
+                println!("Saved the replay to {}", path.display());
+                Some("Replay saved")
+            },
+            Err(err) => {
+                eprintln!("Could not save the replay: {err}");
+                Some("Could not save the replay")
+            },
+        };
+    }
+
+    fn show_hint(&mut self, gs: &mut GameSprites) {
+        // reveals one tracktile of a solution found by the solver, skipping the ones the user already drew.
+        // The first time, the solver has to run first, and the hint is revealed once it is done.
+        let Some(solution) = &self.hint_solution else {
+            if self.hint_search.is_none() {
+                self.start_hint_search();
+            }
+            self.hint_message = Some("Looking for a hint...");
+            return;
+        };
+        self.hint_message = match solution {
+            None => Some("No hint found for this level"),
+            Some(solution) => match self.yard.find_hint(solution) {
+                None => Some("No more hints, start the trains!"),
+                Some(hint) => {
+                    self.yard.reveal_tile(&hint, gs, &mut self.particles);
+                    self.hints_used += 1;
+                    None
+                }
+            },
+        };
+    }
+
+    fn start_hint_search(&mut self) {
+        // the solver can take several seconds, so it runs on its own thread while the game keeps drawing.
+        let (sender, receiver) = mpsc::channel();
+        let level_info = self.yard.get_level_info().clone();
+        let (num_rows, num_cols) = (self.yard.num_rows, self.yard.num_cols);
+        let color_rules = self.yard.get_sim().color_rules.clone();
+        thread::spawn(move || {
+            let solution = solve_with_limit(&level_info, num_rows, num_cols, &color_rules, MAX_BRANCHES)
+                .map(|(layout, _)| layout);
+            // fails if the user left the level in the meantime, and then nobody needs the solution
+            let _ = sender.send(solution);
+        });
+        self.hint_search = Some(receiver);
+    }
+
+    fn poll_hint_search(&mut self, gs: &mut GameSprites) {
+        // called every frame. Once the solver is done, the hint the user asked for is revealed, unless the
+        // trains were started in the meantime.
+        let Some(receiver) = &self.hint_search else {
+            return;
+        };
+        let solution = match receiver.try_recv() {
+            Ok(solution) => solution,
+            Err(TryRecvError::Empty) => return,
+            // the solver panicked
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.hint_search = None;
+        self.hint_solution = Some(solution);
+        if self.yard.state == YardState::Drawing {
+            // the hint is undone on its own, even if the user is drawing at that moment
+            let before = self.yard.get_drawn_tiles().clone();
+            self.show_hint(gs);
+            self.history.push_stroke(&before, self.yard.get_drawn_tiles());
+        }
+    }
+
+    fn render_hint_button(&self) {
+        let bg_color = if self.yard.state == YardState::Drawing {WHITE} else {GRAY};
+        let label = match (self.yard.state, self.hint_message, self.score) {
+            (YardState::Playing {num_ticks_elapsed, next_step, ..}, _, _) if self.yard.is_paused => format!(
+                "Paused at step {num_ticks_elapsed}, next {next_step:?} (P: resume, arrows: step)"
+            ),
+            (_, Some(message), _) => message.to_owned(),
+            (YardState::Drawing, None, _) => format!("Hint ({} used)", self.hints_used),
+            (YardState::Won, None, Some(score)) => format!(
+                "{} tracks, {} switches, {} ticks: {} of {} stars",
+                score.tracks, score.switches, score.ticks, score.stars(&self.par), MAX_EARNED_STARS,
+            ),
+            _ => "P: pause, D: show edges and trains".to_owned(),
+        };
+        draw_text_button(&label, self.hint_rect, bg_color);
+    }
+
+    pub fn take_progress_changed(&mut self) -> bool {
+        // hints and scores only change along with the yard, so the yard knows about every change.
+        self.yard.take_progress_changed()
+    }
+
     pub fn get_current_progress(&self) -> LevelProgress {
         self.yard.get_current_progress()
     }
+    pub fn get_hints_used(&self) -> u32 {
+        self.hints_used
+    }
+    pub fn get_score(&self) -> Option<Score> {
+        self.score
+    }
+    pub fn get_last_replay(&self) -> Option<&Replay> {
+        self.last_replay.as_ref()
+    }
 }
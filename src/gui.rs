@@ -1,2 +1,4 @@
 pub mod button;
-pub mod list;
\ No newline at end of file
+pub mod list;
+pub mod profile_menu;
+pub mod settings_menu;
\ No newline at end of file
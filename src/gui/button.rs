@@ -1,6 +1,8 @@
 use macroquad::prelude::*;
 
+use crate::score::MAX_EARNED_STARS;
 use crate::sprites::GameSprites;
+use crate::utils::draw_star;
 
 
 // const BUTTON_HEIGHT: f32 = 20.;
@@ -9,6 +11,9 @@ const BUTTON_MARGIN_LEFT: f32 = 8.;
 const BUTTON_COLOR: Color = WHITE;
 const BUTTON_IN_PROGRESS_COLOR: Color = YELLOW;
 const BUTTON_SOLVED_COLOR: Color = GREEN;
+const BUTTON_EDITOR_COLOR: Color = SKYBLUE;
+const BUTTON_PROFILE_COLOR: Color = BEIGE;
+const BUTTON_SETTINGS_COLOR: Color = LIGHTGRAY;
 
 #[derive(Eq, PartialEq, Clone, Copy)]
 pub enum ButtonStyle {
@@ -17,10 +22,15 @@ pub enum ButtonStyle {
     LevelNotStarted,
     LevelInProgress,
     LevelSolved,
+    Editor,
+    Profile,
+    Settings,
 }
 pub struct Button {
     pub label_text: String,
     pub style: ButtonStyle,
+    // the stars earned by the best solution of a level, if it has been solved.
+    pub earned_stars: Option<u32>,
 }
 
 impl Button {
@@ -28,6 +38,7 @@ impl Button {
         Button {
             label_text: label_text.to_owned(),
             style,
+            earned_stars: None,
         }
     }
 
@@ -38,6 +49,9 @@ impl Button {
             ButtonStyle::LevelInProgress => BUTTON_IN_PROGRESS_COLOR,
             ButtonStyle::LevelSolved => BUTTON_SOLVED_COLOR,
             ButtonStyle::Tutorial => BUTTON_COLOR,
+            ButtonStyle::Editor => BUTTON_EDITOR_COLOR,
+            ButtonStyle::Profile => BUTTON_PROFILE_COLOR,
+            ButtonStyle::Settings => BUTTON_SETTINGS_COLOR,
         };
         draw_rectangle(x, y, BUTTON_WIDTH, height, bg_color);
         draw_rectangle_lines(x, y, BUTTON_WIDTH, height, 1., BLACK);
@@ -63,5 +77,14 @@ impl Button {
             y + height - (height - text_height)/2.,
             params,
         );
+
+        if let Some(earned_stars) = self.earned_stars {
+            let radius = height * 0.35;
+            for index in 0..MAX_EARNED_STARS {
+                let color = if index < earned_stars {GOLD} else {LIGHTGRAY};
+                let star_x = x + BUTTON_WIDTH - (MAX_EARNED_STARS - index) as f32 * 2.2 * radius;
+                draw_star(star_x, y + height / 2., radius, color);
+            }
+        }
     }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use crate::GameState;
 use crate::gameplay::Gameplay;
 use crate::levels::LevelManager;
 use crate::{gui::button::Button, sprites::GameSprites, utils::mouse_in_rect};
+use crate::utils::draw_text_button;
 use crate::gui::button::BUTTON_WIDTH;
 use crate::sprites::SoundType;
 
@@ -17,12 +18,19 @@ pub struct List{
     y:f32,
     initial_index: f32,
     pub level_manager: LevelManager,
+    // shown over the list until it is clicked, e.g. when the save had to be restored from a backup
+    warning: Option<String>,
 }
 
 
 impl List {
-    pub fn new(x:f32, y:f32, max_height:f32, level_manager: LevelManager) -> List {
-        let mut buttons = vec![];
+    pub fn new(x:f32, y:f32, max_height:f32, mut level_manager: LevelManager, profile_name: &str) -> List {
+        let warning = level_manager.take_load_warning();
+        let mut buttons = vec![
+            Button::new(&format!("Profile: {profile_name}"), ButtonStyle::Profile),
+            Button::new("Level Editor", ButtonStyle::Editor),
+            Button::new("Settings", ButtonStyle::Settings),
+        ];
         for city_name in level_manager.get_city_names() {
             buttons.push(Button::new(
                 &city_name,
@@ -38,10 +46,9 @@ impl List {
                 } else {
                     style = ButtonStyle::LevelNotStarted;
                 }
-                buttons.push(Button::new(
-                    &level_name,
-                    style,
-                ));
+                let mut button = Button::new(&level_name, style);
+                button.earned_stars = level_manager.get_level(&level_name).get_earned_stars();
+                buttons.push(button);
             }
         }
         
@@ -52,6 +59,7 @@ impl List {
             max_height,
             initial_index: 0.,
             level_manager,
+            warning,
         }
     }
 
@@ -62,6 +70,9 @@ impl List {
         for (index, button) in self.buttons[i..i+num_to_display].iter().enumerate() {
             button.render(self.x, self.y + index as f32 * LIST_ITEM_HEIGHT, LIST_ITEM_HEIGHT, gs);
         }
+        if let Some(warning) = &self.warning {
+            draw_text_button(&format!("{warning} (click to close)"), self.warning_rect(), PINK);
+        }
     }
 
     pub fn push_button(&mut self, button: Button) {
@@ -85,7 +96,17 @@ impl List {
         self.initial_index = new_init_index;
     }
 
+    fn warning_rect(&self) -> Rect {
+        Rect::new(self.x, self.y, screen_width() - 2. * self.x, LIST_ITEM_HEIGHT)
+    }
+
     pub fn update(&mut self, gs: &mut GameSprites, game_state: &mut GameState, gameplay: &mut Gameplay) {
+        if self.warning.is_some() {
+            if is_mouse_button_pressed(MouseButton::Left) {
+                self.warning = None;
+            }
+            return;
+        }
         if is_key_pressed(KeyCode::Up) {
             self.change_initial_index(-1.);
         }
@@ -110,11 +131,24 @@ impl List {
 
                     match button.style {
                         ButtonStyle::LevelNotStarted | ButtonStyle::LevelInProgress | ButtonStyle::LevelSolved => {
-                            *game_state = GameState::Level(level_label.clone());
-                            gameplay.reset_yard_from_level(
+                            let result = gameplay.reset_yard_from_level(
                                 self.level_manager.get_level(&level_label),
+                                self.level_manager.color_rules(),
                                 gs,
                             );
+                            match result {
+                                Ok(()) => *game_state = GameState::Level(level_label.clone()),
+                                Err(err) => eprintln!("Unable to open `{level_label}`: {err}"),
+                            }
+                        },
+                        ButtonStyle::Editor => {
+                            *game_state = GameState::Editor;
+                        },
+                        ButtonStyle::Profile => {
+                            *game_state = GameState::Profiles;
+                        },
+                        ButtonStyle::Settings => {
+                            *game_state = GameState::Settings;
                         },
                         _ => {},
                     }
@@ -137,8 +171,9 @@ impl List {
         for button in &mut self.buttons {
             if button.label_text == level_name {
                 button.style = style;
+                button.earned_stars = level.get_earned_stars();
             }
         }
     }
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,131 @@
+use macroquad::prelude::*;
+
+use crate::GameState;
+use crate::gui::button::BUTTON_WIDTH;
+use crate::profiles::{Profiles, MAX_PROFILE_NAME_LEN};
+use crate::sprites::GameSprites;
+use crate::sprites::SoundType::ButtonPress;
+use crate::utils::{draw_text_button, point_in_rect};
+
+const ITEM_HEIGHT: f32 = 40.;
+
+enum Item {
+    Profile(String),
+    NewProfile,
+    Back,
+}
+
+// Lets the player pick the profile whose progress is shown, or make a new one.
+pub struct ProfileMenu {
+    x: f32,
+    y: f32,
+    profile_names: Vec<String>,
+    current: String,
+    // Some while the player is typing the name of a new profile
+    new_name: Option<String>,
+    message: Option<String>,
+}
+
+impl ProfileMenu {
+    pub fn new(x: f32, y: f32) -> ProfileMenu {
+        ProfileMenu {
+            x,
+            y,
+            profile_names: vec![],
+            current: String::new(),
+            new_name: None,
+            message: None,
+        }
+    }
+
+    pub fn open(&mut self, profiles: &Profiles) {
+        // called when the menu is shown, so that profiles made outside of the game show up too.
+        self.profile_names = profiles.list();
+        self.current = profiles.current().to_owned();
+        self.new_name = None;
+        self.message = None;
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    fn items(&self) -> Vec<Item> {
+        let mut items: Vec<Item> = self.profile_names.iter().cloned().map(Item::Profile).collect();
+        items.push(Item::NewProfile);
+        items.push(Item::Back);
+        items
+    }
+
+    fn item_rect(&self, index: usize) -> Rect {
+        // the first row holds the title
+        Rect::new(self.x, self.y + (index + 1) as f32 * ITEM_HEIGHT, BUTTON_WIDTH, ITEM_HEIGHT)
+    }
+
+    pub fn update(&mut self, gs: &mut GameSprites, game_state: &mut GameState) -> Option<String> {
+        // returns the name of the profile to switch to, if the player picked or made one.
+        if let Some(new_name) = &mut self.new_name {
+            while let Some(ch) = get_char_pressed() {
+                if !ch.is_control() && new_name.len() < MAX_PROFILE_NAME_LEN {
+                    new_name.push(ch);
+                }
+            }
+            if is_key_pressed(KeyCode::Backspace) {
+                new_name.pop();
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                self.new_name = None;
+            } else if is_key_pressed(KeyCode::Enter) {
+                return self.new_name.take();
+            }
+            return None;
+        }
+        while get_char_pressed().is_some() {}
+
+        if is_key_pressed(KeyCode::Escape) {
+            *game_state = GameState::Menu;
+            return None;
+        }
+        if !is_mouse_button_pressed(MouseButton::Left) {
+            return None;
+        }
+        let (x, y) = mouse_position();
+        let items = self.items();
+        let clicked = (0..items.len()).find(|index| point_in_rect(x, y, self.item_rect(*index)))?;
+        gs.add_sound(ButtonPress);
+        match &items[clicked] {
+            Item::Profile(name) => Some(name.clone()),
+            Item::NewProfile => {
+                self.new_name = Some(String::new());
+                self.message = None;
+                None
+            }
+            Item::Back => {
+                *game_state = GameState::Menu;
+                None
+            }
+        }
+    }
+
+    pub fn render(&self) {
+        draw_text("Profiles", self.x, self.y + ITEM_HEIGHT * 0.75, ITEM_HEIGHT * 0.8, DARKBLUE);
+        for (index, item) in self.items().iter().enumerate() {
+            let (label, bg_color) = match item {
+                Item::Profile(name) if *name == self.current => (format!("{name} (playing)"), GOLD),
+                Item::Profile(name) => (name.clone(), WHITE),
+                Item::NewProfile => match &self.new_name {
+                    Some(new_name) => (format!("{new_name}_"), GOLD),
+                    None => ("New profile".to_owned(), SKYBLUE),
+                },
+                Item::Back => ("Back".to_owned(), WHITE),
+            };
+            draw_text_button(&label, self.item_rect(index), bg_color);
+        }
+        let message_y = self.item_rect(self.items().len()).y + ITEM_HEIGHT * 0.75;
+        if let Some(message) = &self.message {
+            draw_text(message, self.x, message_y, ITEM_HEIGHT * 0.6, RED);
+        } else if self.new_name.is_some() {
+            draw_text("Type a name, then press Enter", self.x, message_y, ITEM_HEIGHT * 0.6, DARKGRAY);
+        }
+    }
+}
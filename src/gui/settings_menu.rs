@@ -0,0 +1,211 @@
+use macroquad::prelude::*;
+
+use crate::GameState;
+use crate::color::ColorblindMode;
+use crate::gui::button::BUTTON_WIDTH;
+use crate::particle::ParticleDensity;
+use crate::settings::Settings;
+use crate::sprites::GameSprites;
+use crate::sprites::mixer::Channel;
+use crate::sprites::SoundType::ButtonPress;
+use crate::utils::{draw_text_button, point_in_rect};
+
+const ITEM_HEIGHT: f32 = 40.;
+
+#[derive(Clone, Copy)]
+enum Item {
+    DefaultSpeed,
+    Volume(Channel),
+    DoubleClickTime,
+    Colorblind,
+    ParticleDensity,
+    Fullscreen,
+    Back,
+}
+
+const ITEMS: [Item; 10] = [
+    Item::DefaultSpeed,
+    Item::Volume(Channel::Master),
+    Item::Volume(Channel::Ui),
+    Item::Volume(Channel::Train),
+    Item::Volume(Channel::Effect),
+    Item::DoubleClickTime,
+    Item::Colorblind,
+    Item::ParticleDensity,
+    Item::Fullscreen,
+    Item::Back,
+];
+
+impl Item {
+    fn has_steps(self) -> bool {
+        // items with a value that goes up and down, with `-` and `+` buttons. The others change when clicked.
+        matches!(self, Item::DefaultSpeed | Item::Volume(_) | Item::DoubleClickTime)
+    }
+
+    fn has_mute(self) -> bool {
+        // volumes also have a button that mutes their channel, left of the `-` button
+        matches!(self, Item::Volume(_))
+    }
+}
+
+// Lets the player change the settings of their profile. Every change applies and is saved right away.
+pub struct SettingsMenu {
+    x: f32,
+    y: f32,
+}
+
+impl SettingsMenu {
+    pub fn new(x: f32, y: f32) -> SettingsMenu {
+        SettingsMenu { x, y }
+    }
+
+    fn item_rect(&self, index: usize) -> Rect {
+        // the first row holds the title
+        Rect::new(self.x, self.y + (index + 1) as f32 * ITEM_HEIGHT, BUTTON_WIDTH, ITEM_HEIGHT)
+    }
+
+    fn step_rects(&self, index: usize) -> (Rect, Rect, Rect) {
+        // the label, the `-` button and the `+` button of an item with steps
+        let rect = self.item_rect(index);
+        let label = Rect::new(rect.x, rect.y, rect.w - 2. * ITEM_HEIGHT, rect.h);
+        let minus = Rect::new(label.x + label.w, rect.y, ITEM_HEIGHT, rect.h);
+        let plus = Rect::new(minus.x + minus.w, rect.y, ITEM_HEIGHT, rect.h);
+        (label, minus, plus)
+    }
+
+    fn mute_rects(&self, index: usize) -> (Rect, Rect) {
+        // the label and the mute button of an item with a mute button, which take the room of the label
+        // given by step_rects
+        let (label, _, _) = self.step_rects(index);
+        let mute = Rect::new(label.x + label.w - 1.5 * ITEM_HEIGHT, label.y, 1.5 * ITEM_HEIGHT, label.h);
+        (Rect::new(label.x, label.y, label.w - mute.w, label.h), mute)
+    }
+
+    pub fn update(&mut self, settings: &mut Settings, gs: &mut GameSprites, game_state: &mut GameState) -> bool {
+        // returns whether a setting changed.
+        if is_key_pressed(KeyCode::Escape) {
+            *game_state = GameState::Menu;
+            return false;
+        }
+        if !is_mouse_button_pressed(MouseButton::Left) {
+            return false;
+        }
+        let (x, y) = mouse_position();
+        let Some(index) = (0..ITEMS.len()).find(|index| point_in_rect(x, y, self.item_rect(*index))) else {
+            return false;
+        };
+        let item = ITEMS[index];
+        let mut mute = false;
+        let steps = if item.has_steps() {
+            let (_, minus, plus) = self.step_rects(index);
+            if item.has_mute() && point_in_rect(x, y, self.mute_rects(index).1) {
+                mute = true;
+                0
+            } else if point_in_rect(x, y, minus) {
+                -1
+            } else if point_in_rect(x, y, plus) {
+                1
+            } else {
+                return false;
+            }
+        } else {
+            0
+        };
+        gs.add_sound(ButtonPress);
+        match item {
+            Item::DefaultSpeed => settings.change_default_speed(steps),
+            Item::Volume(channel) if mute => settings.toggle_mute(channel),
+            Item::Volume(channel) => settings.change_volume(channel, steps),
+            Item::DoubleClickTime => settings.change_double_click_time(steps),
+            Item::Colorblind => settings.colorblind_mode = next_colorblind_mode(settings.colorblind_mode),
+            Item::ParticleDensity => settings.particle_density = next_particle_density(settings.particle_density),
+            Item::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            Item::Back => {
+                *game_state = GameState::Menu;
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn render(&self, settings: &Settings) {
+        draw_text("Settings", self.x, self.y + ITEM_HEIGHT * 0.75, ITEM_HEIGHT * 0.8, DARKBLUE);
+        for (index, item) in ITEMS.iter().enumerate() {
+            let on_off = |value: bool| if value {"on"} else {"off"};
+            let label = match item {
+                Item::DefaultSpeed => format!("Default speed: {:.0}%", settings.default_speed * 100.),
+                Item::Volume(channel) => {
+                    let channel_settings = settings.mixer.channel(*channel);
+                    if channel_settings.muted {
+                        format!("{}: muted", channel_name(*channel))
+                    } else {
+                        format!("{}: {:.0}%", channel_name(*channel), channel_settings.volume * 100.)
+                    }
+                }
+                Item::DoubleClickTime => format!("Double click time: {:.2}s", settings.double_click_time),
+                Item::Colorblind => format!("Colorblind mode: {}", colorblind_mode_name(settings.colorblind_mode)),
+                Item::ParticleDensity => format!("Particles: {}", particle_density_name(settings.particle_density)),
+                Item::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+                Item::Back => "Back".to_owned(),
+            };
+            if item.has_steps() {
+                let (mut label_rect, minus, plus) = self.step_rects(index);
+                if let Item::Volume(channel) = item {
+                    let mute;
+                    (label_rect, mute) = self.mute_rects(index);
+                    let muted = settings.mixer.channel(*channel).muted;
+                    draw_text_button("mute", mute, if muted {ORANGE} else {SKYBLUE});
+                }
+                draw_text_button(&label, label_rect, WHITE);
+                draw_text_button("-", minus, SKYBLUE);
+                draw_text_button("+", plus, SKYBLUE);
+            } else {
+                draw_text_button(&label, self.item_rect(index), WHITE);
+            }
+        }
+    }
+}
+
+fn channel_name(channel: Channel) -> &'static str {
+    match channel {
+        Channel::Master => "Volume",
+        Channel::Ui => "Interface sounds",
+        Channel::Train => "Train sounds",
+        Channel::Effect => "Effect sounds",
+    }
+}
+
+fn colorblind_mode_name(mode: ColorblindMode) -> &'static str {
+    match (mode.glyphs, mode.high_contrast) {
+        (false, false) => "off",
+        (true, false) => "glyphs",
+        (false, true) => "palette",
+        (true, true) => "glyphs + palette",
+    }
+}
+
+fn next_colorblind_mode(mode: ColorblindMode) -> ColorblindMode {
+    // off, glyphs, palette, both, then off again
+    match (mode.glyphs, mode.high_contrast) {
+        (false, false) => ColorblindMode { glyphs: true, high_contrast: false },
+        (true, false) => ColorblindMode { glyphs: false, high_contrast: true },
+        (false, true) => ColorblindMode { glyphs: true, high_contrast: true },
+        (true, true) => ColorblindMode::default(),
+    }
+}
+
+fn particle_density_name(density: ParticleDensity) -> &'static str {
+    match density {
+        ParticleDensity::Full => "full",
+        ParticleDensity::Reduced => "reduced",
+        ParticleDensity::Off => "off",
+    }
+}
+
+fn next_particle_density(density: ParticleDensity) -> ParticleDensity {
+    match density {
+        ParticleDensity::Full => ParticleDensity::Reduced,
+        ParticleDensity::Reduced => ParticleDensity::Off,
+        ParticleDensity::Off => ParticleDensity::Full,
+    }
+}
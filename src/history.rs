@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+
+use crate::tile::tracktile::Tracktile;
+use crate::tile::Tile;
+
+// the number of strokes that can be undone. Older strokes are forgotten.
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone)]
+struct TileChange {
+    r: usize,
+    c: usize,
+    before: Tracktile,
+    after: Tracktile,
+}
+
+// A stroke is everything that changed between pressing and releasing the mouse button: a whole drag of
+// drawn track, a whole erase, a double click switching a tracktile or a hint. Strokes are undone as a
+// single step. Only the drawn tiles are looked at, so the history survives starting the trains and
+// going back to the drawing board.
+#[derive(Default)]
+pub struct History {
+    undo_stack: VecDeque<Vec<TileChange>>,
+    redo_stack: Vec<Vec<TileChange>>,
+    stroke_start: Option<Vec<Vec<Tile>>>,
+}
+
+impl History {
+    pub fn begin_stroke(&mut self, drawn_tiles: &[Vec<Tile>]) {
+        self.stroke_start = Some(drawn_tiles.to_vec());
+    }
+
+    pub fn end_stroke(&mut self, drawn_tiles: &[Vec<Tile>]) {
+        if let Some(stroke_start) = self.stroke_start.take() {
+            self.push(changes_between(&stroke_start, drawn_tiles));
+        }
+    }
+
+    pub fn push_stroke(&mut self, before: &[Vec<Tile>], after: &[Vec<Tile>]) {
+        // for changes that don't come from the mouse, e.g. a hint revealed once the solver is done. They are
+        // a stroke of their own, even when they happen in the middle of a stroke of the user.
+        let changes = changes_between(before, after);
+        if let Some(stroke_start) = &mut self.stroke_start {
+            for change in &changes {
+                stroke_start[change.r][change.c] = Tile::Tracktile(change.after.clone());
+            }
+        }
+        self.push(changes);
+    }
+
+    fn push(&mut self, changes: Vec<TileChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.undo_stack.push_back(changes);
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+    }
+
+    // undo and redo give each tracktile to put back to set_tracktile, e.g. Yard::set_tracktile.
+    // They return whether there was a stroke to undo or redo.
+    pub fn undo(&mut self, mut set_tracktile: impl FnMut(usize, usize, &Tracktile)) -> bool {
+        let Some(changes) = self.undo_stack.pop_back() else {
+            return false;
+        };
+        for change in changes.iter().rev() {
+            set_tracktile(change.r, change.c, &change.before);
+        }
+        self.redo_stack.push(changes);
+        true
+    }
+
+    pub fn redo(&mut self, mut set_tracktile: impl FnMut(usize, usize, &Tracktile)) -> bool {
+        let Some(changes) = self.redo_stack.pop() else {
+            return false;
+        };
+        for change in &changes {
+            set_tracktile(change.r, change.c, &change.after);
+        }
+        self.undo_stack.push_back(changes);
+        true
+    }
+}
+
+fn changes_between(before: &[Vec<Tile>], after: &[Vec<Tile>]) -> Vec<TileChange> {
+    let mut changes = vec![];
+    for (r, (row_before, row_after)) in before.iter().zip(after).enumerate() {
+        for (c, tiles) in row_before.iter().zip(row_after).enumerate() {
+            if let (Tile::Tracktile(before), Tile::Tracktile(after)) = tiles {
+                if !before.has_same_connections(after) {
+                    changes.push(TileChange { r, c, before: before.clone(), after: after.clone() });
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::Connection;
+
+    fn empty_tiles(size: usize) -> Vec<Vec<Tile>> {
+        (0..size).map(|_| (0..size).map(|_| Tile::Tracktile(Tracktile::new(None, None))).collect()).collect()
+    }
+
+    fn draw(tiles: &mut [Vec<Tile>], r: usize, c: usize) {
+        tiles[r][c] = Tile::Tracktile(Tracktile::new(Some(Connection { dir1: 1, dir2: 3 }), None));
+    }
+
+    fn is_drawn(tiles: &[Vec<Tile>], r: usize, c: usize) -> bool {
+        matches!(&tiles[r][c], Tile::Tracktile(tracktile) if tracktile.active_connection().is_some())
+    }
+
+    fn undo(history: &mut History, tiles: &mut [Vec<Tile>]) -> bool {
+        history.undo(|r, c, tracktile| tiles[r][c] = Tile::Tracktile(tracktile.clone()))
+    }
+
+    fn redo(history: &mut History, tiles: &mut [Vec<Tile>]) -> bool {
+        history.redo(|r, c, tracktile| tiles[r][c] = Tile::Tracktile(tracktile.clone()))
+    }
+
+    #[test]
+    fn a_stroke_is_undone_in_one_step() {
+        let mut history = History::default();
+        let mut tiles = empty_tiles(7);
+        history.begin_stroke(&tiles);
+        for c in 1..4 {
+            draw(&mut tiles, 3, c);
+        }
+        history.end_stroke(&tiles);
+
+        assert!(undo(&mut history, &mut tiles));
+        assert!((1..4).all(|c| !is_drawn(&tiles, 3, c)));
+        assert!(!undo(&mut history, &mut tiles));
+        assert!(redo(&mut history, &mut tiles));
+        assert!((1..4).all(|c| is_drawn(&tiles, 3, c)));
+    }
+
+    #[test]
+    fn a_new_stroke_clears_the_redo() {
+        let mut history = History::default();
+        let mut tiles = empty_tiles(7);
+        history.begin_stroke(&tiles);
+        draw(&mut tiles, 0, 0);
+        history.end_stroke(&tiles);
+        assert!(undo(&mut history, &mut tiles));
+
+        history.begin_stroke(&tiles);
+        draw(&mut tiles, 1, 1);
+        history.end_stroke(&tiles);
+        assert!(!redo(&mut history, &mut tiles));
+        assert!(!is_drawn(&tiles, 0, 0));
+    }
+
+    #[test]
+    fn the_oldest_strokes_are_forgotten() {
+        let mut history = History::default();
+        let mut tiles = empty_tiles(11);
+        for index in 0..MAX_HISTORY + 1 {
+            history.begin_stroke(&tiles);
+            draw(&mut tiles, index / 11, index % 11);
+            history.end_stroke(&tiles);
+        }
+        for _ in 0..MAX_HISTORY {
+            assert!(undo(&mut history, &mut tiles));
+        }
+        assert!(!undo(&mut history, &mut tiles));
+        // only the first stroke is left
+        assert!(is_drawn(&tiles, 0, 0));
+        assert!(!is_drawn(&tiles, 0, 1));
+    }
+
+    #[test]
+    fn starting_the_trains_keeps_the_history() {
+        // starting the trains and going back to the drawing board are clicks, so they are strokes too.
+        // They don't change the drawn tiles, so they neither add a step nor clear the redo.
+        let mut history = History::default();
+        let mut tiles = empty_tiles(7);
+        for c in 0..2 {
+            history.begin_stroke(&tiles);
+            draw(&mut tiles, 0, c);
+            history.end_stroke(&tiles);
+        }
+        assert!(undo(&mut history, &mut tiles));
+        for _ in 0..2 {
+            history.begin_stroke(&tiles);
+            history.end_stroke(&tiles);
+        }
+
+        assert!(redo(&mut history, &mut tiles));
+        assert!(is_drawn(&tiles, 0, 1));
+        assert!(undo(&mut history, &mut tiles));
+        assert!(undo(&mut history, &mut tiles));
+        assert!(!is_drawn(&tiles, 0, 0));
+    }
+
+    #[test]
+    fn changes_pushed_during_a_stroke_are_undone_on_their_own() {
+        // a hint revealed while the user is drawing
+        let mut history = History::default();
+        let mut tiles = empty_tiles(7);
+        history.begin_stroke(&tiles);
+        draw(&mut tiles, 0, 0);
+        let before_hint = tiles.clone();
+        draw(&mut tiles, 1, 1);
+        history.push_stroke(&before_hint, &tiles);
+        draw(&mut tiles, 0, 1);
+        history.end_stroke(&tiles);
+
+        assert!(undo(&mut history, &mut tiles));
+        assert!(!is_drawn(&tiles, 0, 0) && !is_drawn(&tiles, 0, 1));
+        assert!(is_drawn(&tiles, 1, 1));
+        assert!(undo(&mut history, &mut tiles));
+        assert!(!is_drawn(&tiles, 1, 1));
+    }
+}
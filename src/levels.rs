@@ -1,14 +1,18 @@
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::str;
-use std::io::prelude::*;
-use crate::color::Color::{self, Blue, Brown, Green, Orange, Purple, Red, Yellow};
-use crate::connection::Connection;
-use crate::tile::painter::Painter;
-use crate::tile::splitter::Splitter;
-use crate::tile::trainsink::Trainsink;
-use crate::tile::trainsource::Trainsource;
-use crate::tile::rock::Rock;
+use crate::color::rules::ColorRules;
+use crate::score::{Par, Score};
 use crate::tile::Tile;
+use crate::yard::{DEFAULT_NUM_COLS, DEFAULT_NUM_ROWS};
+
+pub mod lint;
+pub mod parser;
+pub mod save;
+pub mod writer;
+use parser::LevelParseError;
+use save::{back_up, write_atomically, LevelSave, SaveFile};
 
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -20,251 +24,137 @@ pub struct PositionedTile {
 pub type LevelInfo = Vec<PositionedTile>;
 pub type LevelProgress = (LevelInfo, bool); // the bool represents whether the play has won
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Level {
     pub level_info: LevelInfo,
     pub current_progress: LevelProgress,
+    #[serde(default)]
+    pub hints_used: u32,
+    // what the progress of the level is saved under. Unlike the name, it doesn't change when the level is renamed.
+    #[serde(default)]
+    pub id: String,
     pub name: String,
     pub num_stars: u32,
+    #[serde(default = "default_num_rows")]
+    pub num_rows: usize,
+    #[serde(default = "default_num_cols")]
+    pub num_cols: usize,
+    #[serde(default)]
+    pub par: Par,
+    #[serde(default)]
+    pub best_score: Option<Score>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct City {
-    name: String, 
-    levels:Vec<Level>,
+pub fn level_id_from_name(name: &str) -> String {
+    // the id of a level that doesn't declare one, e.g. `Red Line` has the id `red-line`.
+    let words: Vec<String> = name
+        .split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    words.join("-")
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct LevelManager(Vec<City>);
-
-fn convert_string_to_color(s: &str) -> Option<Color> {
-    // return none if s is invalid input
-    match s {
-        "red" => Some(Red),
-        "blue" => Some(Blue),
-        "yellow" => Some(Yellow),
-        "purple" => Some(Purple),
-        "green" => Some(Green),
-        "orange" => Some(Orange),
-        "brown" => Some(Brown),
-        _ => None,
+pub fn is_valid_level_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|ch| (ch.is_alphanumeric() && !ch.is_uppercase()) || ch == '-')
+}
+
+impl Level {
+    pub fn get_earned_stars(&self) -> Option<u32> {
+        self.best_score.map(|score| score.stars(&self.par))
     }
 }
 
-const SAVE_FILENAME: &str = ".trainyard_saved_progress.json";
+// saves from before levels could have their own size only contain 7x7 levels.
+fn default_num_rows() -> usize {
+    DEFAULT_NUM_ROWS
+}
+fn default_num_cols() -> usize {
+    DEFAULT_NUM_COLS
+}
 
-fn convert_string_to_dir(s: &str) -> Option<u8> {
-    // return none if s was an invalid direction.
-    match s {
-        "up" => Some(0),
-        "right" => Some(1),
-        "down" => Some(2),
-        "left" => Some(3),
-        _ => None,
-    }
+#[derive(Serialize, Deserialize, Debug)]
+pub struct City {
+    name: String, 
+    levels:Vec<Level>,
 }
 
-impl LevelManager {
-    pub fn new() -> LevelManager {
-        let f = std::fs::File::open(SAVE_FILENAME);
-        if let Ok(mut file) = f {
-            let mut contents = String::new();
-            file.read_to_string(&mut contents).expect("something went wrong reading the file");
-            let new_level_manager: LevelManager = serde_json::from_str(&contents).unwrap();
-            return new_level_manager;
-        }
-        println!("Unable to find previous save file");
+#[derive(Debug)]
+pub struct LevelManager {
+    cities: Vec<City>,
+    // progress found in the save file for levels that don't exist anymore. It is written back when saving,
+    // so that it comes back if the level does.
+    unknown_progress: BTreeMap<String, LevelSave>,
+    // where the progress is saved, see profiles.rs
+    save_path: PathBuf,
+    // set when the save couldn't be loaded as it was, to tell the player.
+    load_warning: Option<String>,
+    // the time of the last change that wasn't saved yet
+    last_unsaved_change: Option<f64>,
+    // whether the save was backed up this session, see save::back_up
+    backed_up: bool,
+    // set when the save comes from a newer version of the game, so that its progress isn't overwritten
+    save_disabled: bool,
+    // the color rules declared at the top of the levels file, see color/rules.rs
+    color_rules: ColorRules,
+}
 
+// levels made in the level editor are saved in this city of the user level file (see Profiles::user_levels_path),
+// in the same format as assets/levels.txt
+const USER_LEVELS_CITY: &str = "User Levels";
+// the levels of this city test the game itself rather than being puzzles, so they don't have to be winnable.
+// trainyard-lint and trainyard-solve skip them.
+pub const DEBUG_CITY: &str = "Debug";
+// in seconds
+const AUTOSAVE_DELAY: f64 = 1.0;
 
-        let mut lm = LevelManager(vec![]);
+impl LevelManager {
+    pub fn new(save_path: &Path) -> Result<LevelManager, LevelParseError> {
         let info_str = str::from_utf8(include_bytes!("../assets/levels.txt")).unwrap();
-        let mut arr = info_str
-            .split("\n")
-            .enumerate()
-            .map(|(index, line)| (index+1, line))
-            .filter(|(_, line)| !(line.starts_with("//") || line.is_empty()));
-        
-        
-        loop {
-            match arr.next() {
-                None => {
-                    return lm;
-                }
-                Some((line_num, line)) => {
-                    if !line.starts_with("CITY:") {
-                        panic!("expected line {} to start with `CITY:`", line_num)
-                    }
-                    let city_name = &line[5..];
-                    let mut city: City = City{name: city_name.to_owned(), levels: vec![]};
-                    loop {
-                        // load all levels within a city
-                        match arr.next() {
-                            None => {
-                                panic!("unexpected EOF");
-                            }
-                            Some((line_num, line)) => {
-                                if line == "----" {
-                                    break;
-                                }
-
-                                let fields: Vec<&str> = line.split(":").collect();
-                                if fields.len() != 2 {
-                                    panic!("expected two arguments separated by a `:` at line {}", line_num)
-                                }
-                                let level_name = fields[0];
-                                let num_stars: u32 = fields[1].parse()
-                                    .expect(&format!("expected second argument to be an integer at line {line_num}"));
-
-                                let level_info = LevelManager::extract_level_from_lines(&mut arr);
-
-                                city.levels.push(Level {
-                                    level_info,
-                                    current_progress: (vec![], false),
-                                    name: level_name.to_owned(),
-                                    num_stars,
-                                });
-
-                            }
-                        }
-                    }
-                    lm.0.push(city);
-                }
-            }
-        }
-    }
+        let mut level_manager = LevelManager::parse(info_str)?;
+        level_manager.save_path = save_path.to_owned();
 
-    fn extract_level_from_lines<'a, I>(arr: &mut I) -> LevelInfo 
-        where I: Iterator<Item = (usize, &'a str)>, 
-    {
-        let mut level_info: LevelInfo = vec![];
-
-        loop {
-            match arr.next() {
-                None => panic!("unexpected EOF"),
-                Some((line_num, line)) => {
-                    if line == "---" {
-                        break;
-                    } 
-
-                    let x: u8 = line[2..3].parse()
-                        .expect(&format!("expected a single-digit integer at the 3rd character of line {line_num}"));
-                    let y: u8 = line[4..5].parse()
-                        .expect(&format!("expected a single-digit integer at the 5th character of line {line_num}"));
-
-                    if line.starts_with("+ ") {
-                        // handle a new trainsource
-                        let fields: Vec<&str> = line.split(" ").collect();
-                        assert!(fields.len() == 4, "wrong number of spaces at line {line_num} (expected 3 spaces)");
-                        let colors: Vec<Color> = fields[2].split(",")
-                            .map(convert_string_to_color)
-                            .map(|o| o.expect(&format!("invalid color at line {line_num}")))
-                            .collect();
-                        let dir = convert_string_to_dir(fields[3])
-                            .expect(&format!("invalid direction in line {line_num}"));
-                        level_info.push(PositionedTile {
-                            tile: Tile::Trainsource(Trainsource::new(colors, dir)),
-                            x,
-                            y,
-                        });
-                    } else if line.starts_with("o ") {
-                        // handle a new trainsink
-                        let fields: Vec<&str> = line.split(" ").collect();
-                        assert!(fields.len() == 4, "wrong number of spaces at line {line_num} (expected 3 spaces)");
-                        let colors: Vec<Color> = fields[2].split(",")
-                            .map(convert_string_to_color)
-                            .map(|o| o.expect(&format!("invalid color at line {line_num}")))
-                            .collect();
-                        let dirs = fields[3]
-                            .split(",")
-                            .map(convert_string_to_dir)
-                            .map(|o| o.expect(&format!("invalid direction at line {line_num}")));
-                        let mut border_state = [false, false, false, false];
-                        for dir in dirs {
-                            border_state[dir as usize] = true;
-                        }
-                        level_info.push(PositionedTile {
-                            tile: Tile::Trainsink(Trainsink::new(colors, border_state)),
-                            x,
-                            y,
-                        });
-                    } else if line.starts_with("* ") {
-                        let mut positions = line[2..].split(" ");
-                        while let Some(position) = positions.next() {
-                            let x: u8 = position[0..1].parse()
-                                .expect(&format!("invalid single-digit number on line {line_num}"));
-                            let y: u8 = position[2..3].parse()
-                                .expect(&format!("invalid single-digit number on line {line_num}"));
-                            level_info.push(PositionedTile {
-                                tile: Tile::Rock(Rock::new()),
-                                x,
-                                y,
-                            });
-                        }
-                    } else if line.starts_with("p ") {
-                        // handle a new painter
-                        let fields: Vec<&str> = line.split(" ").collect();
-                        assert!(fields.len() == 4, "wrong number of spaces at line {line_num} (expected 3 spaces)");
-
-                        let color = convert_string_to_color(fields[2]).expect(&format!("invalid color at line {line_num}"));
-                        let dirs: Vec<u8> = fields[3].split(",")
-                            .map(convert_string_to_dir)
-                            .map(|o| o.expect(&format!("invalid direction at line {line_num}")))
-                            .collect();
-                        
-                        assert!(dirs.len() == 2, "expected exactly two directions for painter at line {line_num}");
-
-                        level_info.push(PositionedTile {
-                            tile: Tile::Painter(Painter::new(
-                                Connection {
-                                    dir1: dirs[0] as u8,
-                                    dir2: dirs[1] as u8,
-                                },
-                                color,
-                            )),
-                            x,
-                            y,
-                        });
-                    } else if line.starts_with("s ") {
-                        // handle a new splitter
-                        let fields: Vec<&str> = line.split(" ").collect();
-                        assert!(fields.len() == 3, "wrong number of spaces at line {line_num} (expected 2 spaces)");
-                        
-                        let dir = convert_string_to_dir(fields[2]).expect(&format!("invalid direction at line {line_num}"));
-                        level_info.push(PositionedTile {
-                            tile: Tile::Splitter(Splitter::new(dir)),
-                            x,
-                            y,
-                        });
-                    } else {
-                        panic!("line {line_num} begins with an invalid character: {line}");
-                    }
-                }
+        match save::load(save_path) {
+            Ok((save_file, load_warning)) => {
+                level_manager.apply_save(save_file);
+                level_manager.load_warning = load_warning;
+            }
+            Err(err) => {
+                eprintln!("Unable to load the save file {}: {err}", save_path.display());
+                level_manager.save_disabled = true;
+                level_manager.load_warning = Some("Save made by a newer version, progress won't be saved".to_owned());
             }
         }
+        Ok(level_manager)
+    }
 
-        level_info
+    pub fn parse(text: &str) -> Result<LevelManager, LevelParseError> {
+        parser::parse_levels(text)
     }
 
+    pub fn color_rules(&self) -> &ColorRules {
+        &self.color_rules
+    }
 
     pub fn get_city_names(&self) -> Vec<String> {
-        self.0.iter().map(|city| city.name.clone()).collect()
+        self.cities.iter().map(|city| city.name.clone()).collect()
     }
     pub fn get_names_in_city(&self, city_name: &str) -> Vec<String> {
-        let city = self.0.iter().find(|city| city.name == city_name).unwrap();
+        let city = self.cities.iter().find(|city| city.name == city_name).unwrap();
         city.levels.iter().map(|level| level.name.clone()).collect()
     }
     pub fn get_level(&self, level_name: &str) -> &Level {
-        for City{name: _, levels} in &self.0 {
+        for City{name: _, levels} in &self.cities {
             for level in levels {
                 if level.name == level_name {
-                    return &level;
+                    return level;
                 }
             }
         }
         panic!("trying to get level `{level_name}`, name not found");
     }
     pub fn set_level_current_progress(&mut self, level_name: &str, progress: &LevelProgress) {
-        for City{name: _, levels} in &mut self.0 {
+        for City{name: _, levels} in &mut self.cities {
             for level in levels {
                 if level.name == level_name {
                     level.current_progress = progress.clone();
@@ -275,11 +165,226 @@ impl LevelManager {
         panic!("trying to set current progress on `{level_name}`, name not found");
     }
 
-    pub fn save_progress_to_file(&self) {
-        println!("saving progress to file...");
-        let mut file = std::fs::File::create(SAVE_FILENAME).expect("create file failed");
-        let contents_to_write = serde_json::to_string(self).unwrap();
-        file.write_all(contents_to_write.as_bytes()).expect("write failed");
+    pub fn set_level_hints_used(&mut self, level_name: &str, hints_used: u32) {
+        for City{name: _, levels} in &mut self.cities {
+            for level in levels {
+                if level.name == level_name {
+                    level.hints_used = hints_used;
+                    return;
+                }
+            }
+        }
+        panic!("trying to set hints used on `{level_name}`, name not found");
+    }
+
+    pub fn set_level_score(&mut self, level_name: &str, score: Score) {
+        // only keeps the score if it beats the best one so far.
+        for City{name: _, levels} in &mut self.cities {
+            for level in levels {
+                if level.name == level_name {
+                    if level.best_score.is_none_or(|best| score.is_better_than(&best, &level.par)) {
+                        level.best_score = Some(score);
+                    }
+                    return;
+                }
+            }
+        }
+        panic!("trying to set the score of `{level_name}`, name not found");
+    }
+
+    pub fn to_levels_text(&self) -> String {
+        let cities = self.cities.iter()
+            .map(|city| writer::city_to_string(&city.name, &city.levels, &self.color_rules))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let rules = writer::rules_to_string(&self.color_rules);
+        if rules.is_empty() {
+            cities
+        } else {
+            rules + "\n" + &cities
+        }
+    }
+
+    pub fn save_user_level(path: &Path, level: &Level, color_rules: &ColorRules) -> Result<(), String> {
+        // adds the level to the user level file at path (see Profiles::user_levels_path), replacing any level
+        // with the same id. color_rules are the rules the level was made with.
+        let mut user_levels = match std::fs::read_to_string(path) {
+            Ok(text) => LevelManager::parse(&text)
+                .map_err(|err| format!("{} is invalid, not overwriting it. {err}", path.display()))?,
+            // a new file gets the rules of the levels being played, since the level may use their colors.
+            Err(_) => LevelManager {
+                cities: vec![],
+                unknown_progress: BTreeMap::new(),
+                save_path: PathBuf::new(),
+                load_warning: None,
+                last_unsaved_change: None,
+                backed_up: false,
+                save_disabled: false,
+                color_rules: color_rules.clone(),
+            },
+        };
+        if !user_levels.cities.iter().any(|city| city.name == USER_LEVELS_CITY) {
+            user_levels.cities.push(City { name: USER_LEVELS_CITY.to_owned(), levels: vec![] });
+        }
+        let city = user_levels.cities.iter_mut().find(|city| city.name == USER_LEVELS_CITY).unwrap();
+        match city.levels.iter_mut().find(|other| other.id == level.id) {
+            Some(other) => *other = level.clone(),
+            None => city.levels.push(level.clone()),
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        std::fs::write(path, user_levels.to_levels_text()).map_err(|err| err.to_string())
+    }
+
+    fn apply_save(&mut self, mut save_file: SaveFile) {
+        for level in self.cities.iter_mut().flat_map(|city| &mut city.levels) {
+            if let Some(level_save) = save_file.levels.remove(&level.id) {
+                level.current_progress = level_save.current_progress;
+                level.hints_used = level_save.hints_used;
+                level.best_score = level_save.best_score;
+                // the level may have changed since the progress was saved, so drop the tracks that
+                // no longer fit in its yard, or that are now under one of its tiles.
+                let (num_rows, num_cols) = (level.num_rows, level.num_cols);
+                let level_info = &level.level_info;
+                level.current_progress.0.retain(|tile| {
+                    (tile.y as usize) < num_rows && (tile.x as usize) < num_cols
+                        && !level_info.iter().any(|level_tile| (level_tile.x, level_tile.y) == (tile.x, tile.y))
+                });
+            }
+        }
+        self.unknown_progress = save_file.levels;
+    }
+
+    fn to_save_file(&self) -> SaveFile {
+        let mut save_file = SaveFile { levels: self.unknown_progress.clone(), ..SaveFile::default() };
+        for level in self.cities.iter().flat_map(|city| &city.levels) {
+            let level_save = LevelSave {
+                current_progress: level.current_progress.clone(),
+                hints_used: level.hints_used,
+                best_score: level.best_score,
+            };
+            let is_started = !level_save.current_progress.0.is_empty() || level_save.current_progress.1
+                || level_save.hints_used > 0 || level_save.best_score.is_some();
+            if is_started {
+                save_file.levels.insert(level.id.clone(), level_save);
+            }
+        }
+        save_file
+    }
+
+    pub fn take_load_warning(&mut self) -> Option<String> {
+        self.load_warning.take()
+    }
+
+    pub fn mark_unsaved(&mut self, now: f64) {
+        self.last_unsaved_change = Some(now);
+    }
+
+    pub fn autosave(&mut self, now: f64) {
+        // saves once no change was made for AUTOSAVE_DELAY seconds, so that drawing a track doesn't save
+        // after every tile.
+        if self.last_unsaved_change.is_some_and(|time| now - time >= AUTOSAVE_DELAY) {
+            self.save_progress_to_file();
+        }
+    }
+
+    pub fn save_if_unsaved(&mut self) {
+        if self.last_unsaved_change.is_some() {
+            self.save_progress_to_file();
+        }
+    }
+
+    pub fn save_progress_to_file(&mut self) {
+        self.last_unsaved_change = None;
+        if self.save_disabled {
+            return;
+        }
+        if !self.backed_up {
+            self.backed_up = true;
+            if let Err(err) = back_up(&self.save_path) {
+                eprintln!("Unable to back up {}: {err}", self.save_path.display());
+            }
+        }
+        if let Err(err) = write_atomically(&self.save_path, &self.to_save_file().to_json()) {
+            eprintln!("Unable to save progress to {}: {err}", self.save_path.display());
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_save_is_backed_up_once_per_session() {
+        let dir = std::env::temp_dir().join(format!("trainyard-levels-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let save_path = dir.join("progress.json");
+        let backup_path = dir.join("progress.json.bak1");
+        let backup_2_path = dir.join("progress.json.bak2");
+
+        let mut level_manager = LevelManager::new(&save_path).unwrap();
+        level_manager.set_level_hints_used("Red Line", 1);
+        level_manager.save_progress_to_file();
+        assert!(!backup_path.exists());
+
+        let mut level_manager = LevelManager::new(&save_path).unwrap();
+        level_manager.set_level_hints_used("Red Line", 2);
+        level_manager.save_progress_to_file();
+        level_manager.set_level_hints_used("Red Line", 3);
+        level_manager.save_progress_to_file();
+        assert!(backup_path.exists());
+        assert!(!backup_2_path.exists());
+        // the backup holds the progress from before the session
+        let (backup, _) = save::load(&backup_path).unwrap();
+        assert_eq!(backup.levels["red-line"].hints_used, 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_from_a_newer_version_are_not_overwritten() {
+        let dir = std::env::temp_dir().join(format!("trainyard-levels-newer-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let save_path = dir.join("progress.json");
+        let newer = format!(r#"{{"version": {}, "levels": {{"red-line": {{}}}}, "stars": 3}}"#, save::SAVE_VERSION + 1);
+        std::fs::write(&save_path, &newer).unwrap();
+
+        let mut level_manager = LevelManager::new(&save_path).unwrap();
+        assert!(level_manager.take_load_warning().is_some());
+        level_manager.set_level_hints_used("Red Line", 1);
+        level_manager.save_progress_to_file();
+        assert_eq!(std::fs::read_to_string(&save_path).unwrap(), newer);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn progress_is_kept_when_a_level_is_renamed() {
+        let before = "CITY:Abbotsford\n\nRed Line:1\n+ 1,3 red right\no 5,3 red left\n---\n----\n";
+        let after = "CITY:Abbotsford\n\nRed Road:1\nid red-line\n+ 1,3 red right\no 5,3 red left\n---\n----\n";
+        let mut level_manager = LevelManager::parse(before).unwrap();
+        assert_eq!(level_manager.get_level("Red Line").id, "red-line");
+        level_manager.set_level_hints_used("Red Line", 2);
+        let save_file = level_manager.to_save_file();
+
+        let mut level_manager = LevelManager::parse(after).unwrap();
+        level_manager.apply_save(save_file);
+        assert_eq!(level_manager.get_level("Red Road").hints_used, 2);
+        assert!(level_manager.unknown_progress.is_empty());
     }
 
+    #[test]
+    fn level_ids_are_made_from_names() {
+        assert_eq!(level_id_from_name("Red Line"), "red-line");
+        assert_eq!(level_id_from_name("  U-Turn!  "), "u-turn");
+        assert_eq!(level_id_from_name("Québec 2"), "québec-2");
+        assert!(is_valid_level_id(&level_id_from_name("Québec 2")));
+        assert!(!is_valid_level_id(&level_id_from_name("!!")));
+        assert!(!is_valid_level_id("Red-Line"));
+    }
 }
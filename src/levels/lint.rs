@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::color::Color;
+use crate::color::rules::ColorRules;
+use crate::levels::writer::color_name;
+use crate::levels::{Level, LevelManager, DEBUG_CITY};
+use crate::tile::Tile;
+
+// Finds levels that parse fine but can never be played or won. The parser already refuses some of these
+// problems, but everything is checked again on the parsed levels so that this doesn't depend on where they
+// came from.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintProblem {
+    pub level_name: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "level `{}`: {}", self.level_name, self.message)
+    }
+}
+
+pub fn lint_levels(level_manager: &LevelManager) -> Vec<LintProblem> {
+    let mut problems = vec![];
+    let mut names = HashSet::new();
+    let mut ids = HashSet::new();
+    for city in &level_manager.cities {
+        for level in &city.levels {
+            // LevelManager::get_level returns the first level with a name, so the others could never be played.
+            if !names.insert(&level.name) {
+                problems.push(LintProblem {
+                    level_name: level.name.clone(),
+                    message: "another level has the same name".to_owned(),
+                });
+            }
+            // the progress of both levels would be saved in the same place.
+            if !ids.insert(&level.id) {
+                problems.push(LintProblem {
+                    level_name: level.name.clone(),
+                    message: format!("another level has the id `{}`", level.id),
+                });
+            }
+            if city.name == DEBUG_CITY {
+                continue;
+            }
+            problems.extend(lint_level(level, &level_manager.color_rules).into_iter().map(|message| LintProblem {
+                level_name: level.name.clone(),
+                message,
+            }));
+        }
+    }
+    problems
+}
+
+pub fn lint_level(level: &Level, rules: &ColorRules) -> Vec<String> {
+    let mut problems = vec![];
+    let mut positions = HashSet::new();
+    for tile in &level.level_info {
+        let (x, y) = (tile.x as usize, tile.y as usize);
+        if x >= level.num_cols || y >= level.num_rows {
+            problems.push(format!("the tile at {x},{y} is outside of the {}x{} yard", level.num_cols, level.num_rows));
+            continue;
+        }
+        if !positions.insert((x, y)) {
+            problems.push(format!("there is more than one tile at {x},{y}"));
+        }
+        match &tile.tile {
+            Tile::Trainsource(trainsource) if faces_border(level, x, y, trainsource.dir) => {
+                problems.push(format!("the trainsource at {x},{y} faces the border of the yard"));
+            }
+            Tile::Trainsink(trainsink) if !trainsink.border_state.contains(&true) => {
+                problems.push(format!("the trainsink at {x},{y} has no open side"));
+            }
+            Tile::Painter(painter) if painter.connection.dir1 == painter.connection.dir2 => {
+                problems.push(format!("the painter at {x},{y} uses the same direction twice"));
+            }
+            Tile::Filter(filter) if filter.connection.dir1 == filter.connection.dir2 => {
+                problems.push(format!("the filter at {x},{y} uses the same direction twice"));
+            }
+            Tile::Station(station) if station.entry_dir == station.exit_dir => {
+                problems.push(format!("the station at {x},{y} uses the same direction twice"));
+            }
+            Tile::Station(station) if faces_border(level, x, y, station.exit_dir) => {
+                problems.push(format!("the exit of the station at {x},{y} faces the border of the yard"));
+            }
+            Tile::OneWay(oneway) if oneway.connection.from == oneway.connection.to => {
+                problems.push(format!("the one-way track at {x},{y} uses the same direction twice"));
+            }
+            Tile::OneWay(oneway) if faces_border(level, x, y, oneway.connection.to) => {
+                problems.push(format!("the one-way track at {x},{y} leads to the border of the yard"));
+            }
+            Tile::Turntable(turntable) => {
+                for dir in &turntable.exits {
+                    if faces_border(level, x, y, *dir) {
+                        problems.push(format!("the turntable at {x},{y} has an exit facing the border of the yard"));
+                        break;
+                    }
+                }
+            }
+            Tile::Tunnel(tunnel) if faces_border(level, x, y, tunnel.dir) => {
+                problems.push(format!("the tunnel at {x},{y} faces the border of the yard"));
+            }
+            _ => {}
+        }
+    }
+    problems.extend(lint_tunnels(level));
+    problems.extend(lint_colors(level, rules));
+    problems
+}
+
+pub fn lint_tunnels(level: &Level) -> Vec<String> {
+    let mut problems = vec![];
+    let mut ids: Vec<u8> = level.level_info.iter()
+        .filter_map(|tile| match &tile.tile {
+            Tile::Tunnel(tunnel) => Some(tunnel.id),
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    for id in ids {
+        let count = level.level_info.iter().filter(|tile| matches!(&tile.tile, Tile::Tunnel(tunnel) if tunnel.id == id)).count();
+        if count != 2 {
+            problems.push(format!("tunnel {id} has {count} end(s), but a tunnel needs exactly 2"));
+        }
+    }
+    problems
+}
+
+fn faces_border(level: &Level, x: usize, y: usize, dir: u8) -> bool {
+    match dir {
+        0 => y == 0,
+        1 => x + 1 == level.num_cols,
+        2 => y + 1 == level.num_rows,
+        _ => x == 0,
+    }
+}
+
+fn lint_colors(level: &Level, rules: &ColorRules) -> Vec<String> {
+    // only reports what no layout can fix. Painters can turn any train into any color, and splitters can
+    // turn one train into two, so most checks are skipped for levels that have them.
+    let mut problems = vec![];
+    let tiles = || level.level_info.iter().map(|tile| &tile.tile);
+    let trains: Vec<Color> = tiles()
+        .filter_map(|tile| match tile {
+            Tile::Trainsource(trainsource) => Some(trainsource.trains.iter().flatten().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    let desires: Vec<Color> = tiles()
+        .filter_map(|tile| match tile {
+            Tile::Trainsink(trainsink) => Some(trainsink.desires.iter().flatten().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    let has_painters = tiles().any(|tile| matches!(tile, Tile::Painter(_)));
+    let has_splitters = tiles().any(|tile| matches!(tile, Tile::Splitter(_)));
+
+    if trains.is_empty() && !desires.is_empty() {
+        problems.push("the trainsinks want trains, but there are no trains".to_owned());
+    }
+    if !trains.is_empty() && desires.is_empty() {
+        problems.push("there are trains, but no trainsink wants them".to_owned());
+    }
+    // trains can merge, but only a splitter can make more of them.
+    if !has_splitters && trains.len() < desires.len() {
+        problems.push(format!("the trainsinks want {} trains, but there are only {}", desires.len(), trains.len()));
+    }
+    if has_painters {
+        return problems;
+    }
+
+    let colors = rules.all_colors();
+    // every color that the trains can become by mixing with each other, or going through splitters.
+    let mut reachable: Vec<Color> = vec![];
+    for color in &trains {
+        if !reachable.contains(color) {
+            reachable.push(*color);
+        }
+    }
+    loop {
+        let new_colors: Vec<Color> = colors.iter().copied()
+            .filter(|color| !reachable.contains(color))
+            .filter(|color| {
+                reachable.iter().any(|a| reachable.iter().any(|b| rules.mix(*a, *b) == *color))
+                    || (has_splitters && reachable.iter().any(|other| rules.split(*other).contains(color)))
+            })
+            .collect();
+        if new_colors.is_empty() {
+            break;
+        }
+        reachable.extend(new_colors);
+    }
+    for &color in &colors {
+        if desires.contains(&color) && !reachable.contains(&color) {
+            let name = color_name(rules, color);
+            problems.push(format!("a trainsink wants a {name} train, but no train can become {name}"));
+        }
+    }
+    if has_splitters {
+        return problems;
+    }
+
+    // a train has to end up in a trainsink, so its color has to be wanted, or it has to be able to mix
+    // into a wanted color.
+    let mut useful = desires.clone();
+    loop {
+        let new_colors: Vec<Color> = colors.iter().copied()
+            .filter(|color| !useful.contains(color))
+            .filter(|color| colors.iter().any(|other| useful.contains(&rules.mix(*color, *other))))
+            .collect();
+        if new_colors.is_empty() {
+            break;
+        }
+        useful.extend(new_colors);
+    }
+    for &color in &colors {
+        if trains.contains(&color) && !useful.contains(&color) {
+            problems.push(format!("there is a {} train, but no trainsink can accept it", color_name(rules, color)));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_levels_have_no_problems() {
+        let level_manager = LevelManager::parse(include_str!("../../assets/levels.txt")).unwrap();
+        assert_eq!(lint_levels(&level_manager), vec![]);
+    }
+
+    #[test]
+    fn debug_levels_are_skipped() {
+        let text = "CITY:Debug\n\nNo Sink:1\n+ 1,3 red right\n---\n----\n";
+        let level_manager = LevelManager::parse(text).unwrap();
+        assert_eq!(lint_levels(&level_manager), vec![]);
+
+        let level_manager = LevelManager::parse(&text.replace("CITY:Debug", "CITY:Abbotsford")).unwrap();
+        let messages: Vec<String> = lint_levels(&level_manager).into_iter().map(|problem| problem.message).collect();
+        assert!(messages.contains(&"there are trains, but no trainsink wants them".to_owned()), "{messages:?}");
+    }
+
+    #[test]
+    fn pack_colors_are_named() {
+        let text = "color pink 240,130,200\n\nCITY:Abbotsford\n\nPink:1\n+ 1,3 red right\no 5,3 pink left\n---\n----\n";
+        let level_manager = LevelManager::parse(text).unwrap();
+        let messages: Vec<String> = lint_levels(&level_manager).into_iter().map(|problem| problem.message).collect();
+        assert!(messages.contains(&"a trainsink wants a pink train, but no train can become pink".to_owned()), "{messages:?}");
+    }
+}
@@ -0,0 +1,763 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::fmt;
+
+use crate::color::rules::{ColorRules, CustomColor, ManyTrainMix};
+use crate::color::Color::{self, Blue, Brown, Green, Orange, Purple, Red, Yellow};
+use crate::connection::{Connection, DirectedConnection};
+use crate::levels::{is_valid_level_id, level_id_from_name, City, Level, LevelInfo, LevelManager, PositionedTile};
+use crate::score::Par;
+use crate::tile::oneway::OneWay;
+use crate::tile::painter::Painter;
+use crate::tile::rock::Rock;
+use crate::tile::splitter::Splitter;
+use crate::tile::station::{self, Station};
+use crate::tile::trainsink::Trainsink;
+use crate::tile::trainsource::Trainsource;
+use crate::tile::turntable::Turntable;
+use crate::tile::bridge::Bridge;
+use crate::tile::filter::Filter;
+use crate::tile::tunnel::Tunnel;
+use crate::tile::Tile;
+use crate::yard::{DEFAULT_NUM_COLS, DEFAULT_NUM_ROWS, MAX_NUM_COLS, MAX_NUM_ROWS};
+
+// The format of levels.txt is described at the top of that file. The parser goes through it line by line and
+// never stops at the first problem: a malformed line is reported and skipped, so that the author of a level pack
+// sees every mistake at once.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseProblem {
+    // both line and column start at 1
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelParseError {
+    pub problems: Vec<ParseProblem>,
+}
+
+impl fmt::Display for ParseProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl fmt::Display for LevelParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "found {} problem(s) while parsing levels:", self.problems.len())?;
+        for problem in &self.problems {
+            write!(f, "\n  {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LevelParseError {}
+
+pub fn convert_string_to_color(s: &str) -> Option<Color> {
+    // return none if s is invalid input
+    match s {
+        "red" => Some(Red),
+        "blue" => Some(Blue),
+        "yellow" => Some(Yellow),
+        "purple" => Some(Purple),
+        "green" => Some(Green),
+        "orange" => Some(Orange),
+        "brown" => Some(Brown),
+        _ => None,
+    }
+}
+
+pub fn convert_string_to_many_train_mix(s: &str) -> Option<ManyTrainMix> {
+    match s {
+        "same" => Some(ManyTrainMix::Same),
+        "pairwise" => Some(ManyTrainMix::Pairwise),
+        _ => None,
+    }
+}
+
+pub fn convert_string_to_dir(s: &str) -> Option<u8> {
+    // return none if s was an invalid direction.
+    match s {
+        "up" => Some(0),
+        "right" => Some(1),
+        "down" => Some(2),
+        "left" => Some(3),
+        _ => None,
+    }
+}
+
+// a piece of a line, along with the column it starts at.
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    column: usize,
+    text: &'a str,
+}
+
+impl<'a> Token<'a> {
+    fn split(&self, separator: char) -> Vec<Token<'a>> {
+        let mut column = self.column;
+        self.text.split(separator).map(|text| {
+            let token = Token { column, text };
+            column += text.len() + 1;
+            token
+        }).collect()
+    }
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (index, ch) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, ch.is_whitespace()) {
+            (None, false) => start = Some(index),
+            (Some(s), true) => {
+                tokens.push(Token { column: s + 1, text: &line[s..index] });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+enum State {
+    ExpectCity,
+    ExpectLevel(City),
+    InLevel(City, Level),
+}
+
+struct Parser {
+    problems: Vec<ParseProblem>,
+    line_num: usize,
+    rules: ColorRules,
+    // the rule keywords seen so far, since the first mix or split of a pack replaces the classic ones.
+    declared_rules: Vec<String>,
+}
+
+const RULE_KEYWORDS: [&str; 5] = ["color", "mix", "mix-fallback", "mix-many", "split"];
+
+impl Parser {
+    fn error(&mut self, column: usize, message: String) {
+        self.problems.push(ParseProblem { line: self.line_num, column, message });
+    }
+
+    fn parse(&mut self, text: &str) -> Vec<City> {
+        let mut cities = vec![];
+        let mut state = State::ExpectCity;
+        let mut last_line_num = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            self.line_num = index + 1;
+            last_line_num = self.line_num;
+            let line = line.trim_end();
+            if line.starts_with("//") || line.is_empty() {
+                continue;
+            }
+
+            state = match state {
+                State::ExpectCity => match line.strip_prefix("CITY:") {
+                    Some(name) => self.start_city(name),
+                    None if RULE_KEYWORDS.contains(&tokenize(line)[0].text) => {
+                        if cities.is_empty() {
+                            self.parse_rule_line(line);
+                        } else {
+                            self.error(1, "color rules apply to the whole file, they have to come before the first city".to_owned());
+                        }
+                        State::ExpectCity
+                    }
+                    None => {
+                        self.error(1, format!("expected a line of the form `CITY:<name>`, found `{line}`"));
+                        State::ExpectCity
+                    }
+                },
+                State::ExpectLevel(city) => {
+                    if line == "----" {
+                        cities.push(city);
+                        State::ExpectCity
+                    } else if let Some(name) = line.strip_prefix("CITY:") {
+                        self.error(1, format!("expected `----` to close city `{}` before starting a new city", city.name));
+                        cities.push(city);
+                        self.start_city(name)
+                    } else {
+                        let level = self.parse_level_header(line);
+                        State::InLevel(city, level)
+                    }
+                }
+                State::InLevel(mut city, mut level) => {
+                    if line == "---" {
+                        self.check_tunnels(&level);
+                        if level.id.is_empty() {
+                            level.id = level_id_from_name(&level.name);
+                        }
+                        city.levels.push(level);
+                        State::ExpectLevel(city)
+                    } else if line == "----" {
+                        self.error(1, format!("expected `---` to close level `{}` before closing the city", level.name));
+                        city.levels.push(level);
+                        cities.push(city);
+                        State::ExpectCity
+                    } else if tokenize(line)[0].text == "par" {
+                        self.parse_par_line(line, &mut level.par);
+                        State::InLevel(city, level)
+                    } else if tokenize(line)[0].text == "id" {
+                        self.parse_id_line(line, &mut level.id);
+                        State::InLevel(city, level)
+                    } else {
+                        if let Some(tile) = self.parse_tile_line(line, level.num_rows, level.num_cols) {
+                            self.push_tiles(&mut level.level_info, tile);
+                        }
+                        State::InLevel(city, level)
+                    }
+                }
+            };
+        }
+
+        self.line_num = last_line_num + 1;
+        match state {
+            State::ExpectCity => {}
+            State::ExpectLevel(city) => {
+                self.error(1, format!("unexpected end of file, expected `----` to close city `{}`", city.name));
+                cities.push(city);
+            }
+            State::InLevel(mut city, level) => {
+                self.error(1, format!("unexpected end of file, expected `---` to close level `{}`", level.name));
+                city.levels.push(level);
+                cities.push(city);
+            }
+        }
+        cities
+    }
+
+    fn parse_rule_line(&mut self, line: &str) {
+        // `color <name> <r>,<g>,<b>`, `mix <color>+<color> <color>`, `split <color> <left>,<right>`,
+        // `mix-fallback <color>` or `mix-many same|pairwise`.
+        let tokens = tokenize(line);
+        let keyword = tokens[0].text;
+        let num_fields = if ["color", "mix", "split"].contains(&keyword) {3} else {2};
+        if tokens.len() != num_fields {
+            let column = tokens.get(num_fields).map_or(line.len() + 1, |token| token.column);
+            self.error(column, format!("expected {} fields separated by spaces, found {}", num_fields, tokens.len()));
+            return;
+        }
+        let first_of_its_kind = !self.declared_rules.iter().any(|other| other == keyword);
+        self.declared_rules.push(keyword.to_owned());
+        match keyword {
+            "color" => self.parse_custom_color(tokens[1], tokens[2]),
+            "mix" => {
+                if first_of_its_kind {
+                    self.rules.mixes.clear();
+                }
+                let colors = self.parse_color_pair(tokens[1], '+');
+                let result = self.parse_color(tokens[2]);
+                let (Some(colors), Some(result)) = (colors, result) else {
+                    return;
+                };
+                if colors[0] == colors[1] {
+                    self.error(tokens[1].column, "two trains of the same color always keep it, they can't have a mix".to_owned());
+                } else if self.rules.mixes.iter().any(|(other, _)| *other == colors || *other == [colors[1], colors[0]]) {
+                    self.error(tokens[1].column, format!("the mix of {} is declared twice", tokens[1].text));
+                } else {
+                    self.rules.mixes.push((colors, result));
+                }
+            }
+            "split" => {
+                if first_of_its_kind {
+                    self.rules.splits.clear();
+                }
+                let color = self.parse_color(tokens[1]);
+                let result = self.parse_color_pair(tokens[2], ',');
+                let (Some(color), Some(result)) = (color, result) else {
+                    return;
+                };
+                if self.rules.splits.iter().any(|(other, _)| *other == color) {
+                    self.error(tokens[1].column, format!("the split of {} is declared twice", tokens[1].text));
+                } else {
+                    self.rules.splits.push((color, result));
+                }
+            }
+            "mix-fallback" => {
+                if !first_of_its_kind {
+                    self.error(1, "mix-fallback is declared twice".to_owned());
+                }
+                if let Some(color) = self.parse_color(tokens[1]) {
+                    self.rules.fallback = color;
+                }
+            }
+            _ => {
+                if !first_of_its_kind {
+                    self.error(1, "mix-many is declared twice".to_owned());
+                }
+                match convert_string_to_many_train_mix(tokens[1].text) {
+                    Some(many_train_mix) => self.rules.many_train_mix = many_train_mix,
+                    None => self.error(tokens[1].column, format!("invalid way of mixing many trains `{}`, expected same or pairwise", tokens[1].text)),
+                }
+            }
+        }
+    }
+
+    fn parse_custom_color(&mut self, name: Token, rgb: Token) {
+        if convert_string_to_color(name.text).is_some() || self.rules.find_custom_color(name.text).is_some() {
+            self.error(name.column, format!("there is already a color called `{}`", name.text));
+            return;
+        }
+        if name.text.contains([',', '+']) {
+            self.error(name.column, format!("the name of a color can't contain `,` or `+`, found `{}`", name.text));
+            return;
+        }
+        if self.rules.custom_colors.len() > u8::MAX as usize {
+            self.error(name.column, format!("a level pack can't declare more than {} colors", u8::MAX as usize + 1));
+            return;
+        }
+        let components: Vec<Option<u8>> = rgb.split(',').iter().map(|token| token.text.parse::<u8>().ok()).collect();
+        match components[..] {
+            [Some(r), Some(g), Some(b)] => self.rules.custom_colors.push(CustomColor { name: name.text.to_owned(), rgb: [r, g, b] }),
+            _ => self.error(rgb.column, format!("expected a color of the form `<red>,<green>,<blue>` with each from 0 to 255, found `{}`", rgb.text)),
+        }
+    }
+
+    fn parse_color_pair(&mut self, token: Token, separator: char) -> Option<[Color; 2]> {
+        let fields = token.split(separator);
+        if fields.len() != 2 {
+            self.error(token.column, format!("expected two colors of the form `<color>{}<color>`, found `{}`", separator, token.text));
+            return None;
+        }
+        let first = self.parse_color(fields[0]);
+        let second = self.parse_color(fields[1]);
+        Some([first?, second?])
+    }
+
+    fn start_city(&mut self, name: &str) -> State {
+        if name.trim().is_empty() {
+            self.error(6, "expected a city name after `CITY:`".to_owned());
+        }
+        State::ExpectLevel(City { name: name.trim().to_owned(), levels: vec![] })
+    }
+
+    fn parse_level_header(&mut self, line: &str) -> Level {
+        // a level starts with `<name>:<number of stars>`, optionally followed by `:<width>x<height>`.
+        // Even if the header is malformed, we return a level so that its tiles still get checked.
+        let mut level = Level {
+            level_info: vec![],
+            current_progress: (vec![], false),
+            hints_used: 0,
+            // filled in from the name when the level ends, unless the level has an id line
+            id: String::new(),
+            name: line.to_owned(),
+            num_stars: 0,
+            num_rows: DEFAULT_NUM_ROWS,
+            num_cols: DEFAULT_NUM_COLS,
+            par: Par::default(),
+            best_score: None,
+        };
+        let fields = Token { column: 1, text: line }.split(':');
+        if !(2..=3).contains(&fields.len()) {
+            self.error(1, format!("expected a level header of the form `<name>:<number of stars>[:<width>x<height>]`, found `{line}`"));
+            return level;
+        }
+        level.name = fields[0].text.to_owned();
+        if fields[0].text.trim().is_empty() {
+            self.error(1, "expected a level name before `:`".to_owned());
+        }
+        match fields[1].text.trim().parse() {
+            Ok(num_stars) => level.num_stars = num_stars,
+            Err(_) => self.error(fields[1].column, format!("expected the number of stars to be an integer, found `{}`", fields[1].text)),
+        }
+        if let Some(size) = fields.get(2) {
+            let dims = size.split('x');
+            if dims.len() != 2 {
+                self.error(size.column, format!("expected the size of the yard to be of the form `<width>x<height>`, found `{}`", size.text));
+                return level;
+            }
+            if let Some(num_cols) = self.parse_dimension(dims[0], MAX_NUM_COLS) {
+                level.num_cols = num_cols;
+            }
+            if let Some(num_rows) = self.parse_dimension(dims[1], MAX_NUM_ROWS) {
+                level.num_rows = num_rows;
+            }
+        }
+        level
+    }
+
+    fn parse_dimension(&mut self, token: Token, max: usize) -> Option<usize> {
+        match token.text.parse::<usize>() {
+            Ok(value) if (1..=max).contains(&value) => Some(value),
+            _ => {
+                self.error(token.column, format!("expected a yard dimension from 1 to {}, found `{}`", max, token.text));
+                None
+            }
+        }
+    }
+
+    fn parse_par_line(&mut self, line: &str, par: &mut Par) {
+        // `par tracks=<n> switches=<n> ticks=<n>`, each of the three being optional.
+        if !par.is_empty() {
+            self.error(1, "this level already has a par".to_owned());
+        }
+        let tokens = tokenize(line);
+        if tokens.len() < 2 {
+            self.error(line.len() + 1, "expected at least one of `tracks=<n>`, `switches=<n>` or `ticks=<n>` after `par`".to_owned());
+        }
+        for token in &tokens[1..] {
+            let fields = token.split('=');
+            let value = match fields.get(1).map(|value| value.text.parse::<u32>()) {
+                Some(Ok(value)) if fields.len() == 2 => value,
+                _ => {
+                    self.error(token.column, format!("expected a par of the form `<name>=<number>`, found `{}`", token.text));
+                    continue;
+                }
+            };
+            let field = match fields[0].text {
+                "tracks" => &mut par.tracks,
+                "switches" => &mut par.switches,
+                "ticks" => &mut par.ticks,
+                _ => {
+                    self.error(token.column, format!("unknown par `{}`, expected one of tracks, switches, ticks", fields[0].text));
+                    continue;
+                }
+            };
+            if field.is_some() {
+                self.error(token.column, format!("the par for {} is given twice", fields[0].text));
+            }
+            *field = Some(value);
+        }
+    }
+
+    fn parse_id_line(&mut self, line: &str, id: &mut String) {
+        // `id <id>`, the id being made of lowercase letters, digits and dashes.
+        if !id.is_empty() {
+            self.error(1, "this level already has an id".to_owned());
+        }
+        let tokens = tokenize(line);
+        let Some(token) = tokens.get(1) else {
+            self.error(line.len() + 1, "expected an id after `id`".to_owned());
+            return;
+        };
+        if tokens.len() > 2 {
+            self.error(tokens[2].column, format!("expected the end of the line after the id, found `{}`", tokens[2].text));
+        }
+        if !is_valid_level_id(token.text) {
+            self.error(token.column, format!("a level id can only contain lowercase letters, digits and dashes, found `{}`", token.text));
+        }
+        *id = token.text.to_owned();
+    }
+
+    fn push_tiles(&mut self, level_info: &mut LevelInfo, tiles: Vec<(Token, PositionedTile)>) {
+        for (token, tile) in tiles {
+            if level_info.iter().any(|other| (other.x, other.y) == (tile.x, tile.y)) {
+                self.error(token.column, format!("there is already a tile at {}", token.text));
+                continue;
+            }
+            level_info.push(tile);
+        }
+    }
+
+    fn parse_tile_line<'a>(&mut self, line: &'a str, num_rows: usize, num_cols: usize) -> Option<Vec<(Token<'a>, PositionedTile)>> {
+        // returns the tiles on the line along with the token of their position, or None if the line is invalid.
+        let tokens = tokenize(line);
+        let symbol = tokens[0];
+        let expected_num_fields = match symbol.text {
+            "+" | "o" | "p" | "t" | "f" | "d" => Some(4),
+            "s" | "b" | "r" | ">" => Some(3),
+            "*" => None,
+            _ => {
+                self.error(symbol.column, format!("expected a tile symbol (one of `+`, `o`, `*`, `p`, `s`, `t`, `f`, `b`, `d`, `r`, `>`), found `{}`", symbol.text));
+                return None;
+            }
+        };
+        match expected_num_fields {
+            Some(num_fields) if tokens.len() != num_fields => {
+                let column = tokens.get(num_fields).map_or(line.len() + 1, |token| token.column);
+                self.error(column, format!("expected {} fields separated by spaces, found {}", num_fields, tokens.len()));
+                return None;
+            }
+            None if tokens.len() < 2 => {
+                self.error(line.len() + 1, "expected at least one position after `*`".to_owned());
+                return None;
+            }
+            _ => {}
+        }
+
+        if symbol.text == "*" {
+            let rocks: Vec<_> = tokens[1..].iter()
+                .map(|token| self.parse_position(*token, num_rows, num_cols).map(|(x, y)| (*token, PositionedTile { tile: Tile::Rock(Rock::new()), x, y })))
+                .collect();
+            return rocks.into_iter().collect();
+        }
+
+        let position = self.parse_position(tokens[1], num_rows, num_cols);
+        let tile = match symbol.text {
+            "+" => {
+                let colors = self.parse_colors(tokens[2]);
+                let dir = self.parse_dir(tokens[3]);
+                Tile::Trainsource(Trainsource::new(colors?, dir?))
+            }
+            "o" => {
+                let colors = self.parse_colors(tokens[2]);
+                let dirs = self.parse_dirs(tokens[3]);
+                let mut border_state = [false, false, false, false];
+                for dir in dirs? {
+                    border_state[dir as usize] = true;
+                }
+                Tile::Trainsink(Trainsink::new(colors?, border_state))
+            }
+            "p" | "f" => {
+                let color = self.parse_color(tokens[2]);
+                let dirs = self.parse_dirs(tokens[3])?;
+                let tile_name = if symbol.text == "p" {"painter"} else {"filter"};
+                if dirs.len() != 2 || dirs[0] == dirs[1] {
+                    self.error(tokens[3].column, format!("expected exactly two different directions for a {tile_name}"));
+                    return None;
+                }
+                let connection = Connection { dir1: dirs[0], dir2: dirs[1] };
+                if symbol.text == "p" {
+                    Tile::Painter(Painter::new(connection, color?))
+                } else {
+                    Tile::Filter(Filter::new(connection, color?))
+                }
+            }
+            "t" => {
+                let id = self.parse_tunnel_id(tokens[2]);
+                let dir = self.parse_dir(tokens[3]);
+                Tile::Tunnel(Tunnel::new(id?, dir?))
+            }
+            "d" => {
+                let delay = self.parse_delay(tokens[2]);
+                let dirs = self.parse_dirs(tokens[3])?;
+                if dirs.len() != 2 || dirs[0] == dirs[1] {
+                    self.error(tokens[3].column, "expected two different directions for a station, its entry and its exit".to_owned());
+                    return None;
+                }
+                Tile::Station(Station::new(dirs[0], dirs[1], delay?))
+            }
+            ">" => {
+                let dirs = self.parse_dirs(tokens[2])?;
+                if dirs.len() != 2 || dirs[0] == dirs[1] {
+                    self.error(tokens[2].column, "expected two different directions for a one-way track, where trains come from and where they go".to_owned());
+                    return None;
+                }
+                Tile::OneWay(OneWay::new(DirectedConnection { from: dirs[0], to: dirs[1] }))
+            }
+            "r" => Tile::Turntable(Turntable::new(self.parse_dirs(tokens[2])?)),
+            "b" => Tile::Bridge(Bridge::new(self.parse_dir(tokens[2])?)),
+            _ => Tile::Splitter(Splitter::new(self.parse_dir(tokens[2])?)),
+        };
+        let (x, y) = position?;
+        Some(vec![(tokens[1], PositionedTile { tile, x, y })])
+    }
+
+    fn parse_tunnel_id(&mut self, token: Token) -> Option<u8> {
+        let id = token.text.parse::<u8>().ok();
+        if id.is_none() {
+            self.error(token.column, format!("expected the id of a tunnel to be a number from 0 to 255, found `{}`", token.text));
+        }
+        id
+    }
+
+    fn parse_delay(&mut self, token: Token) -> Option<u32> {
+        match token.text.parse::<u32>() {
+            Ok(delay) if delay <= station::MAX_DELAY => Some(delay),
+            _ => {
+                self.error(token.column, format!("expected a delay from 0 to {} ticks, found `{}`", station::MAX_DELAY, token.text));
+                None
+            }
+        }
+    }
+
+    fn check_tunnels(&mut self, level: &Level) {
+        // reported on the line that closes the level, since it is only known once all of its tiles are read.
+        let mut tunnels: BTreeMap<u8, Vec<(u8, u8)>> = BTreeMap::new();
+        for tile in &level.level_info {
+            if let Tile::Tunnel(tunnel) = &tile.tile {
+                tunnels.entry(tunnel.id).or_default().push((tile.x, tile.y));
+            }
+        }
+        for (id, positions) in tunnels {
+            if positions.len() != 2 {
+                let positions: Vec<String> = positions.iter().map(|(x, y)| format!("{x},{y}")).collect();
+                self.error(1, format!(
+                    "tunnel {} of level `{}` is used by {} tunnel(s) at {}, expected exactly 2",
+                    id, level.name, positions.len(), positions.join(" "),
+                ));
+            }
+        }
+    }
+
+    fn parse_position(&mut self, token: Token, num_rows: usize, num_cols: usize) -> Option<(u8, u8)> {
+        let fields = token.split(',');
+        if fields.len() != 2 {
+            self.error(token.column, format!("expected a position of the form `x,y`, found `{}`", token.text));
+            return None;
+        }
+        let x = self.parse_coordinate(fields[0], num_cols);
+        let y = self.parse_coordinate(fields[1], num_rows);
+        Some((x?, y?))
+    }
+
+    fn parse_coordinate(&mut self, token: Token, size: usize) -> Option<u8> {
+        match token.text.parse::<u8>() {
+            Ok(value) if (value as usize) < size => Some(value),
+            Ok(value) => {
+                self.error(token.column, format!("coordinate {} is outside of the yard, expected a number from 0 to {}", value, size - 1));
+                None
+            }
+            Err(_) => {
+                self.error(token.column, format!("expected a coordinate, found `{}`", token.text));
+                None
+            }
+        }
+    }
+
+    fn parse_color(&mut self, token: Token) -> Option<Color> {
+        let color = convert_string_to_color(token.text).or_else(|| self.rules.find_custom_color(token.text));
+        if color.is_none() {
+            let custom_names: String = self.rules.custom_colors.iter().map(|custom_color| format!(", {}", custom_color.name)).collect();
+            self.error(token.column, format!(
+                "invalid color `{}`, expected one of red, blue, yellow, purple, green, orange, brown{}", token.text, custom_names
+            ));
+        }
+        color
+    }
+
+    fn parse_colors(&mut self, token: Token) -> Option<Vec<Color>> {
+        let colors: Vec<Option<Color>> = token.split(',').into_iter().map(|token| self.parse_color(token)).collect();
+        colors.into_iter().collect()
+    }
+
+    fn parse_dir(&mut self, token: Token) -> Option<u8> {
+        let dir = convert_string_to_dir(token.text);
+        if dir.is_none() {
+            self.error(token.column, format!("invalid direction `{}`, expected one of up, right, down, left", token.text));
+        }
+        dir
+    }
+
+    fn parse_dirs(&mut self, token: Token) -> Option<Vec<u8>> {
+        let dirs: Vec<Option<u8>> = token.split(',').into_iter().map(|token| self.parse_dir(token)).collect();
+        dirs.into_iter().collect()
+    }
+}
+
+pub fn parse_levels(text: &str) -> Result<LevelManager, LevelParseError> {
+    let mut parser = Parser { problems: vec![], line_num: 0, rules: ColorRules::default(), declared_rules: vec![] };
+    let cities = parser.parse(text);
+    if parser.problems.is_empty() {
+        Ok(LevelManager {
+            cities,
+            unknown_progress: BTreeMap::new(),
+            save_path: PathBuf::new(),
+            load_warning: None,
+            last_unsaved_change: None,
+            backed_up: false,
+            save_disabled: false,
+            color_rules: parser.rules,
+        })
+    } else {
+        Err(LevelParseError { problems: parser.problems })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(text: &str) -> Vec<ParseProblem> {
+        match parse_levels(text) {
+            Ok(_) => vec![],
+            Err(err) => err.problems,
+        }
+    }
+
+    fn positions(problems: &[ParseProblem]) -> Vec<(usize, usize)> {
+        problems.iter().map(|problem| (problem.line, problem.column)).collect()
+    }
+
+    #[test]
+    fn valid_pack_is_parsed() {
+        let text = "CITY:Abbotsford\n\n// a comment\nRed Line:1\n+ 1,3 red right\no 5,3 red left\n---\n\nTwo:2\n* 0,0\n---\n----\n";
+        let level_manager = parse_levels(text).unwrap();
+        assert_eq!(level_manager.get_city_names(), ["Abbotsford"]);
+        assert_eq!(level_manager.get_names_in_city("Abbotsford"), ["Red Line", "Two"]);
+
+        let level = level_manager.get_level("Red Line");
+        assert_eq!((level.num_stars, level.num_rows, level.num_cols), (1, DEFAULT_NUM_ROWS, DEFAULT_NUM_COLS));
+        assert_eq!(level.id, "red-line");
+        assert!(level.par.is_empty());
+        let positions: Vec<(u8, u8)> = level.level_info.iter().map(|tile| (tile.x, tile.y)).collect();
+        assert_eq!(positions, [(1, 3), (5, 3)]);
+        let Tile::Trainsource(trainsource) = &level.level_info[0].tile else {
+            panic!("expected a trainsource");
+        };
+        assert_eq!((trainsource.trains.iter().flatten().copied().collect::<Vec<_>>(), trainsource.dir), (vec![Red], 1));
+        assert!(matches!(level.level_info[1].tile, Tile::Trainsink(_)));
+        assert_eq!(level_manager.color_rules(), &ColorRules::default());
+    }
+
+    #[test]
+    fn shipped_levels_are_parsed() {
+        let level_manager = parse_levels(include_str!("../../assets/levels.txt")).unwrap();
+        assert_eq!(level_manager.get_level("Red Line").level_info.len(), 2);
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let text = "CITY:Abbotsford\n\nRed Line:one\n+ 1,3 red rigth\no 9,3 red left\n---\n\nFine:1\n* 0,0\n---\n\nBroken:1\n+ 1,3 reed right\n";
+        let problems = problems(text);
+        assert_eq!(positions(&problems), [(3, 10), (4, 11), (5, 3), (13, 7), (14, 1)]);
+        assert!(problems[0].message.contains("number of stars"), "{}", problems[0]);
+        assert!(problems[4].message.contains("unexpected end of file"), "{}", problems[4]);
+    }
+
+    #[test]
+    fn columns_are_byte_offsets() {
+        // `é` and `É` take two bytes each, so what comes after them is further than its number of characters.
+        let text = "color rosé 1,2\n\nCITY:Québec\n\nÉté:one\n---\n----\n";
+        let problems = problems(text);
+        assert_eq!(positions(&problems), [(1, 13), (5, 7)]);
+        assert_eq!(&"color rosé 1,2"[12..], "1,2");
+        assert_eq!(&"Été:one"[6..], "one");
+    }
+
+    #[test]
+    fn size_header_is_parsed() {
+        let text = "CITY:Abbotsford\n\nBig:3:10x12\n* 9,11\n---\n\nTall:1:3x12\n* 3,0\n---\n\nWrong:1:10\n---\n----\n";
+        let problems = problems(text);
+        assert_eq!(positions(&problems), [(8, 3), (11, 9)]);
+
+        let level_manager = parse_levels("CITY:Abbotsford\n\nBig:3:10x12\n* 9,11\n---\n----\n").unwrap();
+        let level = level_manager.get_level("Big");
+        assert_eq!((level.num_stars, level.num_cols, level.num_rows), (3, 10, 12));
+    }
+
+    #[test]
+    fn par_and_id_lines_are_parsed() {
+        let text = "CITY:Abbotsford\n\nRed Line:1\nid the-red-line\npar tracks=3 ticks=6\n---\n----\n";
+        let level_manager = parse_levels(text).unwrap();
+        let level = level_manager.get_level("Red Line");
+        assert_eq!(level.par, Par { tracks: Some(3), switches: None, ticks: Some(6) });
+        assert_eq!(level.id, "the-red-line");
+
+        let text = "CITY:Abbotsford\n\nRed Line:1\npar tracks=3 tracks=4 turns=2 ticks\npar switches=0\nid Red\nid red\n---\n----\n";
+        assert_eq!(positions(&problems(text)), [(4, 14), (4, 23), (4, 31), (5, 1), (6, 4), (7, 1)]);
+    }
+
+    #[test]
+    fn rule_lines_are_parsed() {
+        let text = "color pink 240,130,200\nmix red+blue pink\nmix-fallback pink\nmix-many pairwise\nsplit pink red,blue\n\nCITY:Abbotsford\n\nPink:1\no 1,3 pink left\n---\n----\n";
+        let level_manager = parse_levels(text).unwrap();
+        let rules = level_manager.color_rules();
+        let pink = rules.find_custom_color("pink").unwrap();
+        assert_eq!(rules.custom_colors, [CustomColor { name: "pink".to_owned(), rgb: [240, 130, 200] }]);
+        // the first mix replaces the classic mixes
+        assert_eq!(rules.mixes, [([Red, Blue], pink)]);
+        assert_eq!(rules.mix(Red, Yellow), pink);
+        assert_eq!(rules.many_train_mix, ManyTrainMix::Pairwise);
+        assert_eq!(rules.split(pink), [Red, Blue]);
+        assert_eq!(rules.split(Purple), [Purple, Purple]);
+
+        let text = "color red 1,2,3\ncolor pink 1,2\nmix red+red blue\nmix-many all\nCITY:Abbotsford\n\n----\nmix red+blue green\n";
+        assert_eq!(positions(&problems(text)), [(1, 7), (2, 12), (3, 5), (4, 10), (8, 1)]);
+    }
+}
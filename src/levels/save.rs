@@ -0,0 +1,360 @@
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::levels::{level_id_from_name, LevelProgress};
+use crate::score::Score;
+
+// The save file only holds the progress of the player. Level definitions always come from the level files,
+// so fixed and new levels reach players who already have a save. Progress is keyed by level id, which is
+// unique in a level pack (trainyard-lint checks it) and stays the same when a level is renamed.
+//
+// Versions:
+//   1: the whole LevelManager, level definitions included, as a list of cities. It has no version field.
+//   2: {"version": 2, "levels": {<level name>: LevelSave}}
+//   3: {"version": 3, "levels": {<level id>: LevelSave}}
+// Older saves are migrated forward one version at a time when they are loaded.
+// Newer saves can't be read, so they are left alone and nothing is saved until the game is closed.
+pub const SAVE_VERSION: u32 = 3;
+// Saves are written to a temporary file that is then renamed over the save, so the save is never half written.
+// The save is backed up once per session, before it is first overwritten, so <save>.bak1 (the newest) to
+// <save>.bak3 hold the progress from the start of the last three sessions. They are used if the save can't be read.
+const NUM_BACKUPS: usize = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LevelSave {
+    #[serde(default)]
+    pub current_progress: LevelProgress,
+    #[serde(default)]
+    pub hints_used: u32,
+    #[serde(default)]
+    pub best_score: Option<Score>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SaveFile {
+    pub version: u32,
+    pub levels: BTreeMap<String, LevelSave>,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Json(err) => write!(f, "the save file is not valid JSON: {err}"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f, "the save file has version {version}, but this game only reads versions up to {SAVE_VERSION}"
+            ),
+            SaveError::InvalidFormat(message) => write!(f, "the save file is invalid: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> Self {
+        SaveError::Json(err)
+    }
+}
+
+impl Default for SaveFile {
+    fn default() -> Self {
+        SaveFile { version: SAVE_VERSION, levels: BTreeMap::new() }
+    }
+}
+
+impl SaveFile {
+    pub fn from_json(text: &str) -> Result<SaveFile, SaveError> {
+        let mut value: Value = serde_json::from_str(text)?;
+        loop {
+            value = match get_version(&value)? {
+                1 => migrate_v1(value)?,
+                2 => migrate_v2(value)?,
+                SAVE_VERSION => return Ok(serde_json::from_value(value)?),
+                version => return Err(SaveError::UnsupportedVersion(version)),
+            };
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    path_with_suffix(path, &format!(".bak{index}"))
+}
+
+pub fn write_atomically(path: &Path, contents: &str) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    let tmp_path = path_with_suffix(path, ".tmp");
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    // the rename is only durable once the directory is synced too. Windows can't open a directory as a file.
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+pub fn back_up(path: &Path) -> std::io::Result<()> {
+    // copies the save at path to <save>.bak1, after moving the older backups one step back.
+    // Does nothing if there is no save yet.
+    if !path.exists() {
+        return Ok(());
+    }
+    for index in (1..NUM_BACKUPS).rev() {
+        if backup_path(path, index).exists() {
+            std::fs::rename(backup_path(path, index), backup_path(path, index + 1))?;
+        }
+    }
+    std::fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<(SaveFile, Option<String>), SaveError> {
+    // returns the progress saved at path, along with a warning for the player if it had to come from a backup.
+    // Without any readable save, the player starts over. The only error is a save written by a newer version
+    // of the game: it isn't damaged, so it is left as it is, and it must not be overwritten.
+    let err = match std::fs::read_to_string(path) {
+        Ok(contents) => match SaveFile::from_json(&contents) {
+            Ok(save_file) => return Ok((save_file, None)),
+            Err(err @ SaveError::UnsupportedVersion(_)) => return Err(err),
+            Err(err) => err.to_string(),
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !backup_path(path, 1).exists() => {
+            println!("Unable to find previous save file");
+            return Ok((SaveFile::default(), None));
+        }
+        Err(err) => err.to_string(),
+    };
+    eprintln!("Unable to load the save file {}: {err}", path.display());
+
+    // keep the broken save for later inspection, and so that the next save doesn't push a backup out.
+    let corrupt_path = path_with_suffix(path, ".corrupt");
+    if path.exists() {
+        match std::fs::rename(path, &corrupt_path) {
+            Ok(()) => eprintln!("Moved it to {}", corrupt_path.display()),
+            Err(err) => eprintln!("Unable to move it to {}: {err}", corrupt_path.display()),
+        }
+    }
+    for index in 1..=NUM_BACKUPS {
+        let backup = backup_path(path, index);
+        let Ok(contents) = std::fs::read_to_string(&backup) else {
+            continue;
+        };
+        match SaveFile::from_json(&contents) {
+            Ok(save_file) => {
+                eprintln!("Loaded the backup {} instead", backup.display());
+                return Ok((save_file, Some("Save damaged, restored a backup".to_owned())));
+            }
+            Err(err) => eprintln!("Unable to load the backup {}: {err}", backup.display()),
+        }
+    }
+    Ok((SaveFile::default(), Some("Save damaged, no backup could be read".to_owned())))
+}
+
+fn get_version(value: &Value) -> Result<u32, SaveError> {
+    match value {
+        Value::Array(_) => Ok(1),
+        Value::Object(object) => object.get("version")
+            .and_then(Value::as_u64)
+            .map(|version| version as u32)
+            .ok_or_else(|| SaveError::InvalidFormat("missing version".to_owned())),
+        _ => Err(SaveError::InvalidFormat("expected an object".to_owned())),
+    }
+}
+
+fn migrate_v1(value: Value) -> Result<Value, SaveError> {
+    // keeps the progress of every level and drops the level definitions.
+    let invalid = |message: &str| SaveError::InvalidFormat(format!("version 1: {message}"));
+    let cities = value.as_array().ok_or_else(|| invalid("expected a list of cities"))?;
+    let mut levels = serde_json::Map::new();
+    for city in cities {
+        let city_levels = city.get("levels").and_then(Value::as_array).ok_or_else(|| invalid("expected a list of levels"))?;
+        for level in city_levels {
+            let name = level.get("name").and_then(Value::as_str).ok_or_else(|| invalid("expected a level name"))?;
+            let mut level_save = serde_json::Map::new();
+            for key in ["current_progress", "hints_used", "best_score"] {
+                if let Some(field) = level.get(key) {
+                    level_save.insert(key.to_owned(), field.clone());
+                }
+            }
+            levels.insert(name.to_owned(), Value::Object(level_save));
+        }
+    }
+    Ok(json!({"version": 2, "levels": levels}))
+}
+
+fn migrate_v2(value: Value) -> Result<Value, SaveError> {
+    // keys the progress by level id. When ids were added, every level got the id made from its name,
+    // so that is the id of the level the progress belongs to.
+    let invalid = |message: &str| SaveError::InvalidFormat(format!("version 2: {message}"));
+    let levels = value.get("levels").and_then(Value::as_object).ok_or_else(|| invalid("expected the levels"))?;
+    let levels: serde_json::Map<String, Value> = levels.iter()
+        .map(|(name, level_save)| (level_id_from_name(name), level_save.clone()))
+        .collect();
+    Ok(json!({"version": 3, "levels": levels}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        // an empty directory of its own for each test, since the tests run in parallel.
+        let dir = std::env::temp_dir().join(format!("trainyard-save-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn save_with_level(level_name: &str) -> String {
+        let mut save_file = SaveFile::default();
+        save_file.levels.insert(level_name.to_owned(), LevelSave::default());
+        save_file.to_json()
+    }
+
+    fn level_names(save_file: &SaveFile) -> Vec<&str> {
+        save_file.levels.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn version_1_saves_are_migrated() {
+        let v1 = r#"[{"name": "Abbotsford", "levels": [
+            {"name": "Red Line", "num_stars": 1, "level_info": [], "current_progress": [[], true], "hints_used": 2},
+            {"name": "Grorange lines", "num_stars": 1, "level_info": [], "current_progress": [[], false]}
+        ]}]"#;
+        let save_file = SaveFile::from_json(v1).unwrap();
+        assert_eq!(save_file.version, SAVE_VERSION);
+        assert_eq!(level_names(&save_file), ["grorange-lines", "red-line"]);
+        assert!(save_file.levels["red-line"].current_progress.1);
+        assert_eq!(save_file.levels["red-line"].hints_used, 2);
+        assert_eq!(save_file.levels["grorange-lines"].hints_used, 0);
+    }
+
+    #[test]
+    fn version_2_saves_are_keyed_by_level_id() {
+        let v2 = r#"{"version": 2, "levels": {"Red Line": {"hints_used": 1}, "U-Turn": {}}}"#;
+        let save_file = SaveFile::from_json(v2).unwrap();
+        assert_eq!(level_names(&save_file), ["red-line", "u-turn"]);
+        assert_eq!(save_file.levels["red-line"].hints_used, 1);
+    }
+
+    #[test]
+    fn newer_saves_are_refused() {
+        let newer = format!(r#"{{"version": {}, "levels": {{}}}}"#, SAVE_VERSION + 1);
+        assert!(matches!(
+            SaveFile::from_json(&newer),
+            Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+        assert!(matches!(SaveFile::from_json(r#"{"levels": {}}"#), Err(SaveError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn saves_only_rotate_backups_when_asked() {
+        let dir = test_dir("rotate");
+        let path = dir.join("progress.json");
+        back_up(&path).unwrap();
+        for level_name in ["Red Line", "Grorange lines", "Yorple lines"] {
+            write_atomically(&path, &save_with_level(level_name)).unwrap();
+        }
+        assert!(!backup_path(&path, 1).exists());
+
+        for _ in 0..NUM_BACKUPS + 1 {
+            back_up(&path).unwrap();
+        }
+        assert!(backup_path(&path, NUM_BACKUPS).exists());
+        assert!(!backup_path(&path, NUM_BACKUPS + 1).exists());
+        assert!(!path_with_suffix(&path, ".tmp").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_save_falls_back_to_the_newest_readable_backup() {
+        let dir = test_dir("corrupt");
+        let path = dir.join("progress.json");
+        std::fs::write(&path, "{\"version\": 2, \"lev").unwrap();
+        std::fs::write(backup_path(&path, 1), "not json").unwrap();
+        std::fs::write(backup_path(&path, 2), save_with_level("Red Line")).unwrap();
+        std::fs::write(backup_path(&path, 3), save_with_level("Grorange lines")).unwrap();
+
+        let (save_file, warning) = load(&path).unwrap();
+        assert_eq!(level_names(&save_file), ["Red Line"]);
+        assert!(warning.is_some());
+        // the broken save is kept aside, and the backups are left alone
+        assert!(!path.exists());
+        assert!(path_with_suffix(&path, ".corrupt").exists());
+        assert!(backup_path(&path, 1).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_save_falls_back_to_a_backup() {
+        let dir = test_dir("missing");
+        let path = dir.join("progress.json");
+        std::fs::write(backup_path(&path, 1), save_with_level("Red Line")).unwrap();
+
+        let (save_file, warning) = load(&path).unwrap();
+        assert_eq!(level_names(&save_file), ["Red Line"]);
+        assert!(warning.is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_saves_are_left_alone() {
+        let dir = test_dir("newer");
+        let path = dir.join("progress.json");
+        let newer = format!(r#"{{"version": {}, "levels": {{}}}}"#, SAVE_VERSION + 1);
+        std::fs::write(&path, &newer).unwrap();
+        std::fs::write(backup_path(&path, 1), save_with_level("Red Line")).unwrap();
+
+        assert!(matches!(load(&path), Err(SaveError::UnsupportedVersion(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+        assert!(!path_with_suffix(&path, ".corrupt").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_saves_start_over() {
+        let dir = test_dir("unreadable");
+        let path = dir.join("progress.json");
+        let (save_file, warning) = load(&path).unwrap();
+        assert!(save_file.levels.is_empty());
+        assert!(warning.is_none());
+
+        std::fs::write(&path, "[").unwrap();
+        std::fs::write(backup_path(&path, 1), "[").unwrap();
+        let (save_file, warning) = load(&path).unwrap();
+        assert!(save_file.levels.is_empty());
+        assert!(warning.is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
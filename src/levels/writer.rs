@@ -0,0 +1,195 @@
+use crate::color::rules::{ColorRules, ManyTrainMix};
+use crate::color::Color;
+use crate::levels::{Level, PositionedTile};
+use crate::score::Par;
+use crate::tile::Tile;
+use crate::yard::{DEFAULT_NUM_COLS, DEFAULT_NUM_ROWS};
+
+// Turns levels back into the text format of levels.txt, so that levels made in the editor can be
+// pasted into assets/levels.txt or saved in a user level file. Anything written here can be read by the parser.
+
+// the colors declared by a pack are named after the rules of the file being written.
+pub fn color_name(rules: &ColorRules, color: Color) -> String {
+    match color {
+        Color::Red => "red".to_owned(),
+        Color::Blue => "blue".to_owned(),
+        Color::Yellow => "yellow".to_owned(),
+        Color::Purple => "purple".to_owned(),
+        Color::Green => "green".to_owned(),
+        Color::Orange => "orange".to_owned(),
+        Color::Brown => "brown".to_owned(),
+        Color::Custom(index) => match rules.custom_color(index) {
+            Some(custom_color) => custom_color.name.clone(),
+            None => format!("undeclared-color-{index}"),
+        },
+    }
+}
+
+pub fn convert_dir_to_string(dir: u8) -> &'static str {
+    match dir {
+        0 => "up",
+        1 => "right",
+        2 => "down",
+        3 => "left",
+        _ => unreachable!(),
+    }
+}
+
+fn colors_to_string<'a>(rules: &ColorRules, colors: impl Iterator<Item = &'a Option<Color>>) -> String {
+    colors.flatten().map(|color| color_name(rules, *color)).collect::<Vec<_>>().join(",")
+}
+
+fn tile_to_line(rules: &ColorRules, tile: &PositionedTile) -> Option<String> {
+    let position = format!("{},{}", tile.x, tile.y);
+    match &tile.tile {
+        Tile::Trainsource(trainsource) => Some(format!(
+            "+ {} {} {}",
+            position,
+            colors_to_string(rules, trainsource.trains.iter()),
+            convert_dir_to_string(trainsource.dir),
+        )),
+        Tile::Trainsink(trainsink) => {
+            let dirs: Vec<&str> = (0..4)
+                .filter(|dir| trainsink.border_state[*dir as usize])
+                .map(convert_dir_to_string)
+                .collect();
+            Some(format!("o {} {} {}", position, colors_to_string(rules, trainsink.desires.iter()), dirs.join(",")))
+        }
+        Tile::Painter(painter) => Some(format!(
+            "p {} {} {},{}",
+            position,
+            color_name(rules, painter.color),
+            convert_dir_to_string(painter.connection.dir1),
+            convert_dir_to_string(painter.connection.dir2),
+        )),
+        Tile::Filter(filter) => Some(format!(
+            "f {} {} {},{}",
+            position,
+            color_name(rules, filter.color),
+            convert_dir_to_string(filter.connection.dir1),
+            convert_dir_to_string(filter.connection.dir2),
+        )),
+        Tile::Splitter(splitter) => Some(format!("s {} {}", position, convert_dir_to_string(splitter.incoming_dir))),
+        Tile::OneWay(oneway) => Some(format!(
+            "> {} {},{}",
+            position,
+            convert_dir_to_string(oneway.connection.from),
+            convert_dir_to_string(oneway.connection.to),
+        )),
+        Tile::Turntable(turntable) => {
+            let exits: Vec<&str> = turntable.exits.iter().map(|dir| convert_dir_to_string(*dir)).collect();
+            Some(format!("r {} {}", position, exits.join(",")))
+        }
+        Tile::Station(station) => Some(format!(
+            "d {} {} {},{}",
+            position,
+            station.delay,
+            convert_dir_to_string(station.entry_dir),
+            convert_dir_to_string(station.exit_dir),
+        )),
+        Tile::Bridge(bridge) => Some(format!("b {} {}", position, convert_dir_to_string(bridge.upper_dir))),
+        Tile::Tunnel(tunnel) => Some(format!("t {} {} {}", position, tunnel.id, convert_dir_to_string(tunnel.dir))),
+        Tile::Rock(_) => Some(format!("* {position}")),
+        // tracktiles are drawn by the player, they are never part of a level.
+        Tile::Tracktile(_) => None,
+    }
+}
+
+pub fn rules_to_string(rules: &ColorRules) -> String {
+    // only what differs from the classic rules is written, so a pack that keeps them writes nothing.
+    let classic = ColorRules::default();
+    let mut lines = vec![];
+    for custom_color in &rules.custom_colors {
+        let [r, g, b] = custom_color.rgb;
+        lines.push(format!("color {} {r},{g},{b}", custom_color.name));
+    }
+    if rules.mixes != classic.mixes {
+        for ([a, b], result) in &rules.mixes {
+            lines.push(format!("mix {}+{} {}", color_name(rules, *a), color_name(rules, *b), color_name(rules, *result)));
+        }
+    }
+    if rules.fallback != classic.fallback {
+        lines.push(format!("mix-fallback {}", color_name(rules, rules.fallback)));
+    }
+    if rules.many_train_mix != classic.many_train_mix {
+        lines.push(format!("mix-many {}", convert_many_train_mix_to_string(rules.many_train_mix)));
+    }
+    if rules.splits != classic.splits {
+        for (color, [left, right]) in &rules.splits {
+            lines.push(format!("split {} {},{}", color_name(rules, *color), color_name(rules, *left), color_name(rules, *right)));
+        }
+    }
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+pub fn convert_many_train_mix_to_string(many_train_mix: ManyTrainMix) -> &'static str {
+    match many_train_mix {
+        ManyTrainMix::Same => "same",
+        ManyTrainMix::Pairwise => "pairwise",
+    }
+}
+
+fn par_to_line(par: &Par) -> String {
+    let fields = [("tracks", par.tracks), ("switches", par.switches), ("ticks", par.ticks)];
+    let mut line = "par".to_owned();
+    for (name, value) in fields {
+        if let Some(value) = value {
+            line += &format!(" {name}={value}");
+        }
+    }
+    line
+}
+
+pub fn level_to_string(rules: &ColorRules, level: &Level) -> String {
+    let mut lines = vec![];
+    if (level.num_rows, level.num_cols) == (DEFAULT_NUM_ROWS, DEFAULT_NUM_COLS) {
+        lines.push(format!("{}:{}", level.name, level.num_stars));
+    } else {
+        lines.push(format!("{}:{}:{}x{}", level.name, level.num_stars, level.num_cols, level.num_rows));
+    }
+    lines.push(format!("id {}", level.id));
+    if !level.par.is_empty() {
+        lines.push(par_to_line(&level.par));
+    }
+    lines.extend(level.level_info.iter().filter_map(|tile| tile_to_line(rules, tile)));
+    lines.push("---".to_owned());
+    lines.join("\n") + "\n"
+}
+
+pub fn city_to_string(city_name: &str, levels: &[Level], rules: &ColorRules) -> String {
+    let mut text = format!("CITY:{city_name}\n\n");
+    for level in levels {
+        text += &level_to_string(rules, level);
+        text += "\n";
+    }
+    text + "----\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::levels::LevelManager;
+
+    #[test]
+    fn written_levels_parse_back_the_same() {
+        // every kind of tile that a level can have, and a color declared by the pack.
+        let text = "color pink 240,130,200\n\nCITY:Abbotsford\n\n\
+            Everything:3:8x6\nid every-tile\npar tracks=9 ticks=20\n\
+            + 0,0 red,pink right\no 7,0 pink,red left,down\np 1,1 pink up,left\ns 2,1 down\n\
+            f 3,1 pink left,right\nb 4,1 right\nd 5,1 3 left,up\nr 6,1 up,right,right\n\
+            > 1,2 down,up\nt 2,3 0 left\nt 5,3 0 right\n* 0,5\n---\n----\n";
+        let level_manager = LevelManager::parse(text).unwrap();
+        let written = level_manager.to_levels_text();
+        let parsed = LevelManager::parse(&written).unwrap_or_else(|err| panic!("{err}\n{written}"));
+
+        let (before, after) = (level_manager.get_level("Everything"), parsed.get_level("Everything"));
+        assert_eq!(after.level_info.len(), 12);
+        assert_eq!(serde_json::to_value(&after.level_info).unwrap(), serde_json::to_value(&before.level_info).unwrap());
+        assert_eq!((after.num_cols, after.num_rows), (8, 6));
+        assert_eq!(after.num_stars, 3);
+        assert_eq!(after.par, before.par);
+        assert_eq!(after.id, "every-tile");
+        assert_eq!(parsed.color_rules(), level_manager.color_rules());
+        // writing it again gives the same text
+        assert_eq!(parsed.to_levels_text(), written);
+    }
+}
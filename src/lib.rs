@@ -0,0 +1,34 @@
+// The game is split into a library and the binaries using it: the game itself in main.rs, and tools
+// like trainyard-lint in src/bin.
+pub mod color;
+pub mod connection;
+pub mod edge;
+pub mod editor;
+pub mod event;
+pub mod gameplay;
+pub mod history;
+pub mod levels;
+pub mod simulation;
+pub mod solver;
+pub mod sprites;
+pub mod tile;
+pub mod yard;
+pub mod utils;
+pub mod particle;
+pub mod profiles;
+pub mod replay;
+pub mod replay_viewer;
+pub mod score;
+pub mod settings;
+pub mod gui;
+
+#[derive(PartialEq, Clone)]
+pub enum GameState {
+    Menu,
+    Level(String), // The string represents the level name
+    Editor,
+    EditorTest, // playing the level of the editor, going back to the editor when done
+    Replay, // watching a replay, going back to where it was opened from when done
+    Profiles,
+    Settings,
+}
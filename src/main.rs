@@ -1,26 +1,18 @@
-pub mod color;
-pub mod connection;
-pub mod edge;
-pub mod gameplay;
-pub mod levels;
-pub mod sprites;
-pub mod tile;
-pub mod yard;
-pub mod utils;
-pub mod particle;
-pub mod gui;
-use crate::gameplay::Gameplay;
-use crate::levels::LevelManager;
-use crate::sprites::GameSprites;
-use crate::gui::list::List;
+use trainyard_rust::color;
+use trainyard_rust::editor::Editor;
+use trainyard_rust::gameplay::Gameplay;
+use trainyard_rust::levels::LevelManager;
+use trainyard_rust::replay::Replay;
+use trainyard_rust::replay_viewer::ReplayViewer;
+use trainyard_rust::sprites::GameSprites;
+use trainyard_rust::gui::list::List;
+use trainyard_rust::gui::profile_menu::ProfileMenu;
+use trainyard_rust::gui::settings_menu::SettingsMenu;
+use trainyard_rust::profiles::Profiles;
+use trainyard_rust::settings::Settings;
+use trainyard_rust::GameState;
 use macroquad::prelude::*;
 
-#[derive(PartialEq)]
-pub enum GameState {
-    Menu,
-    Level(String), // The string represents the level name
-}
-
 fn window_conf() -> Conf {
     Conf {
         window_title: "Trainyard".to_owned(),
@@ -34,21 +26,49 @@ const MARGIN: f32 = 10.;
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    let level_manager = LevelManager::new();
+    let args: Vec<String> = std::env::args().collect();
+    // `trainyard --data-dir <dir>` saves everything in dir instead of the usual place, see profiles.rs
+    let mut profiles = Profiles::new(arg_value(&args, "--data-dir").map(std::path::PathBuf::from));
+    let level_manager = load_level_manager(&profiles);
+    // the colors declared by the levels are drawn with their own tint
+    color::set_palette(level_manager.color_rules().custom_colors.clone());
+    let mut settings = Settings::load(&profiles.settings_path());
 
     let mut gs = GameSprites::new().await;
+    settings.apply(&mut gs);
+    // the window is only closed at the end of main, once the progress is saved
+    prevent_quit();
 
     let rect = find_yard_rect(screen_height(), screen_width());
    
-    let mut gameplay = Gameplay::new(rect, &level_manager, &gs);
+    let mut gameplay = Gameplay::new(rect, &level_manager, profiles.replay_dir(), &gs);
+    gameplay.apply_settings(&settings);
+    let mut editor = Editor::new(rect, level_manager.color_rules(), profiles.user_levels_path(), &gs);
+    let mut replay_viewer = ReplayViewer::new(rect, &gs);
 
     let (mut prev_width, mut prev_height) = (screen_height(), screen_width());
 
 
     let mut game_state = GameState::Menu;
 
+    // `trainyard --replay <file>` opens a saved replay right away
+    if let Some(path) = arg_value(&args, "--replay") {
+        let result = Replay::load(std::path::Path::new(path))
+            .map_err(|err| err.to_string())
+            .and_then(|replay| replay_viewer.load(replay, GameState::Menu, &gs).map_err(|err| format!("the replay can't be played: {err}")));
+        match result {
+            Ok(()) => game_state = GameState::Replay,
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+    }
+
     
-    let mut list = List::new(MARGIN, MARGIN, screen_height() - MARGIN, level_manager);
+    let mut list = List::new(MARGIN, MARGIN, screen_height() - MARGIN, level_manager, profiles.current());
+    let mut profile_menu = ProfileMenu::new(MARGIN, MARGIN);
+    let mut settings_menu = SettingsMenu::new(MARGIN, MARGIN);
 
 
 
@@ -63,6 +83,8 @@ async fn main() {
         if prev_height != screen_height() || prev_width != screen_width() {
             let rect = find_yard_rect(screen_height(), screen_width());
             gameplay.set_rect(rect, &gs);
+            editor.set_rect(rect, &gs);
+            replay_viewer.set_rect(rect, &gs);
 
             list.set_max_height(screen_height() - MARGIN);
 
@@ -74,33 +96,134 @@ async fn main() {
             GameState::Menu => {
                 list.update(&mut gs, &mut game_state, &mut gameplay);
                 list.render(&gs);
+                if game_state == GameState::Profiles {
+                    profile_menu.open(&profiles);
+                }
+            },
+            GameState::Profiles => {
+                if let Some(profile_name) = profile_menu.update(&mut gs, &mut game_state) {
+                    list.level_manager.save_progress_to_file();
+                    match profiles.select(&profile_name) {
+                        Ok(()) => {
+                            list = List::new(MARGIN, MARGIN, screen_height() - MARGIN, load_level_manager(&profiles), profiles.current());
+                            settings = Settings::load(&profiles.settings_path());
+                            settings.apply(&mut gs);
+                            gameplay.apply_settings(&settings);
+                            gameplay.set_replay_dir(profiles.replay_dir());
+                            game_state = GameState::Menu;
+                        },
+                        Err(err) => profile_menu.set_message(err),
+                    }
+                }
+                profile_menu.render();
+            },
+            GameState::Settings => {
+                if settings_menu.update(&mut settings, &mut gs, &mut game_state) {
+                    settings.apply(&mut gs);
+                    gameplay.apply_settings(&settings);
+                    if let Err(err) = settings.save(&profiles.settings_path()) {
+                        eprintln!("Unable to save the settings: {err}");
+                    }
+                }
+                settings_menu.render(&settings);
             },
             GameState::Level(ref level_name) => {
-                list.level_manager.set_level_current_progress(&level_name.clone(), &gameplay.get_current_progress());
-                list.update_label(level_name.clone());
+                let level_name = level_name.clone();
+                let level_state = game_state.clone();
                 if gameplay.update(&mut gs, &mut game_state) {
                     break;
                 }
+                if gameplay.take_progress_changed() {
+                    list.level_manager.set_level_current_progress(&level_name, &gameplay.get_current_progress());
+                    list.level_manager.set_level_hints_used(&level_name, gameplay.get_hints_used());
+                    if let Some(score) = gameplay.get_score() {
+                        list.level_manager.set_level_score(&level_name, score);
+                    }
+                    list.update_label(level_name);
+                    list.level_manager.mark_unsaved(get_time());
+                }
 
                 gameplay.render(&gs);
                 if game_state == GameState::Menu {
                     list.level_manager.save_progress_to_file();
                 }
+                if game_state == GameState::Replay {
+                    if let Some(replay) = gameplay.get_last_replay().cloned() {
+                        if let Err(err) = replay_viewer.load(replay, level_state.clone(), &gs) {
+                            eprintln!("Unable to show the replay: {err}");
+                            game_state = level_state;
+                        }
+                    }
+                }
+            },
+            GameState::Editor => {
+                editor.update(&mut gs, &mut game_state, &mut gameplay);
+                editor.render(&gs);
+            },
+            GameState::EditorTest => {
+                if gameplay.update(&mut gs, &mut game_state) {
+                    break;
+                }
+                gameplay.render(&gs);
+                if game_state == GameState::Menu {
+                    game_state = GameState::Editor;
+                }
+                if game_state == GameState::Replay {
+                    if let Some(replay) = gameplay.get_last_replay().cloned() {
+                        if let Err(err) = replay_viewer.load(replay, GameState::EditorTest, &gs) {
+                            eprintln!("Unable to show the replay: {err}");
+                            game_state = GameState::EditorTest;
+                        }
+                    }
+                }
+            },
+            GameState::Replay => {
+                replay_viewer.update(&mut gs, &mut game_state);
+                replay_viewer.render(&gs);
             },
         }
 
 
+        list.level_manager.autosave(get_time());
+        if is_quit_requested() {
+            break;
+        }
         gs.play_sounds();
         next_frame().await;
     }
+    // the window is closing, don't lose the last changes
+    list.level_manager.save_if_unsaved();
 }
 
 
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    // the argument following flag on the command line, exiting if flag is the last argument.
+    let index = args.iter().position(|arg| arg == flag)?;
+    match args.get(index + 1) {
+        Some(value) => Some(value),
+        None => {
+            eprintln!("{flag} needs a value");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load_level_manager(profiles: &Profiles) -> LevelManager {
+    match LevelManager::new(&profiles.progress_path()) {
+        Ok(level_manager) => level_manager,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn find_yard_rect(height: f32, width: f32) -> Rect {
     let margin = MARGIN;
     let (height, width) = (height - 2. * margin, width - 2. * margin);
 
-    let aspect_ratio = 874./672.;
+    // the yard is 672 pixels tall, and the buttons below it take another 264 pixels
+    let aspect_ratio = 936./672.;
 
     if height < width * aspect_ratio {
         Rect::new(margin, margin, height/aspect_ratio, height)
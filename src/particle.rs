@@ -6,7 +6,9 @@ pub mod drawn_arrow;
 pub mod smoke;
 pub mod sparkle;
 pub mod fire;
+pub mod hint_glow;
 
+use serde::{Serialize, Deserialize};
 use crate::sprites::GameSprites;
 
 pub type ParticleList = Vec<Box<dyn Particle>>;
@@ -15,4 +17,22 @@ pub trait Particle {
     fn render(&self, gs: &GameSprites);
     fn pass_one_frame(&mut self);
     fn still_exists(&self) -> bool;
-}
\ No newline at end of file
+}
+
+// how many particles the bursts of fire, smoke and stars are made of, see settings.rs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParticleDensity {
+    Full,
+    Reduced,
+    Off,
+}
+
+impl ParticleDensity {
+    pub fn particle_count(self, full_count: usize) -> usize {
+        match self {
+            ParticleDensity::Full => full_count,
+            ParticleDensity::Reduced => full_count.div_ceil(2),
+            ParticleDensity::Off => 0,
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use macroquad::prelude::*;
 use crate::particle::Particle;
-use crate::GameSprites;
+use crate::sprites::GameSprites;
 use std::f32::consts::PI;
 
 pub static INITIAL_TTL: i32 = 100;
@@ -1,27 +1,24 @@
 use macroquad::prelude::*;
 use crate::particle::Particle;
-use crate::GameSprites;
+use crate::sprites::GameSprites;
 use crate::color::Color;
 use macroquad::rand::gen_range;
 
 pub static INITIAL_TTL: i32 = 130;
 pub static RANGE: f32 = 20.;
 
+pub const NUM_FIRES: usize = 4;
+
 pub struct Fire {
-    fires: [FireParticle;4],
+    fires: Vec<FireParticle>,
     ttl: i32,
 }
 
 impl Fire {
-    pub fn new(x: f32, y:f32, color: Color, scale: f32) -> Fire {
+    pub fn new(x: f32, y:f32, color: Color, scale: f32, num_fires: usize) -> Fire {
         Fire {
-            // We'll make one big fire and three small fires.
-            fires: [
-                FireParticle::new(x, y, color, true, scale),
-                FireParticle::new(x, y, color, false, scale),
-                FireParticle::new(x, y, color, false, scale),
-                FireParticle::new(x, y, color, false, scale),
-            ],
+            // We'll make one big fire, and small fires for the rest.
+            fires: (0..num_fires).map(|index| FireParticle::new(x, y, color, index == 0, scale)).collect(),
             ttl: INITIAL_TTL,
         }
     }
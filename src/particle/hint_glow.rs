@@ -0,0 +1,50 @@
+use macroquad::prelude::*;
+use crate::particle::Particle;
+use crate::sprites::GameSprites;
+
+pub static INITIAL_TTL: i32 = 45;
+pub static NUM_PULSES: i32 = 3;
+static GLOW_COLOR: Color = Color::new(1.0, 0.85, 0.2, 1.0);
+
+// drawn around a tracktile that was revealed by a hint. Gold squares expand outwards from the tile
+// and fade, a few times in a row.
+pub struct HintGlow {
+    bounding_rect: Rect,
+    ttl: i32,
+}
+
+impl HintGlow {
+    pub fn new(rect: Rect) -> HintGlow {
+        HintGlow {bounding_rect: rect, ttl: INITIAL_TTL}
+    }
+}
+
+impl Particle for HintGlow {
+    fn render(&self, _gs: &GameSprites) {
+        let pulse_length = INITIAL_TTL / NUM_PULSES;
+        // goes from 0 to 1 during each pulse
+        let progress = 1.0 - (self.ttl % pulse_length) as f32 / pulse_length as f32;
+
+        let grow = 0.25 * progress * self.bounding_rect.w;
+        let mut color = GLOW_COLOR;
+        color.a = 1.0 - progress;
+
+        draw_rectangle_lines(
+            self.bounding_rect.x - grow / 2.,
+            self.bounding_rect.y - grow / 2.,
+            self.bounding_rect.w + grow,
+            self.bounding_rect.h + grow,
+            0.08 * self.bounding_rect.w,
+            color,
+        );
+    }
+    fn pass_one_frame(&mut self) {
+        self.ttl -= 1;
+        if self.ttl < 0 {
+            panic!("we should have removed this particle once still_exists returns false!")
+        }
+    }
+    fn still_exists(&self) -> bool {
+        self.ttl > 0
+    }
+}
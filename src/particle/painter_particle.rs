@@ -1,7 +1,8 @@
 use macroquad::prelude::*;
 use crate::color::Color;
+use crate::color::glyph::draw_glyph;
 use crate::particle::Particle;
-use crate::GameSprites;
+use crate::sprites::GameSprites;
 
 pub static INITIAL_TTL: i32 = 20;
 pub static NUM_FRAMES: i32 = 10;
@@ -38,7 +39,9 @@ impl Particle for PainterParticle {
                 flip_y: false,
                 pivot: None
             }
-        )
+        );
+        let rect = self.bounding_rect;
+        draw_glyph(self.color, rect.x + rect.w / 2., rect.y + rect.h / 2., rect.w * 0.2, 1.);
     }
     fn pass_one_frame(&mut self) {
         self.ttl -= 1;
@@ -1,7 +1,8 @@
 use macroquad::prelude::*;
 use crate::particle::Particle;
 use crate::color::Color;
-use crate::GameSprites;
+use crate::color::glyph::draw_glyph;
+use crate::sprites::GameSprites;
 
 pub static INITIAL_TTL: i32 = 20;
 
@@ -35,7 +36,8 @@ impl Particle for ShrinkingPlus {
                 flip_y: false,
                 pivot: None
             }
-        )
+        );
+        draw_glyph(self.color, new_x + new_w / 2., new_y + new_h / 2., new_w * 0.35, 1.);
     }
     fn pass_one_frame(&mut self) {
         self.ttl -= 1;
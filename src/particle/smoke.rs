@@ -1,24 +1,22 @@
 use macroquad::prelude::*;
 use crate::particle::Particle;
-use crate::GameSprites;
+use crate::sprites::GameSprites;
 use crate::color::Color;
 use macroquad::rand::gen_range;
 
 pub static INITIAL_TTL: i32 = 170;
 
+pub const NUM_SMOKES: usize = 3;
+
 pub struct Smoke {
-    smokes: [SmokeParticle;3],
+    smokes: Vec<SmokeParticle>,
     ttl: i32,
 }
 
 impl Smoke {
-    pub fn new(x: f32, y:f32, color: Color, scale: f32) -> Smoke {
+    pub fn new(x: f32, y:f32, color: Color, scale: f32, num_smokes: usize) -> Smoke {
         Smoke {
-            smokes: [
-                SmokeParticle::new(x, y, color, scale),
-                SmokeParticle::new(x, y, color, scale),
-                SmokeParticle::new(x, y, color, scale),
-            ],
+            smokes: (0..num_smokes).map(|_| SmokeParticle::new(x, y, color, scale)).collect(),
             ttl: INITIAL_TTL,
         }
     }
@@ -1,25 +1,23 @@
 use macroquad::prelude::*;
 use macroquad::rand::gen_range;
 use crate::particle::Particle;
-use crate::GameSprites;
+use crate::sprites::GameSprites;
 use crate::color::Color;
 
 pub static INITIAL_TTL: i32 = 50;
 pub static RANGE: f32 = 30.;
 
+pub const NUM_STARS: usize = 3;
+
 pub struct Sparkle {
-    stars: [Star;3],
+    stars: Vec<Star>,
     ttl: i32,
 }
 
 impl Sparkle {
-    pub fn new(x: f32, y:f32, color: Color, scale: f32) -> Sparkle {
+    pub fn new(x: f32, y:f32, color: Color, scale: f32, num_stars: usize) -> Sparkle {
         Sparkle {
-            stars: [
-                Star::new(x, y, color, scale),
-                Star::new(x, y, color, scale),
-                Star::new(x, y, color, scale),
-            ],
+            stars: (0..num_stars).map(|_| Star::new(x, y, color, scale)).collect(),
             ttl: INITIAL_TTL,
         }
     }
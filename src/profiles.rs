@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+
+// Everything the game saves for a player lives in the data directory, with one directory per profile:
+//   <data dir>/profiles/<profile name>/progress.json
+//   <data dir>/profiles/<profile name>/settings.json
+//   <data dir>/profiles/<profile name>/replays/, the replays saved by the player
+//   <data dir>/last_profile.txt, the profile that was selected the last time the game ran
+//   <data dir>/user_levels.txt, the levels saved by the editor, shared by all profiles
+// The data directory is the --data-dir command line argument if given, then the TRAINYARD_DATA_DIR
+// environment variable, and otherwise the usual place for user data on the platform.
+pub const DATA_DIR_ENV: &str = "TRAINYARD_DATA_DIR";
+pub const DEFAULT_PROFILE: &str = "Player";
+const PROFILES_DIR: &str = "profiles";
+const LAST_PROFILE_FILENAME: &str = "last_profile.txt";
+const PROGRESS_FILENAME: &str = "progress.json";
+const SETTINGS_FILENAME: &str = "settings.json";
+const REPLAY_DIR: &str = "replays";
+const USER_LEVELS_FILENAME: &str = "user_levels.txt";
+// where the progress was saved before there were profiles, relative to the directory the game was launched from.
+const LEGACY_SAVE_FILENAME: &str = ".trainyard_saved_progress.json";
+pub const MAX_PROFILE_NAME_LEN: usize = 24;
+
+pub struct Profiles {
+    data_dir: PathBuf,
+    current: String,
+}
+
+pub fn default_data_dir() -> PathBuf {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    let dir = if cfg!(target_os = "windows") {
+        env_dir("APPDATA").map(|dir| dir.join("Trainyard"))
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|dir| dir.join("Library/Application Support/Trainyard"))
+    } else {
+        env_dir("XDG_DATA_HOME")
+            .or_else(|| env_dir("HOME").map(|dir| dir.join(".local/share")))
+            .map(|dir| dir.join("trainyard"))
+    };
+    // without a home directory, fall back to the directory the game was launched from.
+    dir.unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn is_valid_profile_name(name: &str) -> bool {
+    // profile names are used as directory names, so only allow characters that are safe everywhere.
+    let name_chars_valid = name.chars().all(|ch| ch.is_alphanumeric() || ch == ' ' || ch == '-' || ch == '_');
+    !name.trim().is_empty() && name.len() <= MAX_PROFILE_NAME_LEN && name_chars_valid
+}
+
+impl Profiles {
+    pub fn new(data_dir: Option<PathBuf>) -> Profiles {
+        let data_dir = data_dir
+            .or_else(|| std::env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()).map(PathBuf::from))
+            .unwrap_or_else(default_data_dir);
+        let current = std::fs::read_to_string(data_dir.join(LAST_PROFILE_FILENAME))
+            .ok()
+            .map(|name| name.trim().to_owned())
+            .filter(|name| is_valid_profile_name(name))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_owned());
+        let profiles = Profiles { data_dir, current };
+        profiles.import_legacy_save();
+        profiles
+    }
+
+    fn import_legacy_save(&self) {
+        // the first time the game runs with profiles, the old save becomes the progress of the default profile.
+        let progress_path = self.progress_path_of(DEFAULT_PROFILE);
+        if progress_path.exists() || !Path::new(LEGACY_SAVE_FILENAME).exists() {
+            return;
+        }
+        let result = std::fs::create_dir_all(self.profile_dir_of(DEFAULT_PROFILE))
+            .and_then(|_| std::fs::copy(LEGACY_SAVE_FILENAME, &progress_path));
+        match result {
+            Ok(_) => println!("Copied {} to {}", LEGACY_SAVE_FILENAME, progress_path.display()),
+            Err(err) => eprintln!("Unable to copy {} to {}: {err}", LEGACY_SAVE_FILENAME, progress_path.display()),
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.current
+    }
+
+    fn profile_dir_of(&self, name: &str) -> PathBuf {
+        self.data_dir.join(PROFILES_DIR).join(name)
+    }
+
+    fn progress_path_of(&self, name: &str) -> PathBuf {
+        self.profile_dir_of(name).join(PROGRESS_FILENAME)
+    }
+
+    pub fn profile_dir(&self) -> PathBuf {
+        // the directory of the current profile, which holds its progress and settings.
+        self.profile_dir_of(&self.current)
+    }
+
+    pub fn progress_path(&self) -> PathBuf {
+        self.progress_path_of(&self.current)
+    }
+
+    pub fn settings_path(&self) -> PathBuf {
+        self.profile_dir().join(SETTINGS_FILENAME)
+    }
+
+    pub fn replay_dir(&self) -> PathBuf {
+        self.profile_dir().join(REPLAY_DIR)
+    }
+
+    pub fn user_levels_path(&self) -> PathBuf {
+        self.data_dir.join(USER_LEVELS_FILENAME)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        // the names of all profiles, sorted. The current profile is included even if nothing was saved for it yet.
+        let mut names: Vec<String> = std::fs::read_dir(self.data_dir.join(PROFILES_DIR))
+            .map(|entries| entries
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| is_valid_profile_name(name))
+                .collect())
+            .unwrap_or_default();
+        if !names.contains(&self.current) {
+            names.push(self.current.clone());
+        }
+        names.sort();
+        names
+    }
+
+    pub fn select(&mut self, name: &str) -> Result<(), String> {
+        // makes name the current profile, creating it if it doesn't exist yet.
+        if !is_valid_profile_name(name) {
+            return Err(format!("`{name}` is not a valid profile name"));
+        }
+        std::fs::create_dir_all(self.profile_dir_of(name)).map_err(|err| err.to_string())?;
+        std::fs::write(self.data_dir.join(LAST_PROFILE_FILENAME), name).map_err(|err| err.to_string())?;
+        self.current = name.to_owned();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everything_is_saved_in_the_data_dir() {
+        let data_dir = std::env::temp_dir().join(format!("trainyard-profiles-test-{}", std::process::id()));
+        let mut profiles = Profiles::new(Some(data_dir.clone()));
+        profiles.select("Alice").unwrap();
+
+        assert_eq!(profiles.progress_path(), data_dir.join("profiles/Alice/progress.json"));
+        assert_eq!(profiles.settings_path(), data_dir.join("profiles/Alice/settings.json"));
+        assert_eq!(profiles.replay_dir(), data_dir.join("profiles/Alice/replays"));
+        assert_eq!(profiles.user_levels_path(), data_dir.join("user_levels.txt"));
+        assert_eq!(Profiles::new(Some(data_dir.clone())).current(), "Alice");
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+}
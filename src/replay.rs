@@ -0,0 +1,322 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::color::rules::ColorRules;
+use crate::levels::{level_id_from_name, Level, LevelInfo};
+use crate::score::Par;
+use crate::simulation::{SimStatus, Simulation, SimulationError};
+use crate::yard::NextAction;
+
+// bump this whenever the format of a Replay changes. Replays with a newer version are refused, older ones
+// are migrated forward one version at a time when they are loaded.
+//   1: stored the whole simulation after every step, in `frames`.
+//   2: only stores the number of steps, the frames are simulated again, see Replay::frames.
+pub const REPLAY_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SpeedChange {
+    // the speed was changed while going from frame `frame` to frame `frame + 1`
+    pub frame: usize,
+    pub speed: f32,
+}
+
+// A replay is a whole run of a level: the level itself along with the color rules of its pack, the layout
+// drawn by the player, the speed the run was watched at, and how many ProcessTick and ProcessEdges actions
+// were played. The simulation always plays a layout the same way, so the frames of the run are found by
+// playing it again. Frame 0 is the yard right before the trains were started, frame n is the yard after n steps.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Replay {
+    pub version: u32,
+    pub level_name: String,
+    pub level_info: LevelInfo,
+    pub num_rows: usize,
+    pub num_cols: usize,
+    pub color_rules: ColorRules,
+    pub layout: LevelInfo,
+    pub initial_speed: f32,
+    pub speed_changes: Vec<SpeedChange>,
+    pub num_steps: usize,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    InvalidFormat(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(err) => write!(f, "could not access the replay file: {err}"),
+            ReplayError::Json(err) => write!(f, "the replay file is invalid: {err}"),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f, "the replay has version {version}, but this game only reads versions up to {REPLAY_VERSION}"
+            ),
+            ReplayError::InvalidFormat(message) => write!(f, "the replay file is invalid: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(err: std::io::Error) -> Self {
+        ReplayError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(err: serde_json::Error) -> Self {
+        ReplayError::Json(err)
+    }
+}
+
+impl Replay {
+    pub fn new(level_name: &str, level_info: &LevelInfo, layout: &LevelInfo, sim: &Simulation, speed: f32) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            level_name: level_name.to_owned(),
+            level_info: level_info.clone(),
+            num_rows: sim.num_rows,
+            num_cols: sim.num_cols,
+            color_rules: sim.color_rules.clone(),
+            layout: layout.clone(),
+            initial_speed: speed,
+            speed_changes: vec![],
+            num_steps: 0,
+        }
+    }
+
+    pub fn record_step(&mut self) {
+        self.num_steps += 1;
+    }
+
+    pub fn remove_last_step(&mut self) {
+        // used when the run is stepped back, so the replay stays the same as what was played.
+        self.num_steps = self.num_steps.saturating_sub(1);
+        let num_steps = self.num_steps;
+        self.speed_changes.retain(|change| change.frame <= num_steps);
+    }
+
+    pub fn record_speed(&mut self, speed: f32) {
+        let frame = self.num_steps;
+        match self.speed_changes.last_mut() {
+            // several changes during the same frame (e.g. while dragging the slider) only keep the last one
+            Some(last) if last.frame == frame => last.speed = speed,
+            _ => self.speed_changes.push(SpeedChange { frame, speed }),
+        }
+    }
+
+    pub fn speed_at(&self, frame: usize) -> f32 {
+        self.speed_changes.iter()
+            .take_while(|change| change.frame <= frame)
+            .last()
+            .map_or(self.initial_speed, |change| change.speed)
+    }
+
+    pub fn frames(&self) -> Result<Vec<Simulation>, SimulationError> {
+        // plays the run again, and returns the simulation at every frame.
+        let mut sim = Simulation::new(&self.level_info, &self.layout, self.num_rows, self.num_cols, &self.color_rules)?;
+        let mut frames = vec![sim.clone()];
+        for frame in 0..self.num_steps {
+            // only a replay edited by hand goes on after the end of the run
+            if sim.status != SimStatus::Running {
+                break;
+            }
+            sim.advance(Replay::next_step_at(frame));
+            frames.push(sim.clone());
+        }
+        Ok(frames)
+    }
+
+    pub fn next_step_at(frame: usize) -> NextAction {
+        // the yard starts with a ProcessTick and then alternates.
+        if frame.is_multiple_of(2) {NextAction::ProcessTick} else {NextAction::ProcessEdges}
+    }
+
+    pub fn to_level(&self) -> Level {
+        Level {
+            level_info: self.level_info.clone(),
+            current_progress: (self.layout.clone(), false),
+            hints_used: 0,
+            id: level_id_from_name(&self.level_name),
+            name: self.level_name.clone(),
+            num_stars: 0,
+            num_rows: self.num_rows,
+            num_cols: self.num_cols,
+            par: Par::default(),
+            best_score: None,
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, ReplayError> {
+        // saves the replay in dir (see Profiles::replay_dir), and returns the path of the new file.
+        std::fs::create_dir_all(dir)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let file_name: String = format!("{}-{}.json", self.level_name, timestamp)
+            .chars()
+            .map(|ch| if ch.is_alphanumeric() || ch == '.' || ch == '-' {ch} else {'_'})
+            .collect();
+        let path = dir.join(file_name);
+        std::fs::write(&path, serde_json::to_string(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let mut value: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        loop {
+            let version = value.get("version").and_then(Value::as_u64).map(|version| version as u32);
+            value = match version {
+                Some(1) => migrate_v1(value)?,
+                Some(REPLAY_VERSION) => return Ok(serde_json::from_value(value)?),
+                Some(version) => return Err(ReplayError::UnsupportedVersion(version)),
+                None => return Err(ReplayError::InvalidFormat("missing version".to_owned())),
+            };
+        }
+    }
+}
+
+fn migrate_v1(mut value: Value) -> Result<Value, ReplayError> {
+    // keeps the number of steps and the color rules of the first frame, and drops the frames.
+    let invalid = |message: &str| ReplayError::InvalidFormat(format!("version 1: {message}"));
+    let object = value.as_object_mut().ok_or_else(|| invalid("expected an object"))?;
+    let frames = object.remove("frames").ok_or_else(|| invalid("missing frames"))?;
+    let frames = frames.as_array().filter(|frames| !frames.is_empty()).ok_or_else(|| invalid("expected a list of frames"))?;
+    // replays recorded before the simulation knew its rules were played with the classic ones
+    let color_rules = match frames[0].get("color_rules") {
+        Some(color_rules) => color_rules.clone(),
+        None => serde_json::to_value(ColorRules::default())?,
+    };
+    object.insert("color_rules".to_owned(), color_rules);
+    object.insert("num_steps".to_owned(), Value::from(frames.len() - 1));
+    object.insert("version".to_owned(), Value::from(2));
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::connection::Connection;
+    use crate::levels::{LevelManager, PositionedTile};
+    use crate::tile::tracktile::Tracktile;
+    use crate::tile::Tile;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("trainyard-replay-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record_red_line() -> (Replay, Vec<Simulation>) {
+        // plays "Red Line" like the game does, returning the replay and the simulation after every step.
+        let level_manager = LevelManager::parse(include_str!("../assets/levels.txt")).unwrap();
+        let level = level_manager.get_level("Red Line");
+        let layout: LevelInfo = (2..5)
+            .map(|x| PositionedTile { tile: Tile::Tracktile(Tracktile::new(Some(Connection { dir1: 1, dir2: 3 }), None)), x, y: 3 })
+            .collect();
+        let mut sim = Simulation::new(
+            &level.level_info, &layout, level.num_rows, level.num_cols, level_manager.color_rules(),
+        ).unwrap();
+        let mut replay = Replay::new(&level.name, &level.level_info, &layout, &sim, 0.1);
+        let mut frames = vec![sim.clone()];
+        while sim.status == SimStatus::Running {
+            if replay.num_steps == 3 {
+                replay.record_speed(0.2);
+            }
+            sim.advance(Replay::next_step_at(replay.num_steps));
+            replay.record_step();
+            frames.push(sim.clone());
+        }
+        (replay, frames)
+    }
+
+    fn to_json(frames: &[Simulation]) -> Value {
+        serde_json::to_value(frames).unwrap()
+    }
+
+    #[test]
+    fn saved_replays_play_the_same_run() {
+        let (replay, frames) = record_red_line();
+        assert_eq!(frames.last().unwrap().status, SimStatus::Won);
+        assert_eq!(to_json(&replay.frames().unwrap()), to_json(&frames));
+
+        let dir = test_dir("round-trip");
+        let path = replay.save(&dir).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        assert_eq!(loaded.level_name, "Red Line");
+        assert_eq!(loaded.num_steps, frames.len() - 1);
+        assert_eq!(loaded.speed_at(0), 0.1);
+        assert_eq!(loaded.speed_at(3), 0.2);
+        assert_eq!(to_json(&loaded.frames().unwrap()), to_json(&frames));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replays_keep_the_color_rules_of_their_pack() {
+        let (mut replay, _) = record_red_line();
+        replay.color_rules.fallback = Color::Green;
+        let dir = test_dir("rules");
+        let loaded = Replay::load(&replay.save(&dir).unwrap()).unwrap();
+        assert_eq!(loaded.color_rules.fallback, Color::Green);
+        assert_eq!(loaded.frames().unwrap()[0].color_rules.fallback, Color::Green);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn version_1_replays_are_migrated() {
+        let (replay, frames) = record_red_line();
+        let mut v1 = serde_json::to_value(&replay).unwrap();
+        let object = v1.as_object_mut().unwrap();
+        object.remove("color_rules");
+        object.remove("num_steps");
+        object.insert("version".to_owned(), Value::from(1));
+        object.insert("frames".to_owned(), to_json(&frames));
+        let dir = test_dir("v1");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("v1.json");
+        std::fs::write(&path, v1.to_string()).unwrap();
+
+        let loaded = Replay::load(&path).unwrap();
+        assert_eq!(loaded.version, REPLAY_VERSION);
+        assert_eq!(to_json(&loaded.frames().unwrap()), to_json(&frames));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_replays_are_refused() {
+        let (replay, _) = record_red_line();
+        let mut newer = serde_json::to_value(&replay).unwrap();
+        newer["version"] = Value::from(REPLAY_VERSION + 1);
+        let dir = test_dir("newer");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("newer.json");
+        std::fs::write(&path, newer.to_string()).unwrap();
+
+        assert!(matches!(
+            Replay::load(&path),
+            Err(ReplayError::UnsupportedVersion(version)) if version == REPLAY_VERSION + 1
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stepping_back_drops_the_later_speed_changes() {
+        let (mut replay, _) = record_red_line();
+        while replay.num_steps > 2 {
+            replay.remove_last_step();
+        }
+        assert_eq!(replay.speed_at(3), 0.1);
+        assert_eq!(replay.frames().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,172 @@
+use macroquad::prelude::*;
+
+use crate::GameState;
+use crate::replay::Replay;
+use crate::simulation::{SimStatus, Simulation, SimulationError};
+use crate::sprites::GameSprites;
+use crate::sprites::SoundType::ButtonPress;
+use crate::utils::{draw_text_button, point_in_rect};
+use crate::yard::{Yard, YardState};
+
+// Shows a Replay frame by frame. The trains move between frames the same way as in the game,
+// and the replay can be paused, stepped and scrubbed in both directions.
+pub struct ReplayViewer {
+    replay: Option<Replay>,
+    // the replay played again when it was loaded, see Replay::frames
+    frames: Vec<Simulation>,
+    yard: Option<Yard>,
+    yard_area: Rect,
+    scrub_rect: Rect,
+    back_rect: Rect,
+    label_rect: Rect,
+    frame: usize,
+    progress: f32,
+    is_playing: bool,
+    return_state: GameState,
+}
+
+impl ReplayViewer {
+    pub fn new(rect: Rect, gs: &GameSprites) -> ReplayViewer {
+        let mut viewer = ReplayViewer {
+            replay: None,
+            frames: vec![],
+            yard: None,
+            yard_area: rect,
+            scrub_rect: rect,
+            back_rect: rect,
+            label_rect: rect,
+            frame: 0,
+            progress: 0.,
+            is_playing: false,
+            return_state: GameState::Menu,
+        };
+        viewer.set_rect(rect, gs);
+        viewer
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.yard_area = Rect::new(rect.x, rect.y, rect.w, rect.w);
+        let (x, y) = (self.yard_area.x, self.yard_area.y + self.yard_area.h);
+        let scale = self.yard_area.w / 672.;
+        self.scrub_rect = Rect::new(x + 10. * scale, y + 10. * scale, 652. * scale, 60. * scale);
+        self.back_rect = Rect::new(x + 10. * scale, y + 200. * scale, 208. * scale, 60. * scale);
+        self.label_rect = Rect::new(x + 10. * scale, y + 80. * scale, 652. * scale, 110. * scale);
+        if let Some(yard) = &mut self.yard {
+            yard.set_rect(self.yard_area, gs);
+        }
+    }
+
+    pub fn load(&mut self, replay: Replay, return_state: GameState, gs: &GameSprites) -> Result<(), SimulationError> {
+        // return_state is the state the game goes back to when leaving the viewer.
+        self.frames = replay.frames()?;
+        self.yard = Some(Yard::new(&replay.to_level(), &replay.color_rules, self.yard_area, gs)?);
+        self.replay = Some(replay);
+        self.return_state = return_state;
+        self.frame = 0;
+        self.progress = 0.;
+        self.is_playing = true;
+        self.show_frame(gs);
+        Ok(())
+    }
+
+    fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn show_frame(&mut self, gs: &GameSprites) {
+        let (Some(sim), Some(yard)) = (self.frames.get(self.frame), &mut self.yard) else {
+            return;
+        };
+        let state = match sim.status {
+            SimStatus::Crashed => YardState::Crashed,
+            SimStatus::Won => YardState::Won,
+            SimStatus::Running => YardState::Playing {
+                num_ticks_elapsed: self.frame as u32 + 1,
+                progress: self.progress,
+                next_step: Replay::next_step_at(self.frame),
+            },
+        };
+        yard.show_sim(sim, state, gs);
+    }
+
+    fn go_to_frame(&mut self, frame: usize) {
+        self.frame = frame.min(self.num_frames().saturating_sub(1));
+        self.progress = 0.;
+    }
+
+    pub fn update(&mut self, gs: &mut GameSprites, game_state: &mut GameState) {
+        let last_frame = self.num_frames().saturating_sub(1);
+        let (x, y) = mouse_position();
+
+        if is_key_pressed(KeyCode::Escape) || (is_mouse_button_pressed(MouseButton::Left) && point_in_rect(x, y, self.back_rect)) {
+            gs.add_sound(ButtonPress);
+            *game_state = self.return_state.clone();
+            return;
+        }
+        if is_key_pressed(KeyCode::Space) {
+            if self.frame == last_frame {
+                self.go_to_frame(0);
+            }
+            self.is_playing = !self.is_playing;
+        }
+        if is_key_pressed(KeyCode::Right) {
+            self.is_playing = false;
+            self.go_to_frame(self.frame + 1);
+        }
+        if is_key_pressed(KeyCode::Left) {
+            self.is_playing = false;
+            self.go_to_frame(self.frame.saturating_sub(1));
+        }
+        if is_key_pressed(KeyCode::Home) {
+            self.go_to_frame(0);
+        }
+        if is_key_pressed(KeyCode::End) {
+            self.go_to_frame(last_frame);
+        }
+        if is_mouse_button_down(MouseButton::Left) && point_in_rect(x, y, self.scrub_rect) {
+            self.is_playing = false;
+            let fraction = (x - self.scrub_rect.x) / self.scrub_rect.w;
+            self.go_to_frame((fraction * last_frame as f32).round() as usize);
+        }
+
+        if self.is_playing {
+            if let Some(replay) = &self.replay {
+                self.progress += replay.speed_at(self.frame);
+                if self.progress > 1.0 {
+                    self.progress -= 1.0;
+                    self.frame += 1;
+                }
+                if self.frame >= last_frame {
+                    self.go_to_frame(last_frame);
+                    self.is_playing = false;
+                }
+            }
+        }
+        self.show_frame(gs);
+    }
+
+    pub fn render(&self, gs: &GameSprites) {
+        let (Some(replay), Some(yard)) = (&self.replay, &self.yard) else {
+            return;
+        };
+        yard.render(gs);
+
+        let rect = self.scrub_rect;
+        let last_frame = self.num_frames().saturating_sub(1).max(1);
+        let fraction = self.frame as f32 / last_frame as f32;
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, WHITE);
+        draw_rectangle(rect.x, rect.y, rect.w * fraction, rect.h, SKYBLUE);
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2., BLACK);
+
+        let font_size = self.label_rect.h / 3.;
+        let lines = [
+            format!("{}: step {} of {}", replay.level_name, self.frame, self.num_frames().saturating_sub(1)),
+            format!("{}, speed {:.2}", if self.is_playing {"playing"} else {"paused"}, replay.speed_at(self.frame)),
+            "Space: play/pause, arrows: step, click the bar to scrub".to_owned(),
+        ];
+        for (index, line) in lines.iter().enumerate() {
+            draw_text(line, self.label_rect.x, self.label_rect.y + font_size * (index + 1) as f32, font_size, DARKGRAY);
+        }
+        draw_text_button("Back", self.back_rect, WHITE);
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Serialize, Deserialize};
+
+// A solution earns one star for winning, one for staying within the level's track par (both tracks and
+// switches) and one for finishing within its tick par. A par that the level doesn't declare is always met.
+pub const MAX_EARNED_STARS: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Par {
+    pub tracks: Option<u32>,
+    pub switches: Option<u32>,
+    pub ticks: Option<u32>,
+}
+
+impl Par {
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_none() && self.switches.is_none() && self.ticks.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    // tracktiles with at least one connection, switches are the tracktiles whose connections switch
+    // (crossings don't count).
+    pub tracks: u32,
+    pub switches: u32,
+    // the number of ProcessTick actions until the level was won.
+    pub ticks: u32,
+}
+
+fn within(value: u32, par: Option<u32>) -> bool {
+    par.is_none_or(|par| value <= par)
+}
+
+impl Score {
+    pub fn stars(&self, par: &Par) -> u32 {
+        let track_star = within(self.tracks, par.tracks) && within(self.switches, par.switches);
+        let tick_star = within(self.ticks, par.ticks);
+        1 + track_star as u32 + tick_star as u32
+    }
+
+    pub fn is_better_than(&self, other: &Score, par: &Par) -> bool {
+        // more stars first, then fewer pieces of track, then a faster solution.
+        let key = |score: &Score| (
+            MAX_EARNED_STARS - score.stars(par),
+            score.tracks + score.switches,
+            score.ticks,
+        );
+        key(self) < key(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAR: Par = Par { tracks: Some(5), switches: Some(1), ticks: Some(10) };
+
+    fn score(tracks: u32, switches: u32, ticks: u32) -> Score {
+        Score { tracks, switches, ticks }
+    }
+
+    #[test]
+    fn stars_count_the_pars_that_are_met() {
+        assert_eq!(score(5, 1, 10).stars(&PAR), 3);
+        assert_eq!(score(6, 1, 10).stars(&PAR), 2);
+        // too many switches lose the track star, even with few tracks
+        assert_eq!(score(3, 2, 10).stars(&PAR), 2);
+        assert_eq!(score(5, 1, 11).stars(&PAR), 2);
+        assert_eq!(score(6, 2, 11).stars(&PAR), 1);
+    }
+
+    #[test]
+    fn missing_pars_are_always_met() {
+        assert_eq!(score(100, 20, 500).stars(&Par::default()), MAX_EARNED_STARS);
+        let ticks_only = Par { ticks: Some(10), ..Par::default() };
+        assert_eq!(score(100, 20, 10).stars(&ticks_only), 3);
+        assert_eq!(score(100, 20, 11).stars(&ticks_only), 2);
+        let tracks_only = Par { tracks: Some(5), ..Par::default() };
+        assert_eq!(score(5, 20, 500).stars(&tracks_only), 3);
+        assert_eq!(score(6, 0, 500).stars(&tracks_only), 2);
+    }
+
+    #[test]
+    fn better_scores_have_more_stars_then_fewer_pieces_then_fewer_ticks() {
+        // a third star beats any number of pieces of track
+        assert!(score(5, 1, 10).is_better_than(&score(1, 0, 11), &PAR));
+        assert!(score(4, 0, 10).is_better_than(&score(3, 2, 10), &PAR));
+        assert!(score(4, 1, 10).is_better_than(&score(5, 1, 9), &PAR));
+        assert!(score(4, 1, 8).is_better_than(&score(4, 1, 9), &PAR));
+        // switches count as much as tracks
+        assert!(!score(4, 1, 10).is_better_than(&score(5, 0, 10), &PAR));
+        assert!(!score(5, 0, 10).is_better_than(&score(4, 1, 10), &PAR));
+        assert!(!score(4, 1, 10).is_better_than(&score(4, 1, 10), &PAR));
+    }
+}
@@ -0,0 +1,153 @@
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+
+use crate::color::{self, ColorblindMode};
+use crate::levels::save::write_atomically;
+use crate::particle::ParticleDensity;
+use crate::sprites::GameSprites;
+use crate::sprites::mixer::{Channel, MixerSettings};
+
+// The preferences of a player, saved as JSON in the directory of their profile (see profiles.rs) and
+// changed from the settings screen (see gui/settings_menu.rs). Settings missing from the file keep their
+// default, so settings added later don't invalidate older files.
+
+pub const SPEED_STEP: f32 = 0.1;
+pub const VOLUME_STEP: f32 = 0.1;
+// in seconds
+pub const DOUBLE_CLICK_TIME_STEP: f64 = 0.05;
+pub const MIN_DOUBLE_CLICK_TIME: f64 = 0.2;
+pub const MAX_DOUBLE_CLICK_TIME: f64 = 1.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    // the speed the trains go at when the game starts, as a fraction of the fastest speed
+    pub default_speed: f32,
+    // the volume of each channel of the mixer, and whether it is muted. See sprites/mixer.rs.
+    pub mixer: MixerSettings,
+    // the longest time between the two clicks of a double click, in seconds
+    pub double_click_time: f64,
+    pub colorblind_mode: ColorblindMode,
+    pub particle_density: ParticleDensity,
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            default_speed: 0.5,
+            mixer: MixerSettings::default(),
+            double_click_time: 0.55,
+            colorblind_mode: ColorblindMode::default(),
+            particle_density: ParticleDensity::Full,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> Settings {
+        // never fails: settings that can't be read are replaced by the defaults.
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Settings::default(),
+            Err(err) => {
+                eprintln!("Unable to read the settings {}: {err}", path.display());
+                return Settings::default();
+            }
+        };
+        match serde_json::from_str(&text) {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!("Unable to load the settings {}: {err}", path.display());
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        write_atomically(path, &text).map_err(|err| err.to_string())
+    }
+
+    pub fn apply(&self, gs: &mut GameSprites) {
+        // the settings that don't belong to a single screen. The gameplay reads its own, see Gameplay::apply_settings.
+        color::set_colorblind_mode(self.colorblind_mode);
+        gs.particle_density = self.particle_density;
+        gs.mixer.settings = self.mixer;
+        // macroquad 0.3 only lets the window go fullscreen through the miniquad context
+        unsafe {
+            macroquad::window::get_internal_gl().quad_context.set_fullscreen(self.fullscreen);
+        }
+    }
+
+    pub fn change_default_speed(&mut self, steps: i32) {
+        self.default_speed = step_f32(self.default_speed, steps, SPEED_STEP, 0., 1.);
+    }
+
+    pub fn change_volume(&mut self, channel: Channel, steps: i32) {
+        let channel = self.mixer.channel_mut(channel);
+        channel.volume = step_f32(channel.volume, steps, VOLUME_STEP, 0., 1.);
+    }
+
+    pub fn toggle_mute(&mut self, channel: Channel) {
+        let channel = self.mixer.channel_mut(channel);
+        channel.muted = !channel.muted;
+    }
+
+    pub fn change_double_click_time(&mut self, steps: i32) {
+        let value = self.double_click_time + steps as f64 * DOUBLE_CLICK_TIME_STEP;
+        // rounded to the step, so that going up and down comes back to the same value
+        let value = (value / DOUBLE_CLICK_TIME_STEP).round() * DOUBLE_CLICK_TIME_STEP;
+        self.double_click_time = value.clamp(MIN_DOUBLE_CLICK_TIME, MAX_DOUBLE_CLICK_TIME);
+    }
+}
+
+fn step_f32(value: f32, steps: i32, step: f32, min: f32, max: f32) -> f32 {
+    let value = value + steps as f32 * step;
+    ((value / step).round() * step).clamp(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_are_rounded_to_the_step() {
+        // a value off the grid, e.g. edited by hand in the settings file, snaps to the nearest step
+        assert!((step_f32(0.53, 0, 0.1, 0., 1.) - 0.5).abs() < 1e-6);
+        assert!((step_f32(0.53, 2, 0.1, 0., 1.) - 0.7).abs() < 1e-6);
+        // going up and down comes back to exactly the same value
+        let value = step_f32(0.3, 1, 0.1, 0., 1.);
+        assert_eq!(step_f32(step_f32(value, 3, 0.1, 0., 1.), -3, 0.1, 0., 1.), value);
+    }
+
+    #[test]
+    fn steps_are_clamped() {
+        assert_eq!(step_f32(0.9, 5, 0.1, 0., 1.), 1.);
+        assert_eq!(step_f32(0.1, -5, 0.1, 0., 1.), 0.);
+
+        let mut settings = Settings::default();
+        settings.change_volume(Channel::Train, 100);
+        assert_eq!(settings.mixer.train.volume, 1.);
+        settings.change_default_speed(-100);
+        assert_eq!(settings.default_speed, 0.);
+    }
+
+    #[test]
+    fn double_click_time_is_rounded_and_clamped() {
+        let mut settings = Settings::default();
+        settings.double_click_time = 0.57;
+        settings.change_double_click_time(1);
+        assert!((settings.double_click_time - 0.6).abs() < 1e-9);
+        let value = settings.double_click_time;
+        settings.change_double_click_time(4);
+        settings.change_double_click_time(-4);
+        assert_eq!(settings.double_click_time, value);
+
+        settings.change_double_click_time(100);
+        assert_eq!(settings.double_click_time, MAX_DOUBLE_CLICK_TIME);
+        settings.change_double_click_time(-100);
+        assert_eq!(settings.double_click_time, MIN_DOUBLE_CLICK_TIME);
+    }
+}
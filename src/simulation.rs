@@ -0,0 +1,581 @@
+use crate::color::rules::ColorRules;
+use crate::edge::Edge;
+use crate::event::{EventKind, EventList, SimEvent};
+use crate::levels::{LevelInfo, PositionedTile};
+use crate::tile::tracktile::Tracktile;
+use crate::tile::BorderState;
+use crate::tile::Tile;
+use crate::yard::NextAction;
+
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SimStatus {
+    Running,
+    Crashed,
+    Won,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationError {
+    // a tile of the level or of the layout doesn't fit in the yard
+    OutsideOfYard { x: u8, y: u8 },
+    // the layout puts a tracktile where the level already has another tile
+    DrawnOverLevelTile { x: u8, y: u8 },
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulationError::OutsideOfYard { x, y } => write!(f, "the tile at column {x}, row {y} is outside of the yard"),
+            SimulationError::DrawnOverLevelTile { x, y } => write!(
+                f, "a track is drawn on top of a tile of the level at column {x}, row {y}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+// The Simulation holds the rules of the game: which trains are on which tiles and edges, and how they
+// move from one tick to the next. It knows nothing about textures, sounds or particles, so it can be run
+// without a window. Everything that happens while it advances is reported back as a list of SimEvents.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Simulation {
+    pub tiles: Vec<Vec<Tile>>,
+    pub h_edges: Vec<Vec<Edge>>,
+    pub v_edges: Vec<Vec<Edge>>,
+    pub status: SimStatus,
+    pub num_rows: usize,
+    pub num_cols: usize,
+    // the number of ProcessTick actions so far
+    #[serde(default)]
+    pub num_ticks: u32,
+    // the rules of the level pack, which decide the colors of trains that meet or get split
+    #[serde(default)]
+    pub color_rules: ColorRules,
+}
+
+impl Simulation {
+    pub fn new(
+        level_info: &LevelInfo, layout: &LevelInfo, num_rows: usize, num_cols: usize, color_rules: &ColorRules,
+    ) -> Result<Simulation, SimulationError> {
+        // level_info holds the tiles given by the level, and layout holds the tracktiles drawn by the player.
+        let mut tiles: Vec<Vec<Tile>> = Vec::new();
+        for _ in 0..num_rows {
+            let mut row: Vec<Tile> = Vec::new();
+            for _ in 0..num_cols {
+                row.push(Tile::Tracktile(Tracktile::new(None, None)));
+            }
+            tiles.push(row);
+        }
+        let fits = |tile: &PositionedTile| (tile.y as usize) < num_rows && (tile.x as usize) < num_cols;
+        for tile in level_info {
+            if !fits(tile) {
+                return Err(SimulationError::OutsideOfYard { x: tile.x, y: tile.y });
+            }
+            tiles[tile.y as usize][tile.x as usize] = tile.tile.clone();
+        }
+        for tile in layout {
+            if !fits(tile) {
+                return Err(SimulationError::OutsideOfYard { x: tile.x, y: tile.y });
+            }
+            match tiles[tile.y as usize][tile.x as usize] {
+                Tile::Tracktile(_) => {
+                    tiles[tile.y as usize][tile.x as usize] = tile.tile.clone();
+                },
+                _ => return Err(SimulationError::DrawnOverLevelTile { x: tile.x, y: tile.y }),
+            }
+        }
+
+        let h_edges = (0..(num_rows + 1))
+            .map(|_| (0..num_cols).map(|_| Edge::new()).collect())
+            .collect();
+        let v_edges = (0..num_rows)
+            .map(|_| (0..(num_cols + 1)).map(|_| Edge::new()).collect())
+            .collect();
+
+        Ok(Simulation {
+            tiles,
+            h_edges,
+            v_edges,
+            status: SimStatus::Running,
+            num_rows,
+            num_cols,
+            num_ticks: 0,
+            color_rules: color_rules.clone(),
+        })
+    }
+
+    pub fn advance(&mut self, action: NextAction) -> Vec<SimEvent> {
+        match action {
+            NextAction::ProcessTick => self.process_tick(),
+            NextAction::ProcessEdges => self.process_edges(),
+        }
+    }
+
+    pub fn run(&mut self, max_steps: u32) -> SimStatus {
+        // advances the simulation until it crashes, wins, or max_steps actions have been processed.
+        // Like the yard, we start with a ProcessTick and then alternate.
+        let mut next_step = NextAction::ProcessTick;
+        for _ in 0..max_steps {
+            if self.status != SimStatus::Running {
+                break;
+            }
+            self.advance(next_step);
+            next_step = match next_step {
+                NextAction::ProcessTick => NextAction::ProcessEdges,
+                NextAction::ProcessEdges => NextAction::ProcessTick,
+            };
+        }
+        self.status
+    }
+
+    pub fn process_edges(&mut self) -> Vec<SimEvent> {
+        assert_eq!(self.status, SimStatus::Running);
+        let mut events = vec![];
+
+        // merge all trains that are still in tiles
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                let mut tile_events: EventList = vec![];
+                self.tiles[r][c].process_end_of_tick(&mut tile_events, &self.color_rules);
+                push_tile_events(&mut events, r, c, tile_events);
+            }
+        }
+
+        // dispatch all trains and store them in edges.
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                [
+                    self.h_edges[r][c].train_to_a,
+                    self.v_edges[r][c + 1].train_to_b,
+                    self.h_edges[r + 1][c].train_to_b,
+                    self.v_edges[r][c].train_to_a,
+                ] = self.tiles[r][c].dispatch_trains();
+            }
+        }
+        // mix edges. The event is reported on the side of the tile below (or to the right of) the edge,
+        // except for edges on the bottom and right borders of the yard.
+        for r in 0..(self.num_rows + 1) {
+            for c in 0..self.num_cols {
+                if let Some(color) = self.h_edges[r][c].interact_trains(&self.color_rules) {
+                    let (r, dir) = if r < self.num_rows {(r, 0)} else {(r - 1, 2)};
+                    events.push(SimEvent { r, c, kind: EventKind::EdgeMix { dir, color } });
+                }
+            }
+        }
+        for r in 0..self.num_rows {
+            for c in 0..(self.num_cols + 1) {
+                if let Some(color) = self.v_edges[r][c].interact_trains(&self.color_rules) {
+                    let (c, dir) = if c < self.num_cols {(c, 3)} else {(c - 1, 1)};
+                    events.push(SimEvent { r, c, kind: EventKind::EdgeMix { dir, color } });
+                }
+            }
+        }
+
+        // detect crashes on boundaries of yard (i.e. if a train is about to crash by going
+        // too far up where there is no tile left to catch it)
+        for c in 0..self.num_cols {
+            if let Some(color) = self.h_edges[0][c].train_to_a {
+                self.status = SimStatus::Crashed;
+                events.push(SimEvent { r: 0, c, kind: EventKind::Crash { dir: 0, color } });
+            }
+            if let Some(color) = self.h_edges[self.num_rows][c].train_to_b {
+                self.status = SimStatus::Crashed;
+                events.push(SimEvent { r: self.num_rows - 1, c, kind: EventKind::Crash { dir: 2, color } });
+            }
+        }
+        for r in 0..self.num_rows {
+            if let Some(color) = self.v_edges[r][0].train_to_a {
+                self.status = SimStatus::Crashed;
+                events.push(SimEvent { r, c: 0, kind: EventKind::Crash { dir: 3, color } });
+            }
+            if let Some(color) = self.v_edges[r][self.num_cols].train_to_b {
+                self.status = SimStatus::Crashed;
+                events.push(SimEvent { r, c: self.num_cols - 1, kind: EventKind::Crash { dir: 1, color } });
+            }
+        }
+
+        // all tiles pull in trains from the edges. A crash occurs if there is a
+        // train entering a tile but the tile does not pull it in.
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                let border_state: BorderState = [
+                    self.h_edges[r][c].train_to_b,
+                    self.v_edges[r][c + 1].train_to_a,
+                    self.h_edges[r + 1][c].train_to_a,
+                    self.v_edges[r][c].train_to_b,
+                ];
+                let crashed_trains = self.tiles[r][c].accept_trains(border_state);
+                for (dir, train) in crashed_trains.iter().enumerate() {
+                    if let Some(color) = *train {
+                        self.status = SimStatus::Crashed;
+                        events.push(SimEvent { r, c, kind: EventKind::Crash { dir: dir as u8, color } });
+                    }
+                }
+            }
+        }
+
+        events
+    }
+
+    pub fn process_tick(&mut self) -> Vec<SimEvent> {
+        assert_eq!(self.status, SimStatus::Running);
+        self.num_ticks += 1;
+        let mut events = vec![];
+
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                let mut tile_events: EventList = vec![];
+                self.tiles[r][c].process_tick(&mut tile_events, &self.color_rules);
+                push_tile_events(&mut events, r, c, tile_events);
+            }
+        }
+        self.pass_trains_through_tunnels(&mut events);
+
+        if self.status == SimStatus::Running && self.has_won() {
+            self.status = SimStatus::Won;
+        }
+        events
+    }
+
+    pub fn tunnel_partner(&self, r: usize, c: usize) -> Option<(usize, usize)> {
+        // the position of the other tunnel with the same id as the tunnel at (r, c).
+        let Tile::Tunnel(tunnel) = &self.tiles[r][c] else {
+            return None;
+        };
+        (0..self.num_rows)
+            .flat_map(|other_r| (0..self.num_cols).map(move |other_c| (other_r, other_c)))
+            .find(|&(other_r, other_c)| (other_r, other_c) != (r, c) && matches!(
+                &self.tiles[other_r][other_c], Tile::Tunnel(other) if other.id == tunnel.id
+            ))
+    }
+
+    fn pass_trains_through_tunnels(&mut self, events: &mut Vec<SimEvent>) {
+        // the trains that reached the middle of a tunnel leave from the middle of its partner, so that they
+        // come out during the next tick. A tunnel without a partner can't be parsed, but if it ever happens,
+        // the train crashes.
+        let mut arrivals = vec![];
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                if let Tile::Tunnel(tunnel) = &mut self.tiles[r][c] {
+                    if let Some(color) = tunnel.incoming_train.take() {
+                        arrivals.push((r, c, tunnel.dir, color));
+                    }
+                }
+            }
+        }
+        for (r, c, dir, color) in arrivals {
+            match self.tunnel_partner(r, c) {
+                Some((partner_r, partner_c)) => {
+                    if let Tile::Tunnel(partner) = &mut self.tiles[partner_r][partner_c] {
+                        partner.outgoing_train = Some(color);
+                    }
+                }
+                None => {
+                    self.status = SimStatus::Crashed;
+                    events.push(SimEvent { r, c, kind: EventKind::Crash { dir, color } });
+                }
+            }
+        }
+    }
+
+    pub fn settle_after_crash(&mut self) -> Vec<SimEvent> {
+        // this only exists for the edge case where two trains simultaneously enter a trainsink with only 1 desire.
+        // in that case, one train enters, the other crashes.
+        let mut events = vec![];
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                if let Tile::Trainsink(trainsink) = &mut self.tiles[r][c] {
+                    let mut tile_events: EventList = vec![];
+                    trainsink.process_tick(&mut tile_events);
+                    push_tile_events(&mut events, r, c, tile_events);
+                }
+            }
+        }
+        events
+    }
+
+    pub fn has_won(&self) -> bool {
+        for row in &self.tiles {
+            for tile in row {
+                match tile {
+                    Tile::Trainsink(trainsink) if !trainsink.is_satisfied() => return false,
+                    Tile::Trainsource(trainsource) if !trainsource.is_empty() => return false,
+                    Tile::Tunnel(tunnel) if !tunnel.is_empty() => return false,
+                    Tile::Station(station) if !station.is_empty() => return false,
+                    Tile::Turntable(turntable) if turntable.outgoing_trains.iter().any(Option::is_some) => return false,
+                    _ => {}
+                }
+            }
+        }
+        self.h_edges.iter().chain(self.v_edges.iter()).flatten().all(|edge| edge.is_empty())
+    }
+}
+
+fn push_tile_events(events: &mut Vec<SimEvent>, r: usize, c: usize, tile_events: EventList) {
+    events.extend(tile_events.into_iter().map(|kind| SimEvent { r, c, kind }));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::color::rules::ManyTrainMix;
+    use crate::connection::Connection;
+    use crate::levels::LevelManager;
+    use crate::tile::trainsink::Trainsink;
+    use crate::tile::trainsource::Trainsource;
+
+    const MAX_STEPS: u32 = 1000;
+
+    fn positioned(tile: Tile, x: u8, y: u8) -> PositionedTile {
+        PositionedTile { tile, x, y }
+    }
+
+    fn straight_track(dir1: u8, dir2: u8, x: u8, y: u8) -> PositionedTile {
+        positioned(Tile::Tracktile(Tracktile::new(Some(Connection { dir1, dir2 }), None)), x, y)
+    }
+
+    fn unreachable_sink(x: u8, y: u8) -> PositionedTile {
+        // keeps the level from being won as soon as the trainsources are empty
+        positioned(Tile::Trainsink(Trainsink::new(vec![Color::Brown], [false, false, true, false])), x, y)
+    }
+
+    fn run_collecting_events(sim: &mut Simulation) -> Vec<SimEvent> {
+        let mut events = vec![];
+        let mut next_step = NextAction::ProcessTick;
+        for _ in 0..MAX_STEPS {
+            if sim.status != SimStatus::Running {
+                break;
+            }
+            events.extend(sim.advance(next_step));
+            next_step = match next_step {
+                NextAction::ProcessTick => NextAction::ProcessEdges,
+                NextAction::ProcessEdges => NextAction::ProcessTick,
+            };
+        }
+        events
+    }
+
+    fn simulation_from_text(level_text: &str, layout: &LevelInfo) -> Simulation {
+        // level_text is a single level in the format of levels.txt, without its `---` line.
+        let level_manager = LevelManager::parse(&format!("CITY:Test\n\n{level_text}---\n----\n")).unwrap();
+        let level = level_manager.get_level(&level_manager.get_names_in_city("Test")[0]);
+        Simulation::new(
+            &level.level_info, layout, level.num_rows, level.num_cols, level_manager.color_rules(),
+        ).unwrap()
+    }
+
+    #[test]
+    fn red_line_is_won_with_a_straight_track() {
+        let level_manager = LevelManager::parse(include_str!("../assets/levels.txt")).unwrap();
+        let level = level_manager.get_level("Red Line");
+        let layout = (2..5).map(|x| straight_track(1, 3, x, 3)).collect();
+        let mut sim = Simulation::new(
+            &level.level_info, &layout, level.num_rows, level.num_cols, level_manager.color_rules(),
+        ).unwrap();
+
+        assert!(!sim.has_won());
+        assert_eq!(sim.run(MAX_STEPS), SimStatus::Won);
+        assert!(sim.has_won());
+    }
+
+    #[test]
+    fn red_line_crashes_without_tracks() {
+        let level_manager = LevelManager::parse(include_str!("../assets/levels.txt")).unwrap();
+        let level = level_manager.get_level("Red Line");
+        let mut sim = Simulation::new(
+            &level.level_info, &vec![], level.num_rows, level.num_cols, level_manager.color_rules(),
+        ).unwrap();
+
+        assert_eq!(sim.run(MAX_STEPS), SimStatus::Crashed);
+        assert!(!sim.has_won());
+    }
+
+    #[test]
+    fn train_leaving_the_yard_crashes_on_the_border() {
+        let level_info = vec![
+            positioned(Tile::Trainsource(Trainsource::new(vec![Color::Red], 0)), 1, 0),
+            unreachable_sink(2, 2),
+        ];
+        let mut sim = Simulation::new(&level_info, &vec![], 3, 3, &ColorRules::default()).unwrap();
+
+        let events = run_collecting_events(&mut sim);
+        assert_eq!(sim.status, SimStatus::Crashed);
+        assert!(events.contains(&SimEvent { r: 0, c: 1, kind: EventKind::Crash { dir: 0, color: Color::Red } }));
+    }
+
+    #[test]
+    fn trains_meeting_on_an_edge_mix() {
+        // a red and a blue train leave their sources towards each other, and meet on the edge between them.
+        let level_info = vec![
+            positioned(Tile::Trainsource(Trainsource::new(vec![Color::Red], 1)), 0, 0),
+            positioned(Tile::Trainsource(Trainsource::new(vec![Color::Blue], 3)), 1, 0),
+            unreachable_sink(0, 1),
+        ];
+        let mut sim = Simulation::new(&level_info, &vec![], 2, 2, &ColorRules::default()).unwrap();
+
+        let events = run_collecting_events(&mut sim);
+        assert!(events.contains(&SimEvent { r: 0, c: 1, kind: EventKind::EdgeMix { dir: 3, color: Color::Purple } }));
+    }
+
+    #[test]
+    fn trains_leave_the_partner_tunnel_on_the_next_tick() {
+        let mut sim = simulation_from_text(
+            "Tunnel:1:5x1\n+ 0,0 blue right\nt 1,0 0 left\n* 2,0\nt 3,0 0 right\no 4,0 blue left\n", &vec![],
+        );
+        sim.process_tick();
+        sim.process_edges();
+        assert!(matches!(&sim.tiles[0][1], Tile::Tunnel(tunnel) if tunnel.incoming_train == Some(Color::Blue)));
+
+        sim.process_tick();
+        assert!(matches!(&sim.tiles[0][1], Tile::Tunnel(tunnel) if tunnel.is_empty()));
+        assert!(matches!(&sim.tiles[0][3], Tile::Tunnel(tunnel) if tunnel.outgoing_train == Some(Color::Blue)));
+        // the trainsink only takes the train if it is still blue
+        assert_eq!(sim.run(MAX_STEPS), SimStatus::Won);
+    }
+
+    #[test]
+    fn trains_crash_when_the_partner_tunnel_is_blocked() {
+        let mut sim = simulation_from_text(
+            "Blocked Tunnel:1:5x2\n+ 0,0 red right\nt 1,0 0 left\nt 3,0 0 right\n* 4,0\no 4,1 red left\n", &vec![],
+        );
+        let events = run_collecting_events(&mut sim);
+        assert_eq!(sim.status, SimStatus::Crashed);
+        assert!(events.contains(&SimEvent { r: 0, c: 4, kind: EventKind::Crash { dir: 3, color: Color::Red } }));
+    }
+
+    #[test]
+    fn only_trains_of_the_filter_color_go_through() {
+        let mut sim = simulation_from_text("Filter:1:3x1\n+ 0,0 red right\nf 1,0 red left,right\no 2,0 red left\n", &vec![]);
+        assert_eq!(sim.run(MAX_STEPS), SimStatus::Won);
+
+        let mut sim = simulation_from_text("Filter:1:3x1\n+ 0,0 blue right\nf 1,0 red left,right\no 2,0 blue left\n", &vec![]);
+        let events = run_collecting_events(&mut sim);
+        assert_eq!(sim.status, SimStatus::Crashed);
+        assert!(events.contains(&SimEvent { r: 0, c: 1, kind: EventKind::Crash { dir: 3, color: Color::Blue } }));
+    }
+
+    #[test]
+    fn trains_crossing_a_bridge_together_neither_mix_nor_crash() {
+        // both trains are released on the first tick, so they reach the middle of the tile on the same tick.
+        let sources = "Bridge:1:3x3\n+ 0,1 red right\n+ 1,0 blue down\no 2,1 red left\no 1,2 blue up\n";
+        let mut sim = simulation_from_text(&format!("{sources}b 1,1 up\n"), &vec![]);
+        let events = run_collecting_events(&mut sim);
+        assert_eq!(sim.status, SimStatus::Won);
+        assert!(!events.iter().any(|event| matches!(event.kind, EventKind::TrackMix { .. } | EventKind::Crash { .. })));
+
+        // on a crossing tracktile, the same trains meet
+        let crossing = Tracktile::new(Some(Connection { dir1: 0, dir2: 2 }), Some(Connection { dir1: 1, dir2: 3 }));
+        let mut sim = simulation_from_text(sources, &vec![positioned(Tile::Tracktile(crossing), 1, 1)]);
+        let events = run_collecting_events(&mut sim);
+        assert_eq!(sim.status, SimStatus::Crashed);
+        assert!(events.iter().any(|event| matches!(event.kind, EventKind::TrackMix { .. })));
+    }
+
+    #[test]
+    fn stations_hold_trains_for_their_delay_in_arrival_order() {
+        let mut sim = simulation_from_text(
+            "Station:1:3x1\n+ 0,0 red,blue right\nd 1,0 2 left,right\no 2,0 red,blue left\n", &vec![],
+        );
+        // the tick on which each train leaves the station
+        let mut departures = vec![];
+        while sim.status == SimStatus::Running && sim.num_ticks < MAX_STEPS {
+            sim.process_tick();
+            if let Tile::Station(station) = &sim.tiles[0][1] {
+                departures.extend(station.outgoing_train.map(|color| (sim.num_ticks, color)));
+            }
+            if sim.status == SimStatus::Running {
+                sim.process_edges();
+            }
+        }
+        assert_eq!(sim.status, SimStatus::Won);
+        // a track would send the red train out on tick 2, and the blue one right after it.
+        assert_eq!(departures, vec![(4, Color::Red), (5, Color::Blue)]);
+    }
+
+    #[test]
+    fn turntables_send_trains_through_their_exits_in_turn() {
+        let mut sim = simulation_from_text(
+            "Turntable:1\n+ 0,3 red,blue,yellow,green right\nr 1,3 up,right,down\n\
+                o 1,2 red,green down\no 2,3 blue left\no 1,4 yellow up\n",
+            &vec![],
+        );
+        let mut exits = vec![];
+        while sim.status == SimStatus::Running && sim.num_ticks < MAX_STEPS {
+            sim.process_tick();
+            if let Tile::Turntable(turntable) = &sim.tiles[3][1] {
+                exits.extend((0..4).filter_map(|dir| turntable.outgoing_trains[dir].map(|color| (dir as u8, color))));
+            }
+            if sim.status == SimStatus::Running {
+                sim.process_edges();
+            }
+        }
+        assert_eq!(sim.status, SimStatus::Won);
+        // after the last exit, the turntable starts over from the first one
+        assert_eq!(exits, vec![(0, Color::Red), (1, Color::Blue), (2, Color::Yellow), (0, Color::Green)]);
+    }
+
+    #[test]
+    fn one_way_tracks_refuse_trains_going_the_other_way() {
+        let mut sim = simulation_from_text("One-way:1:3x1\n+ 0,0 red right\n> 1,0 left,right\no 2,0 red left\n", &vec![]);
+        assert_eq!(sim.run(MAX_STEPS), SimStatus::Won);
+
+        let mut sim = simulation_from_text("Wrong Way:1:3x1\n+ 0,0 red right\n> 1,0 right,left\no 2,0 red left\n", &vec![]);
+        let events = run_collecting_events(&mut sim);
+        assert_eq!(sim.status, SimStatus::Crashed);
+        assert!(events.contains(&SimEvent { r: 0, c: 1, kind: EventKind::Crash { dir: 3, color: Color::Red } }));
+    }
+
+    #[test]
+    fn simulations_follow_their_own_color_rules() {
+        // the same level played with two packs, side by side: the classic rules make purple, and the
+        // other pack makes green.
+        let level_info = vec![
+            positioned(Tile::Trainsource(Trainsource::new(vec![Color::Red], 1)), 0, 0),
+            positioned(Tile::Trainsource(Trainsource::new(vec![Color::Blue], 3)), 1, 0),
+            unreachable_sink(0, 1),
+        ];
+        let other_rules = ColorRules {
+            mixes: vec![([Color::Red, Color::Blue], Color::Green)],
+            many_train_mix: ManyTrainMix::Pairwise,
+            ..ColorRules::default()
+        };
+        let mut classic = Simulation::new(&level_info, &vec![], 2, 2, &ColorRules::default()).unwrap();
+        let mut other = Simulation::new(&level_info, &vec![], 2, 2, &other_rules).unwrap();
+
+        let mix_colors = |events: Vec<SimEvent>| -> Vec<Color> {
+            events.into_iter()
+                .filter_map(|event| match event.kind {
+                    EventKind::EdgeMix { color, .. } => Some(color),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(mix_colors(run_collecting_events(&mut classic)), vec![Color::Purple]);
+        assert_eq!(mix_colors(run_collecting_events(&mut other)), vec![Color::Green]);
+    }
+
+    #[test]
+    fn layout_on_top_of_a_level_tile_is_refused() {
+        let level_info = vec![positioned(Tile::Trainsource(Trainsource::new(vec![Color::Red], 1)), 0, 0)];
+        let layout = vec![straight_track(1, 3, 0, 0)];
+        assert_eq!(
+            Simulation::new(&level_info, &layout, 3, 3, &ColorRules::default()).err(),
+            Some(SimulationError::DrawnOverLevelTile { x: 0, y: 0 }),
+        );
+    }
+
+    #[test]
+    fn tiles_outside_of_the_yard_are_refused() {
+        let layout = vec![straight_track(1, 3, 3, 0)];
+        assert_eq!(
+            Simulation::new(&vec![], &layout, 3, 3, &ColorRules::default()).err(),
+            Some(SimulationError::OutsideOfYard { x: 3, y: 0 }),
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use crate::color::Color;
+use crate::color::rules::ColorRules;
+use crate::connection::Connection;
+use crate::event::{EventKind, SimEvent};
+use crate::levels::{Level, LevelInfo, LevelProgress, PositionedTile};
+use crate::simulation::{SimStatus, Simulation};
+use crate::tile::tracktile::{ConnectionType, Tracktile};
+use crate::tile::Tile;
+use crate::yard::NextAction;
+
+use std::collections::VecDeque;
+
+// The solver never decides what goes on an empty tile until a train actually tries to enter it.
+// At that moment the simulation reports a crash on the empty tile, so we rewind to just before the
+// crash and try every tracktile that would have accepted the incoming trains. This keeps the search
+// focused on tiles that matter instead of enumerating every layout of the yard.
+
+// number of ProcessTick/ProcessEdges actions after which we give up on a layout (e.g. trains stuck in a loop)
+const MAX_STEPS: u32 = 2000;
+// number of tracktiles tried in total before the solver gives up on a level
+pub const MAX_BRANCHES: u32 = 500_000;
+
+// added to the distance of a train that has to change color before any trainsink wants it
+const PENALTY: u32 = 100;
+
+const ALL_CONNECTIONS: [Connection; 6] = [
+    Connection { dir1: 0, dir2: 2 },
+    Connection { dir1: 1, dir2: 3 },
+    Connection { dir1: 0, dir2: 1 },
+    Connection { dir1: 1, dir2: 2 },
+    Connection { dir1: 2, dir2: 3 },
+    Connection { dir1: 3, dir2: 0 },
+];
+
+pub fn solve(level: &Level, color_rules: &ColorRules) -> Option<LevelProgress> {
+    solve_with_limit(&level.level_info, level.num_rows, level.num_cols, color_rules, MAX_BRANCHES)
+}
+
+pub fn solve_with_limit(
+    level_info: &LevelInfo, num_rows: usize, num_cols: usize, color_rules: &ColorRules, max_branches: u32,
+) -> Option<LevelProgress> {
+    // returns a layout of tracktiles that wins the level, or None if no layout was found
+    // after trying max_branches tracktiles. The bool of the LevelProgress is false,
+    // since it records whether the player has won the level.
+    // Most levels only need single tracks and crossings, so we first search without switches,
+    // which is much faster, and only then search every tracktile. Each pass gets half of the branches.
+    // Within a pass, we look for layouts with few tracktiles first, so that the trains don't wander
+    // around the whole yard before the solver notices a bad decision.
+    let initial_sim = Simulation::new(level_info, &vec![], num_rows, num_cols, color_rules).ok()?;
+    [false, true].into_iter().find_map(|allow_switches| {
+        let mut solver = Solver {
+            branches: 0,
+            max_branches: max_branches / 2,
+            max_tracktiles: 0,
+            allow_switches,
+        };
+        while solver.branches < solver.max_branches && solver.max_tracktiles < num_rows * num_cols {
+            solver.max_tracktiles += 1;
+            if let Some(layout) = solver.search(initial_sim.clone(), NextAction::ProcessTick, 0, vec![]) {
+                return Some(layout);
+            }
+        }
+        None
+    }).map(|layout| (layout, false))
+}
+
+struct Solver {
+    branches: u32,
+    max_branches: u32,
+    max_tracktiles: usize,
+    allow_switches: bool,
+}
+
+impl Solver {
+    fn search(&mut self, mut sim: Simulation, mut next_step: NextAction, mut steps: u32, layout: LevelInfo) -> Option<LevelInfo> {
+        while steps < MAX_STEPS {
+            steps += 1;
+            match next_step {
+                NextAction::ProcessTick => {
+                    sim.process_tick();
+                    if sim.status == SimStatus::Won {
+                        return Some(layout);
+                    }
+                    next_step = NextAction::ProcessEdges;
+                }
+                NextAction::ProcessEdges => {
+                    let before = sim.clone();
+                    let events = sim.process_edges();
+                    if sim.status == SimStatus::Crashed {
+                        return self.branch(before, steps - 1, layout, &events);
+                    }
+                    if !all_trains_useful(&sim) {
+                        return None;
+                    }
+                    if is_stalled(&sim) {
+                        // no train is moving and none is left to leave a trainsource. One more tick lets the
+                        // trainsinks take in the trains that just arrived.
+                        sim.process_tick();
+                        return if sim.status == SimStatus::Won {Some(layout)} else {None};
+                    }
+                    next_step = NextAction::ProcessTick;
+                }
+            }
+        }
+        None
+    }
+
+    fn branch(&mut self, before: Simulation, steps: u32, layout: LevelInfo, events: &[SimEvent]) -> Option<LevelInfo> {
+        // called when process_edges crashed. We look at the first tile that a train crashed into.
+        // If it has not been decided yet, we try every tracktile that accepts the incoming trains.
+        // If it is a drawn tracktile with a single connection, we can still add a crossing connection
+        // to it (turning it into an H or Z tracktile), since those never switch and so the trains
+        // that already went through it would have behaved the same way.
+        // A crash on the border of the yard can't be fixed here: there is no tile to catch the train, and the
+        // tile it left has already been decided, so adding a crossing to it would only waste branches.
+        let mut target: Option<(usize, usize)> = None;
+        let mut entering_trains = vec![];
+        for event in events {
+            if let EventKind::Crash { dir, color } = event.kind {
+                // on the border, there is no neighbour
+                neighbour(event.r, event.c, dir, before.num_rows, before.num_cols)?;
+                match target {
+                    None => target = Some((event.r, event.c)),
+                    Some(pos) if pos != (event.r, event.c) => continue,
+                    _ => {}
+                }
+                entering_trains.push((dir, color));
+            }
+        }
+        let (r, c) = target?;
+        let entering_dirs: Vec<u8> = entering_trains.iter().map(|(dir, _)| *dir).collect();
+
+        let new_tile = !layout.iter().any(|tile| (tile.y as usize, tile.x as usize) == (r, c));
+        let candidates = match &before.tiles[r][c] {
+            Tile::Tracktile(tt) if tt.connection_type() == ConnectionType::None => {
+                candidate_tracktiles(&entering_dirs, self.allow_switches)
+            }
+            Tile::Tracktile(tt) if layout.iter().any(|tile| (tile.y as usize, tile.x as usize) == (r, c)) => {
+                crossing_tracktiles(tt, &entering_dirs)
+            }
+            _ => return None,
+        };
+
+        // try the tracktiles that send the trains closest to a trainsink first, and single connections
+        // before tracktiles with two connections.
+        // Tracktiles that send a train towards a dead end are skipped.
+        let mut candidates: Vec<(u32, Tracktile)> = candidates
+            .into_iter()
+            .filter_map(|tracktile| {
+                let distances = entering_trains.iter()
+                    .map(|(dir, color)| distance_to_sink(&before, r, c, tracktile.exit_dir(*dir), *color))
+                    .collect::<Option<Vec<u32>>>()?;
+                // every train still has to cross that many tracktiles, which may be shared between trains.
+                let remaining = distances.iter().max().copied().unwrap_or(0) % PENALTY;
+                if new_tile && layout.len() + 1 + remaining as usize > self.max_tracktiles {
+                    return None;
+                }
+                Some((distances.iter().sum(), tracktile))
+            })
+            .collect();
+        candidates.sort_by_key(|(score, tracktile)| (is_double(tracktile), *score));
+
+        for (_, tracktile) in candidates {
+            if self.branches >= self.max_branches {
+                return None;
+            }
+            self.branches += 1;
+
+            let mut sim = before.clone();
+            if let Tile::Tracktile(tt) = &mut sim.tiles[r][c] {
+                tt.set_connections(tracktile.active_connection(), tracktile.passive_connection());
+            }
+            let mut new_layout: LevelInfo = layout.iter()
+                .filter(|tile| (tile.y as usize, tile.x as usize) != (r, c))
+                .cloned()
+                .collect();
+            new_layout.push(PositionedTile {
+                tile: Tile::Tracktile(tracktile),
+                x: c as u8,
+                y: r as u8,
+            });
+            if let Some(solution) = self.search(sim, NextAction::ProcessEdges, steps, new_layout) {
+                return Some(solution);
+            }
+        }
+        None
+    }
+}
+
+fn is_double(tracktile: &Tracktile) -> bool {
+    !matches!(tracktile.connection_type(), ConnectionType::None | ConnectionType::S | ConnectionType::B)
+}
+
+fn all_trains_useful(sim: &Simulation) -> bool {
+    // a train is useful if its color is still wanted by a trainsink, or if it can become such a color
+    // by mixing with another train or going through a painter. A yard with a useless train can never be won.
+    // This check is skipped for levels with splitters.
+    let tiles = sim.tiles.iter().flatten();
+    if tiles.clone().any(|tile| matches!(tile, Tile::Splitter(_))) {
+        return true;
+    }
+    let mut useful: Vec<Color> = tiles.clone()
+        .filter_map(|tile| match tile {
+            Tile::Trainsink(trainsink) => Some(trainsink.desires.iter().flatten().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    let painter_colors: Vec<Color> = tiles
+        .filter_map(|tile| match tile {
+            Tile::Painter(painter) => Some(painter.color),
+            _ => None,
+        })
+        .collect();
+    let colors = sim.color_rules.all_colors();
+    loop {
+        if painter_colors.iter().any(|color| useful.contains(color)) {
+            return true;
+        }
+        let new_colors: Vec<Color> = colors.iter().copied()
+            .filter(|color| !useful.contains(color))
+            .filter(|color| colors.iter().any(|other| useful.contains(&sim.color_rules.mix(*color, *other))))
+            .collect();
+        if new_colors.is_empty() {
+            break;
+        }
+        useful.extend(new_colors);
+    }
+    sim.h_edges.iter().chain(sim.v_edges.iter()).flatten()
+        .flat_map(|edge| [edge.train_to_a, edge.train_to_b])
+        .flatten()
+        .all(|color| useful.contains(&color))
+}
+
+fn is_stalled(sim: &Simulation) -> bool {
+    // after process_edges, every train that is still in the yard sits on an edge.
+    let edges_empty = sim.h_edges.iter().chain(sim.v_edges.iter()).flatten().all(|edge| edge.is_empty());
+    let sources_empty = sim.tiles.iter().flatten().all(|tile| match tile {
+        Tile::Trainsource(trainsource) => trainsource.is_empty(),
+        // trains waiting in a station will leave it later on
+        Tile::Station(station) => station.is_empty(),
+        _ => true,
+    });
+    edges_empty && sources_empty
+}
+
+fn distance_to_sink(sim: &Simulation, r: usize, c: usize, exit_dir: u8, color: Color) -> Option<u32> {
+    // the number of tiles a train leaving tile (r, c) through exit_dir has to cross before it can enter
+    // a trainsink that still wants its color. Only tracktiles (drawn or not) can be crossed.
+    // If no such trainsink can be reached, the train could still change color on the way, so any trainsink,
+    // painter or splitter counts as well, at a penalty. None means the train can never leave the yard.
+    let wants_color = |tile: &Tile, entering_dir: u8| match tile {
+        Tile::Trainsink(trainsink) => trainsink.border_state[entering_dir as usize] && trainsink.private_desires.contains(&Some(color)),
+        _ => false,
+    };
+    let changes_color = |tile: &Tile, entering_dir: u8| match tile {
+        Tile::Trainsink(trainsink) => trainsink.border_state[entering_dir as usize],
+        Tile::Painter(painter) => painter.connection.contains(entering_dir),
+        Tile::Splitter(splitter) => splitter.incoming_dir == entering_dir,
+        _ => false,
+    };
+    shortest_path(sim, r, c, exit_dir, color, wants_color)
+        .or_else(|| shortest_path(sim, r, c, exit_dir, color, changes_color).map(|distance| distance + PENALTY))
+}
+
+fn shortest_path(sim: &Simulation, r: usize, c: usize, exit_dir: u8, color: Color, is_goal: impl Fn(&Tile, u8) -> bool) -> Option<u32> {
+    let num_rows = sim.tiles.len();
+    let num_cols = sim.tiles[0].len();
+    let mut visited = vec![vec![false; num_cols]; num_rows];
+    let mut queue = VecDeque::new();
+    if let Some((next_r, next_c)) = neighbour(r, c, exit_dir, num_rows, num_cols) {
+        queue.push_back((next_r, next_c, (exit_dir + 2) % 4, 0));
+    }
+    while let Some((r, c, entering_dir, distance)) = queue.pop_front() {
+        let tile = &sim.tiles[r][c];
+        if is_goal(tile, entering_dir) {
+            return Some(distance);
+        }
+        if let Tile::Tunnel(tunnel) = tile {
+            // the train comes out of the other tunnel of the pair.
+            if tunnel.dir == entering_dir && !visited[r][c] {
+                visited[r][c] = true;
+                let partner = sim.tunnel_partner(r, c).and_then(|(partner_r, partner_c)| match &sim.tiles[partner_r][partner_c] {
+                    Tile::Tunnel(partner) => neighbour(partner_r, partner_c, partner.dir, num_rows, num_cols)
+                        .map(|(next_r, next_c)| (next_r, next_c, (partner.dir + 2) % 4)),
+                    _ => None,
+                });
+                if let Some((next_r, next_c, next_entering_dir)) = partner {
+                    queue.push_back((next_r, next_c, next_entering_dir, distance + 1));
+                }
+            }
+            continue;
+        }
+        if let Tile::Station(station) = tile {
+            if station.entry_dir == entering_dir && !visited[r][c] {
+                visited[r][c] = true;
+                if let Some((next_r, next_c)) = neighbour(r, c, station.exit_dir, num_rows, num_cols) {
+                    queue.push_back((next_r, next_c, (station.exit_dir + 2) % 4, distance + 1));
+                }
+            }
+            continue;
+        }
+        if let Tile::OneWay(oneway) = tile {
+            if oneway.connection.from == entering_dir && !visited[r][c] {
+                visited[r][c] = true;
+                if let Some((next_r, next_c)) = neighbour(r, c, oneway.connection.to, num_rows, num_cols) {
+                    queue.push_back((next_r, next_c, (oneway.connection.to + 2) % 4, distance + 1));
+                }
+            }
+            continue;
+        }
+        if let Tile::Turntable(turntable) = tile {
+            // which exit the train takes depends on the trains before it, so any of them might do.
+            if !visited[r][c] {
+                visited[r][c] = true;
+                for exit_dir in &turntable.exits {
+                    if let Some((next_r, next_c)) = neighbour(r, c, *exit_dir, num_rows, num_cols) {
+                        queue.push_back((next_r, next_c, (exit_dir + 2) % 4, distance + 1));
+                    }
+                }
+            }
+            continue;
+        }
+        if let Tile::Bridge(_) = tile {
+            // whichever level the train is on, it goes straight through.
+            if let Some((next_r, next_c)) = neighbour(r, c, (entering_dir + 2) % 4, num_rows, num_cols) {
+                queue.push_back((next_r, next_c, entering_dir, distance + 1));
+            }
+            continue;
+        }
+        if let Tile::Filter(filter) = tile {
+            // only trains of the color of the filter go through it.
+            if filter.color == color && filter.connection.contains(entering_dir) && !visited[r][c] {
+                visited[r][c] = true;
+                let exit_dir = if filter.connection.dir1 == entering_dir {filter.connection.dir2} else {filter.connection.dir1};
+                if let Some((next_r, next_c)) = neighbour(r, c, exit_dir, num_rows, num_cols) {
+                    queue.push_back((next_r, next_c, (exit_dir + 2) % 4, distance + 1));
+                }
+            }
+            continue;
+        }
+        if !matches!(tile, Tile::Tracktile(_)) || visited[r][c] {
+            continue;
+        }
+        visited[r][c] = true;
+        for dir in 0..4 {
+            if let Some((next_r, next_c)) = neighbour(r, c, dir, num_rows, num_cols) {
+                queue.push_back((next_r, next_c, (dir + 2) % 4, distance + 1));
+            }
+        }
+    }
+    None
+}
+
+fn neighbour(r: usize, c: usize, dir: u8, num_rows: usize, num_cols: usize) -> Option<(usize, usize)> {
+    match dir {
+        0 if r > 0 => Some((r - 1, c)),
+        1 if c + 1 < num_cols => Some((r, c + 1)),
+        2 if r + 1 < num_rows => Some((r + 1, c)),
+        3 if c > 0 => Some((r, c - 1)),
+        _ => None,
+    }
+}
+
+fn crossing_tracktiles(tracktile: &Tracktile, entering_dirs: &[u8]) -> Vec<Tracktile> {
+    let mut candidates = vec![];
+    if let (Some(active), None) = (tracktile.active_connection(), tracktile.passive_connection()) {
+        for conn in ALL_CONNECTIONS.into_iter().filter(|conn| *conn != active) {
+            let crossing = Tracktile::new(Some(active), Some(conn));
+            let accepts_all = entering_dirs.iter().all(|dir| active.contains(*dir) || conn.contains(*dir));
+            if accepts_all && matches!(crossing.connection_type(), ConnectionType::H | ConnectionType::Z) {
+                candidates.push(crossing);
+            }
+        }
+    }
+    candidates
+}
+
+fn candidate_tracktiles(entering_dirs: &[u8], allow_switches: bool) -> Vec<Tracktile> {
+    // every tracktile that accepts trains from all of entering_dirs. Without allow_switches, only crossings
+    // are tried, and only when several trains enter at once (drawn tracktiles can become crossings later on).
+    let mut candidates = vec![];
+    for conn in ALL_CONNECTIONS {
+        if entering_dirs.iter().all(|dir| conn.contains(*dir)) {
+            candidates.push(Tracktile::new(Some(conn), None));
+        }
+    }
+    for (i, active) in ALL_CONNECTIONS.iter().enumerate() {
+        for (j, passive) in ALL_CONNECTIONS.iter().enumerate() {
+            if i == j || !entering_dirs.iter().all(|dir| active.contains(*dir) || passive.contains(*dir)) {
+                continue;
+            }
+            let tracktile = Tracktile::new(Some(*active), Some(*passive));
+            // H and Z tracktiles never switch, so the order of their connections does not matter.
+            if !allow_switches && (tracktile.is_switch() || entering_dirs.len() < 2) {
+                continue;
+            }
+            if tracktile.is_switch() || i < j {
+                candidates.push(tracktile);
+            }
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::levels::LevelManager;
+    use crate::tile::trainsink::Trainsink;
+    use crate::tile::trainsource::Trainsource;
+
+    #[test]
+    fn solutions_win_their_level() {
+        // the first city, and the city that shows the tiles added since the original game.
+        let level_manager = LevelManager::parse(include_str!("../assets/levels.txt")).unwrap();
+        let level_names = ["Abbotsford", "Regina"].into_iter().flat_map(|city| level_manager.get_names_in_city(city));
+        for level_name in level_names {
+            let level = level_manager.get_level(&level_name);
+            let (layout, _) = solve(level, level_manager.color_rules())
+                .unwrap_or_else(|| panic!("no solution found for `{level_name}`"));
+            let mut sim = Simulation::new(
+                &level.level_info, &layout, level.num_rows, level.num_cols, level_manager.color_rules(),
+            ).unwrap();
+            assert_eq!(sim.run(MAX_STEPS), SimStatus::Won, "{level_name}");
+        }
+    }
+
+    #[test]
+    fn border_crashes_are_not_branched_on() {
+        // the train goes through both drawn tracktiles and leaves the yard. Adding a crossing to the last
+        // one can't keep it in the yard.
+        let level_info = vec![
+            PositionedTile { tile: Tile::Trainsink(Trainsink::new(vec![Color::Red], [false, true, false, false])), x: 0, y: 0 },
+            PositionedTile { tile: Tile::Trainsource(Trainsource::new(vec![Color::Red], 1)), x: 0, y: 1 },
+        ];
+        let layout: LevelInfo = (1..3)
+            .map(|x| PositionedTile { tile: Tile::Tracktile(Tracktile::new(Some(Connection { dir1: 3, dir2: 1 }), None)), x, y: 1 })
+            .collect();
+        let sim = Simulation::new(&level_info, &layout, 2, 3, &ColorRules::default()).unwrap();
+        let mut solver = Solver { branches: 0, max_branches: MAX_BRANCHES, max_tracktiles: 6, allow_switches: true };
+
+        assert!(solver.search(sim, NextAction::ProcessTick, 0, layout).is_none());
+        assert_eq!(solver.branches, 0);
+    }
+}
@@ -1,8 +1,11 @@
 use macroquad::prelude::*;
 use macroquad::audio::Sound;
 use macroquad::audio::load_sound_from_bytes as load_sound;
-use macroquad::audio::play_sound_once;
 use crate::color::Color;
+use crate::particle::ParticleDensity;
+
+pub mod mixer;
+use mixer::{Channel, Mixer};
 
 use std::collections::HashMap;
 use strum::IntoEnumIterator;
@@ -51,6 +54,15 @@ impl SoundType {
             Self::WinLevel => gs.sl_win_level,
         }
     }
+
+    pub fn channel(&self) -> Channel {
+        match self {
+            Self::ButtonPress | Self::DrawTrack | Self::EraseTrack => Channel::Ui,
+            Self::TrainBrown | Self::TrainYellow | Self::TrainRed | Self::TrainBlue
+                | Self::TrainPurple | Self::TrainOrange | Self::TrainGreen => Channel::Train,
+            Self::Splitter | Self::Painter | Self::SwitchTrack | Self::Crash | Self::WinLevel => Channel::Effect,
+        }
+    }
 }
 
 
@@ -113,6 +125,9 @@ pub struct GameSprites {
     pub sl_win_level: Sound,
 
     pub sounds_to_play: HashMap<SoundType, bool>,
+    // set from the settings, see settings.rs
+    pub mixer: Mixer,
+    pub particle_density: ParticleDensity,
 
     pub label_font: Font,
 }
@@ -217,6 +232,8 @@ impl GameSprites {
             sl_win_level,
 
             sounds_to_play: HashMap::new(),
+            mixer: Mixer::new(),
+            particle_density: ParticleDensity::Full,
 
             label_font,
         }
@@ -242,7 +259,8 @@ impl GameSprites {
 
     pub fn play_train_sound(&mut self, color:Color) {
         match color {
-            Color::Brown => self.add_sound(SoundType::TrainBrown),
+            // the colors a level pack declares have no sound of their own
+            Color::Brown | Color::Custom(_) => self.add_sound(SoundType::TrainBrown),
             Color::Yellow => self.add_sound(SoundType::TrainYellow),
             Color::Blue => self.add_sound(SoundType::TrainBlue),
             Color::Red => self.add_sound(SoundType::TrainRed),
@@ -257,11 +275,13 @@ impl GameSprites {
     }
 
     pub fn play_sounds(&mut self) {
+        let mut sounds = vec![];
         for sound in SoundType::iter() {
             if *self.sounds_to_play.get(&sound).unwrap_or(&false) {
-                play_sound_once(sound.get_sl_sound(self));
+                sounds.push((sound.channel(), sound.get_sl_sound(self)));
             }
             self.sounds_to_play.insert(sound, false);
         }
+        self.mixer.play(&sounds, get_time());
     }
 }
@@ -0,0 +1,176 @@
+use macroquad::audio::{play_sound, PlaySoundParams, Sound};
+use serde::{Serialize, Deserialize};
+
+use std::collections::VecDeque;
+
+// Every sound goes through one of the channels below, then through the master channel. Each channel has its
+// own volume and can be muted on its own, from the settings screen (see settings.rs).
+// The trains are ducked: when many train sounds start close together, as happens when the yard runs fast
+// forward, each of them is played softer, so that together they stay about as loud as a single one.
+
+// train sounds started less than this many seconds apart count as playing together
+pub const DUCKING_WINDOW: f64 = 0.4;
+// however many train sounds play together, none of them goes softer than this fraction of the train volume
+pub const MIN_DUCKING: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Master,
+    // the buttons, and drawing and erasing tracks
+    Ui,
+    // the trains reaching sinks, and trains meeting
+    Train,
+    // everything else the yard does: splitters, painters, switches, crashes and winning
+    Effect,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ChannelSettings {
+    // from 0 to 1
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings { volume: 1., muted: false }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct MixerSettings {
+    pub master: ChannelSettings,
+    pub ui: ChannelSettings,
+    pub train: ChannelSettings,
+    pub effect: ChannelSettings,
+}
+
+impl MixerSettings {
+    pub fn channel(&self, channel: Channel) -> &ChannelSettings {
+        match channel {
+            Channel::Master => &self.master,
+            Channel::Ui => &self.ui,
+            Channel::Train => &self.train,
+            Channel::Effect => &self.effect,
+        }
+    }
+
+    pub fn channel_mut(&mut self, channel: Channel) -> &mut ChannelSettings {
+        match channel {
+            Channel::Master => &mut self.master,
+            Channel::Ui => &mut self.ui,
+            Channel::Train => &mut self.train,
+            Channel::Effect => &mut self.effect,
+        }
+    }
+
+    pub fn volume(&self, channel: Channel) -> f32 {
+        // the volume a sound of the channel plays at, before ducking
+        let master = self.master;
+        let own = *self.channel(channel);
+        if master.muted || own.muted {
+            0.
+        } else {
+            master.volume * own.volume
+        }
+    }
+}
+
+pub struct Mixer {
+    pub settings: MixerSettings,
+    // when the train sounds of the last DUCKING_WINDOW seconds started
+    recent_train_sounds: VecDeque<f64>,
+}
+
+impl Mixer {
+    pub fn new() -> Mixer {
+        Mixer {
+            settings: MixerSettings::default(),
+            recent_train_sounds: VecDeque::new(),
+        }
+    }
+
+    pub fn ducking(&self, new_train_sounds: usize) -> f32 {
+        // by how much each of the train sounds about to start is scaled. The loudness of sounds playing
+        // together grows about like the square root of their number, so that is what they are divided by.
+        let playing_together = self.recent_train_sounds.len() + new_train_sounds;
+        if playing_together <= 1 {
+            return 1.;
+        }
+        (1. / (playing_together as f32).sqrt()).max(MIN_DUCKING)
+    }
+
+    fn start_train_sounds(&mut self, new_train_sounds: usize, time: f64) -> f32 {
+        // forgets the train sounds that started at least DUCKING_WINDOW seconds before `time`, and remembers
+        // the new ones. Returns the ducking of the new ones.
+        while self.recent_train_sounds.front().is_some_and(|start| time - start >= DUCKING_WINDOW) {
+            self.recent_train_sounds.pop_front();
+        }
+        let ducking = self.ducking(new_train_sounds);
+        self.recent_train_sounds.extend(std::iter::repeat_n(time, new_train_sounds));
+        ducking
+    }
+
+    pub fn play(&mut self, sounds: &[(Channel, Sound)], time: f64) {
+        // plays the sounds that start on this frame. `time` is the time of the frame, from get_time.
+        let new_train_sounds = sounds.iter().filter(|(channel, _)| *channel == Channel::Train).count();
+        let ducking = self.start_train_sounds(new_train_sounds, time);
+        for (channel, sound) in sounds {
+            let mut volume = self.settings.volume(*channel);
+            if *channel == Channel::Train {
+                volume *= ducking;
+            }
+            if volume > 0. {
+                play_sound(*sound, PlaySoundParams { looped: false, volume });
+            }
+        }
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Mixer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_single_train_sound_is_not_ducked() {
+        let mut mixer = Mixer::new();
+        assert_eq!(mixer.ducking(0), 1.);
+        assert_eq!(mixer.ducking(1), 1.);
+        assert_eq!(mixer.start_train_sounds(1, 0.), 1.);
+    }
+
+    #[test]
+    fn ducking_goes_with_the_square_root_of_the_train_sounds() {
+        let mut mixer = Mixer::new();
+        assert_eq!(mixer.start_train_sounds(4, 0.), 0.5);
+        // the sounds that are still playing count too
+        assert_eq!(mixer.start_train_sounds(5, 0.1), 1. / 3.);
+        assert_eq!(mixer.ducking(7), 0.25);
+    }
+
+    #[test]
+    fn ducking_never_goes_below_the_floor() {
+        let mut mixer = Mixer::new();
+        assert_eq!(mixer.start_train_sounds(100, 0.), MIN_DUCKING);
+        assert_eq!(mixer.ducking(1000), MIN_DUCKING);
+    }
+
+    #[test]
+    fn old_train_sounds_stop_counting_after_the_window() {
+        let mut mixer = Mixer::new();
+        mixer.start_train_sounds(3, 0.);
+        assert_eq!(mixer.start_train_sounds(1, DUCKING_WINDOW / 2.), 0.5);
+        // the first three are forgotten, the one started half a window ago still counts
+        assert_eq!(mixer.start_train_sounds(3, DUCKING_WINDOW), 0.5);
+        assert_eq!(mixer.start_train_sounds(0, 2. * DUCKING_WINDOW), 1.);
+        assert!(mixer.recent_train_sounds.is_empty());
+    }
+}
@@ -6,17 +6,32 @@ pub mod tracktile;
 pub mod trainsink;
 pub mod trainsource;
 pub mod rock;
+pub mod tunnel;
+pub mod filter;
+pub mod bridge;
+pub mod station;
+pub mod turntable;
+pub mod oneway;
 
 use crate::color::Color;
+use crate::color::rules::ColorRules;
+use crate::color::glyph::draw_glyph;
 use crate::tile::painter::Painter;
 use crate::tile::splitter::Splitter;
 use crate::tile::tracktile::Tracktile;
 use crate::tile::trainsink::Trainsink;
 use crate::tile::trainsource::Trainsource;
 use crate::tile::rock::Rock;
+use crate::tile::tunnel::Tunnel;
+use crate::tile::filter::Filter;
+use crate::tile::bridge::Bridge;
+use crate::tile::station::Station;
+use crate::tile::turntable::Turntable;
+use crate::tile::oneway::OneWay;
 use crate::sprites::GameSprites;
-use crate::particle::ParticleList;
-use crate::particle::smoke::Smoke;
+use crate::event::EventList;
+
+use std::f32::consts::PI;
 
 
 pub type BorderState = [Option<Color>; 4];
@@ -29,68 +44,31 @@ pub enum Tile {
     Rock(Rock),
     Painter(Painter),
     Splitter(Splitter),
+    Tunnel(Tunnel),
+    Filter(Filter),
+    Bridge(Bridge),
+    Station(Station),
+    Turntable(Turntable),
+    OneWay(OneWay),
 }
 
 impl Tile {
-    pub fn accept_trains(&mut self, trains: BorderState, p: &mut ParticleList, scale: f32) -> bool {
-        let border_state: BorderState;
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        // returns the trains that the tile did not pull in. Each of those trains has crashed.
         match self {
-            Tile::Tracktile(tracktile) => {
-                border_state = tracktile.accept_trains(trains);
-            }
-            Tile::Trainsource(trainsource) => {
-                border_state = trainsource.accept_trains(trains);
-            }
-            Tile::Trainsink(trainsink) => {
-                border_state = trainsink.accept_trains(trains);
-            }
-            Tile::Rock(_) => {
-                border_state = trains;
-            }
-            Tile::Painter(painter) => {
-                border_state = painter.accept_trains(trains);
-            }
-            Tile::Splitter(splitter) => {
-                border_state = splitter.accept_trains(trains);
-            }
+            Tile::Tracktile(tracktile) => tracktile.accept_trains(trains),
+            Tile::Trainsource(trainsource) => trainsource.accept_trains(trains),
+            Tile::Trainsink(trainsink) => trainsink.accept_trains(trains),
+            Tile::Rock(_) => trains,
+            Tile::Painter(painter) => painter.accept_trains(trains),
+            Tile::Splitter(splitter) => splitter.accept_trains(trains),
+            Tile::Tunnel(tunnel) => tunnel.accept_trains(trains),
+            Tile::Filter(filter) => filter.accept_trains(trains),
+            Tile::Bridge(bridge) => bridge.accept_trains(trains),
+            Tile::Station(station) => station.accept_trains(trains),
+            Tile::Turntable(turntable) => turntable.accept_trains(trains),
+            Tile::OneWay(oneway) => oneway.accept_trains(trains),
         }
-        let mut no_crashes = true;
-        let rect = self.get_rect();
-        for (dir, train) in border_state.iter().enumerate() {
-            if let Some(color) = train {
-                no_crashes = false;
-                match dir {
-                    0 => p.push(Box::new(Smoke::new(
-                        rect.x + rect.w / 2.,
-                        rect.y,
-                        *color,
-                        scale,
-                    ))),
-                    1 => p.push(Box::new(Smoke::new(
-                        rect.x + rect.w,
-                        rect.y + rect.h / 2.,
-                        *color,
-                        scale,
-                    ))),
-                    2 => p.push(Box::new(Smoke::new(
-                        rect.x + rect.w / 2.,
-                        rect.y + rect.h,
-                        *color,
-                        scale,
-                    ))),
-                    3 => p.push(Box::new(Smoke::new(
-                        rect.x,
-                        rect.y + rect.h / 2.,
-                        *color,
-                        scale,
-                    ))),
-                    _ => unreachable!(),
-                }
-            }
-        }
-
-
-        return no_crashes;
     }
     pub fn dispatch_trains(&mut self) -> BorderState {
         match self {
@@ -112,48 +90,64 @@ impl Tile {
             Tile::Splitter(splitter) => {
                 splitter.dispatch_trains()
             }
+            Tile::Tunnel(tunnel) => {
+                tunnel.dispatch_trains()
+            }
+            Tile::Filter(filter) => {
+                filter.dispatch_trains()
+            }
+            Tile::Bridge(bridge) => {
+                bridge.dispatch_trains()
+            }
+            Tile::Station(station) => {
+                station.dispatch_trains()
+            }
+            Tile::Turntable(turntable) => {
+                turntable.dispatch_trains()
+            }
+            Tile::OneWay(oneway) => {
+                oneway.dispatch_trains()
+            }
         }
     }
-    pub fn process_end_of_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
-        // the tracktile Tile type is the only one which needs to process things at the end of each tick (merging trains)
+    pub fn process_end_of_tick(&mut self, events: &mut EventList, rules: &ColorRules) {
+        // tracktiles, and the tracks of bridges, are the only ones which need to process things at the end of
+        // each tick (merging trains)
         match self {
-            Tile::Tracktile(tracktile) => {
-                tracktile.interact_trains(gs, p);
-            }
+            Tile::Tracktile(tracktile) => tracktile.interact_trains(events, rules),
+            Tile::Bridge(bridge) => bridge.interact_trains(events, rules),
             _ => {}
         }
     }
 
-    pub fn process_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
+    pub fn process_tick(&mut self, events: &mut EventList, rules: &ColorRules) {
+        // trains going through tunnels are moved by the simulation, see Simulation::pass_trains_through_tunnels.
         match self {
             Tile::Tracktile(tracktile) => {
-                tracktile.process_tick(gs, p);
+                tracktile.process_tick(events, rules);
             }
             Tile::Trainsource(trainsource) => {
-                trainsource.process_tick(p);
+                trainsource.process_tick(events);
             }
             Tile::Trainsink(trainsink) => {
-                trainsink.process_tick(gs, p);
+                trainsink.process_tick(events);
             }
             Tile::Painter(painter) => {
-                painter.process_tick(gs, p)
+                painter.process_tick(events)
             }
             Tile::Splitter(splitter) => {
-                splitter.process_tick(gs, p)
+                splitter.process_tick(events, rules)
             }
-            Tile::Rock(_) => {}
-        }
-    }
-    
-    pub fn get_char(&self) -> char {
-        // a deprecated method for rendering a tile to the console screen.
-        match self {
-            Tile::Tracktile(tracktile) => tracktile.connection_type().get_char(),
-            Tile::Trainsource(_) => 'S',
-            Tile::Trainsink(_) => 'S',
-            Tile::Rock(_) => 'R',
-            Tile::Painter(_) => 'P',
-            Tile::Splitter(_) => 'C',
+            Tile::Bridge(bridge) => {
+                bridge.process_tick(events, rules)
+            }
+            Tile::Station(station) => {
+                station.process_tick()
+            }
+            Tile::Turntable(turntable) => {
+                turntable.process_tick(events, rules)
+            }
+            Tile::Rock(_) | Tile::Tunnel(_) | Tile::Filter(_) | Tile::OneWay(_) => {}
         }
     }
 
@@ -174,6 +168,24 @@ impl Tile {
             Tile::Splitter(splitter) => {
                 splitter.render_trains(gs, progress)
             }
+            Tile::Tunnel(tunnel) => {
+                tunnel.render_trains(gs, progress)
+            }
+            Tile::Filter(filter) => {
+                filter.render_trains(gs, progress)
+            }
+            Tile::Bridge(bridge) => {
+                bridge.render_trains(gs, progress)
+            }
+            Tile::Station(station) => {
+                station.render_trains(gs, progress)
+            }
+            Tile::Turntable(turntable) => {
+                turntable.render_trains(gs, progress)
+            }
+            Tile::OneWay(oneway) => {
+                oneway.render_trains(gs, progress)
+            }
             Tile::Rock(_) => {}
         }
     }
@@ -195,6 +207,24 @@ impl Tile {
             Tile::Splitter(splitter) => {
                 splitter.set_rect(rect, gs);
             }
+            Tile::Tunnel(tunnel) => {
+                tunnel.set_rect(rect, gs);
+            }
+            Tile::Filter(filter) => {
+                filter.set_rect(rect, gs);
+            }
+            Tile::Bridge(bridge) => {
+                bridge.set_rect(rect, gs);
+            }
+            Tile::Station(station) => {
+                station.set_rect(rect, gs);
+            }
+            Tile::Turntable(turntable) => {
+                turntable.set_rect(rect, gs);
+            }
+            Tile::OneWay(oneway) => {
+                oneway.set_rect(rect, gs);
+            }
             Tile::Rock(rock) => {
                 rock.set_rect(rect);
             }
@@ -218,9 +248,54 @@ impl Tile {
             Tile::Splitter(splitter) => {
                 splitter.rect.unwrap()
             }
+            Tile::Tunnel(tunnel) => {
+                tunnel.rect.unwrap()
+            }
+            Tile::Filter(filter) => {
+                filter.rect.unwrap()
+            }
+            Tile::Bridge(bridge) => {
+                bridge.rect.unwrap()
+            }
+            Tile::Station(station) => {
+                station.rect.unwrap()
+            }
+            Tile::Turntable(turntable) => {
+                turntable.rect.unwrap()
+            }
+            Tile::OneWay(oneway) => {
+                oneway.rect.unwrap()
+            }
             Tile::Rock(rock) => {
                 rock.rect.unwrap()
             }
         }
     }
 }
+
+pub fn render_train(gs: &GameSprites, rect: Rect, scale: f32, color: Color, side: u8, entering: bool, progress: f32) {
+    // draws a train going straight between side `side` of the tile and its center. An entering train goes from
+    // the side to the center while progress goes from 0 to 1, and a leaving train goes from the center to the
+    // side while progress goes from 1 to 2.
+    let (distance, moving_dir) = if entering {(1. - progress, (side + 2) % 4)} else {(progress - 1., side)};
+    let (dx, dy) = [(0., -1.), (1., 0.), (0., 1.), (-1., 0.)][side as usize];
+    let train_center_x = rect.x + rect.w/2. * (1. + dx * distance);
+    let train_center_y = rect.y + rect.h/2. * (1. + dy * distance);
+    let train_width = scale * gs.train.width();
+    let train_height = scale * gs.train.height();
+    draw_texture_ex(
+        gs.train,
+        train_center_x - (train_width/2.),
+        train_center_y - (train_height/2.),
+        color.get_color(),
+        DrawTextureParams {
+            dest_size: Some(Vec2::new(train_width, train_height)),
+            source: None,
+            rotation: moving_dir as f32 * PI/2.,
+            flip_x: false,
+            flip_y: false,
+            pivot: None,
+        }
+    );
+    draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
+}
@@ -0,0 +1,116 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::color::rules::ColorRules;
+use crate::connection::Connection;
+use crate::event::EventList;
+use crate::tile::tracktile::Tracktile;
+use crate::tile::BorderState;
+use crate::sprites::GameSprites;
+
+// A bridge carries one straight track over another, perpendicular one. Unlike on an H tracktile, a train on
+// the bridge and a train under it never meet: they neither mix nor crash, whenever they go through.
+// Each level is its own straight tracktile, so two trains on the same level still follow the usual rules
+// of a straight track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bridge {
+    // the upper track goes from this side to the opposite one. It is always 0 (up) or 1 (right).
+    pub upper_dir: u8,
+    upper: Tracktile,
+    lower: Tracktile,
+    #[serde(skip)]
+    pub rect: Option<Rect>,
+    scale: f32,
+}
+
+impl Bridge {
+    pub fn new(upper_dir: u8) -> Bridge {
+        let upper_dir = upper_dir % 2;
+        let lower_dir = upper_dir + 1;
+        Bridge {
+            upper_dir,
+            upper: Tracktile::new(Some(Connection { dir1: upper_dir, dir2: upper_dir + 2 }), None),
+            lower: Tracktile::new(Some(Connection { dir1: lower_dir, dir2: (lower_dir + 2) % 4 }), None),
+            rect: None,
+            scale: 1.,
+        }
+    }
+
+    fn is_upper(&self, dir: usize) -> bool {
+        dir as u8 % 2 == self.upper_dir
+    }
+
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        // every side belongs to exactly one of the two tracks, so no train can crash here.
+        let mut upper_trains = [None, None, None, None];
+        let mut lower_trains = [None, None, None, None];
+        for (dir, train) in trains.iter().enumerate() {
+            if self.is_upper(dir) {
+                upper_trains[dir] = *train;
+            } else {
+                lower_trains[dir] = *train;
+            }
+        }
+        let upper_crashed = self.upper.accept_trains(upper_trains);
+        let lower_crashed = self.lower.accept_trains(lower_trains);
+        [0, 1, 2, 3].map(|dir| upper_crashed[dir].or(lower_crashed[dir]))
+    }
+
+    pub fn process_tick(&mut self, events: &mut EventList, rules: &ColorRules) {
+        self.upper.process_tick(events, rules);
+        self.lower.process_tick(events, rules);
+    }
+
+    pub fn interact_trains(&mut self, events: &mut EventList, rules: &ColorRules) {
+        self.upper.interact_trains(events, rules);
+        self.lower.interact_trains(events, rules);
+    }
+
+    pub fn dispatch_trains(&mut self) -> BorderState {
+        let upper_trains = self.upper.dispatch_trains();
+        let lower_trains = self.lower.dispatch_trains();
+        [0, 1, 2, 3].map(|dir| upper_trains[dir].or(lower_trains[dir]))
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.rect = Some(rect);
+        self.scale = rect.w / gs.tracktile_blank.width();
+        self.upper.set_rect(rect, gs);
+        self.lower.set_rect(rect, gs);
+    }
+
+    pub fn render_trains(&self, gs: &GameSprites, progress: f32) {
+        // the deck of the bridge is drawn here, between the trains under it and the trains on it.
+        // The lower track itself is drawn by the yard along with the other tiles.
+        let rect = self.rect.unwrap();
+        self.lower.render_trains(gs, progress);
+
+        let deck_width = rect.w * 0.5;
+        let rail_width = rect.w * 0.06;
+        let (deck, shadow, rails) = if self.upper_dir == 0 {
+            let deck = Rect::new(rect.x + (rect.w - deck_width) / 2., rect.y, deck_width, rect.h);
+            let shadow = Rect::new(deck.x + rail_width, deck.y, deck.w, deck.h);
+            let rails = [
+                Rect::new(deck.x, deck.y, rail_width, deck.h),
+                Rect::new(deck.x + deck.w - rail_width, deck.y, rail_width, deck.h),
+            ];
+            (deck, shadow, rails)
+        } else {
+            let deck = Rect::new(rect.x, rect.y + (rect.h - deck_width) / 2., rect.w, deck_width);
+            let shadow = Rect::new(deck.x, deck.y + rail_width, deck.w, deck.h);
+            let rails = [
+                Rect::new(deck.x, deck.y, deck.w, rail_width),
+                Rect::new(deck.x, deck.y + deck.h - rail_width, deck.w, rail_width),
+            ];
+            (deck, shadow, rails)
+        };
+        // the shadow of the deck falls on the lower track
+        draw_rectangle(shadow.x, shadow.y, shadow.w, shadow.h, Color::new(0., 0., 0., 0.3));
+        draw_rectangle(deck.x, deck.y, deck.w, deck.h, GRAY);
+        for rail in rails {
+            draw_rectangle(rail.x, rail.y, rail.w, rail.h, DARKGRAY);
+        }
+
+        self.upper.render_trains(gs, progress);
+    }
+}
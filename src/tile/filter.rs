@@ -0,0 +1,73 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::color::Color;
+
+use crate::connection::Connection;
+use crate::tile::{render_train, BorderState};
+use crate::sprites::GameSprites;
+
+// A filter is a straight or bent track that only trains of its color can go through. Any other train
+// crashes when it tries to enter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Filter {
+    pub connection: Connection,
+    pub color: Color,
+    pub train_to_dir1: Option<Color>,
+    pub train_to_dir2: Option<Color>,
+    #[serde(skip)]
+    pub rect: Option<Rect>,
+    scale: f32,
+}
+
+impl Filter {
+    pub fn new(conn: Connection, color: Color) -> Filter {
+        Filter {
+            connection: conn,
+            color,
+            train_to_dir1: None,
+            train_to_dir2: None,
+            rect: None,
+            scale: 1.,
+        }
+    }
+
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        let mut border_state = [None, None, None, None];
+        for (dir, train) in trains.iter().enumerate() {
+            let passes = train.is_some_and(|color| color == self.color);
+            if passes && dir as u8 == self.connection.dir1 {
+                self.train_to_dir2 = *train;
+            } else if passes && dir as u8 == self.connection.dir2 {
+                self.train_to_dir1 = *train;
+            } else if train.is_some() {
+                border_state[dir] = *train;
+            }
+        }
+        border_state
+    }
+
+    pub fn dispatch_trains(&mut self) -> BorderState {
+        let mut border_state = [None, None, None, None];
+        border_state[self.connection.dir1 as usize] = self.train_to_dir1.take();
+        border_state[self.connection.dir2 as usize] = self.train_to_dir2.take();
+        border_state
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.rect = Some(rect);
+        self.scale = rect.w / gs.tracktile_blank.width();
+    }
+
+    pub fn render_trains(&self, gs: &GameSprites, progress: f32) {
+        let rect = self.rect.unwrap();
+        let entering = progress <= 1.0;
+        if let Some(color) = self.train_to_dir2 {
+            let side = if entering {self.connection.dir1} else {self.connection.dir2};
+            render_train(gs, rect, self.scale, color, side, entering, progress);
+        }
+        if let Some(color) = self.train_to_dir1 {
+            let side = if entering {self.connection.dir2} else {self.connection.dir1};
+            render_train(gs, rect, self.scale, color, side, entering, progress);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::color::Color;
+
+use crate::connection::DirectedConnection;
+use crate::tile::{render_train, BorderState};
+use crate::sprites::GameSprites;
+
+// A one-way track only takes trains that come in through the start of its connection, and sends them out
+// through the end. A train coming the other way, or from any other side, crashes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OneWay {
+    pub connection: DirectedConnection,
+    pub train: Option<Color>,
+    #[serde(skip)]
+    pub rect: Option<Rect>,
+    scale: f32,
+}
+
+impl OneWay {
+    pub fn new(connection: DirectedConnection) -> OneWay {
+        OneWay {
+            connection,
+            train: None,
+            rect: None,
+            scale: 1.,
+        }
+    }
+
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        let mut border_state = trains;
+        self.train = border_state[self.connection.from as usize].take();
+        border_state
+    }
+
+    pub fn dispatch_trains(&mut self) -> BorderState {
+        let mut border_state = [None, None, None, None];
+        border_state[self.connection.to as usize] = self.train.take();
+        border_state
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.rect = Some(rect);
+        self.scale = rect.w / gs.tracktile_blank.width();
+    }
+
+    pub fn render_trains(&self, gs: &GameSprites, progress: f32) {
+        let rect = self.rect.unwrap();
+        if let Some(color) = self.train {
+            if progress <= 1.0 {
+                render_train(gs, rect, self.scale, color, self.connection.from, true, progress);
+            } else {
+                render_train(gs, rect, self.scale, color, self.connection.to, false, progress);
+            }
+        }
+    }
+
+    pub fn render_arrows(&self) {
+        // an arrow on each half of the track, pointing the way the trains have to go.
+        let rect = self.rect.unwrap();
+        let (center_x, center_y) = (rect.x + rect.w / 2., rect.y + rect.h / 2.);
+        let offset = |dir: u8| [(0., -1.), (1., 0.), (0., 1.), (-1., 0.)][dir as usize];
+        let size = rect.w * 0.1;
+        for (side, moving_dir) in [(self.connection.from, (self.connection.from + 2) % 4), (self.connection.to, self.connection.to)] {
+            let (dx, dy) = offset(side);
+            let (x, y) = (center_x + dx * rect.w * 0.25, center_y + dy * rect.h * 0.25);
+            let (fx, fy) = offset(moving_dir);
+            let tip = Vec2::new(x + fx * size, y + fy * size);
+            let left = Vec2::new(x - fx * size - fy * size, y - fy * size + fx * size);
+            let right = Vec2::new(x - fx * size + fy * size, y - fy * size - fx * size);
+            draw_triangle(tip, left, right, GOLD);
+            draw_triangle_lines(tip, left, right, 1., BLACK);
+        }
+    }
+}
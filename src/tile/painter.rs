@@ -2,14 +2,13 @@ use macroquad::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
 use crate::color::Color;
+use crate::color::glyph::draw_glyph;
 
 use crate::connection::Connection;
 use crate::tile::BorderState;
 
 use crate::sprites::GameSprites;
-use crate::sprites::SoundType;
-use crate::particle::ParticleList;
-use crate::particle::painter_particle::PainterParticle;
+use crate::event::{EventKind, EventList};
 
 use std::f32::consts::PI;
 
@@ -62,20 +61,15 @@ impl Painter {
         }
         border_state
     }
-    pub fn process_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
+    pub fn process_tick(&mut self, events: &mut EventList) {
         if self.train_to_dir1.is_some() {
             self.train_to_dir1 = Some(self.color);
-            gs.add_sound(SoundType::Painter);
         }
         if self.train_to_dir2.is_some() {
             self.train_to_dir2 = Some(self.color);
-            gs.add_sound(SoundType::Painter);
         }
         if self.train_to_dir1.is_some() || self.train_to_dir2.is_some() {
-            p.push(Box::new(PainterParticle::new(
-                self.rect.unwrap(),
-                self.color,
-            )));
+            events.push(EventKind::Painted { color: self.color });
         }
     }
 
@@ -121,6 +115,7 @@ impl Painter {
                     color.get_color(),
                     DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
                 );
+                draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
             }
             if let Some(color) = self.train_to_dir1 {
                 let train_center_x;
@@ -150,6 +145,7 @@ impl Painter {
                     color.get_color(),
                     DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
                 );
+                draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
             }
         } else {
             //render the outgoing trains
@@ -183,6 +179,7 @@ impl Painter {
                     color.get_color(),
                     DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
                 );
+                draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
             }
             if let Some(color) = self.train_to_dir2 {
                 let train_center_x;
@@ -212,6 +209,7 @@ impl Painter {
                     color.get_color(),
                     DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
                 );
+                draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
             }
         }
     }
@@ -2,11 +2,11 @@ use macroquad::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
 use crate::color::Color;
+use crate::color::rules::ColorRules;
+use crate::color::glyph::draw_glyph;
 use crate::tile::BorderState;
 use crate::sprites::GameSprites;
-use crate::sprites::SoundType;
-use crate::particle::ParticleList;
-use crate::particle::splitter_particle::SplitterParticle;
+use crate::event::{EventKind, EventList};
 
 use std::f32::consts::PI;
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,32 +57,13 @@ impl Splitter {
         border_state
     }
 
-    pub fn process_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
+    pub fn process_tick(&mut self, events: &mut EventList, rules: &ColorRules) {
         if let Some(color) = self.incoming_train {
             self.incoming_train = None;
-            match color {
-                Color::Brown | Color::Blue | Color::Red | Color::Yellow => {
-                    self.train_going_left = Some(color);
-                    self.train_going_right = Some(color);
-                }
-                Color::Orange => {
-                    self.train_going_left = Some(Color::Yellow);
-                    self.train_going_right = Some(Color::Red);
-                }
-                Color::Purple => {
-                    self.train_going_left = Some(Color::Blue);
-                    self.train_going_right = Some(Color::Red);
-                }
-                Color::Green => {
-                    self.train_going_left = Some(Color::Blue);
-                    self.train_going_right = Some(Color::Yellow);
-                }
-            }
-            p.push(Box::new(SplitterParticle::new(
-                self.rect.unwrap(),
-                self.incoming_dir,
-            )));
-            gs.add_sound(SoundType::Splitter);
+            let [left, right] = rules.split(color);
+            self.train_going_left = Some(left);
+            self.train_going_right = Some(right);
+            events.push(EventKind::Split { dir: self.incoming_dir });
         }
     }
 
@@ -129,6 +110,7 @@ impl Splitter {
                 color.get_color(),
                 DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
             );
+            draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
         }
         if let Some(color) = self.train_going_left {
             let train_center_x;
@@ -158,6 +140,7 @@ impl Splitter {
                 color.get_color(),
                 DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
             );
+            draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
         }
 
         if let Some(color) = self.train_going_right {
@@ -188,6 +171,7 @@ impl Splitter {
                 color.get_color(),
                 DrawTextureParams { dest_size, source: None, rotation: rot, flip_x: false, flip_y: false, pivot: None }
             );
+            draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
         }
     }
 }
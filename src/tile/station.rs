@@ -0,0 +1,122 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::color::Color;
+use crate::color::glyph::draw_glyph;
+
+use std::collections::VecDeque;
+
+use crate::tile::{render_train, BorderState};
+use crate::sprites::GameSprites;
+
+// the countdown of a station only has room for one digit
+pub const MAX_DELAY: u32 = 9;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WaitingTrain {
+    pub color: Color,
+    pub ticks_left: u32,
+}
+
+// A station holds every train that arrives through its entry for `delay` more ticks than a track would,
+// then sends it out through its exit. Trains leave in the order they arrived, at most one per tick,
+// so a train can wait longer than `delay` when the trains before it are still leaving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Station {
+    pub entry_dir: u8,
+    pub exit_dir: u8,
+    pub delay: u32,
+    pub incoming_train: Option<Color>,
+    pub waiting_trains: VecDeque<WaitingTrain>,
+    pub outgoing_train: Option<Color>,
+    #[serde(skip)]
+    pub rect: Option<Rect>,
+    scale: f32,
+}
+
+impl Station {
+    pub fn new(entry_dir: u8, exit_dir: u8, delay: u32) -> Station {
+        Station {
+            entry_dir,
+            exit_dir,
+            delay,
+            incoming_train: None,
+            waiting_trains: VecDeque::new(),
+            outgoing_train: None,
+            rect: None,
+            scale: 1.,
+        }
+    }
+
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        let mut border_state = trains;
+        self.incoming_train = border_state[self.entry_dir as usize].take();
+        border_state
+    }
+
+    pub fn process_tick(&mut self) {
+        // the train that just reached the middle of the station starts waiting, and the first train
+        // that is done waiting starts leaving.
+        for train in &mut self.waiting_trains {
+            train.ticks_left = train.ticks_left.saturating_sub(1);
+        }
+        if let Some(color) = self.incoming_train.take() {
+            self.waiting_trains.push_back(WaitingTrain { color, ticks_left: self.delay });
+        }
+        if self.waiting_trains.front().is_some_and(|train| train.ticks_left == 0) {
+            self.outgoing_train = self.waiting_trains.pop_front().map(|train| train.color);
+        }
+    }
+
+    pub fn dispatch_trains(&mut self) -> BorderState {
+        let mut border_state = [None, None, None, None];
+        border_state[self.exit_dir as usize] = self.outgoing_train.take();
+        border_state
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.incoming_train.is_none() && self.waiting_trains.is_empty() && self.outgoing_train.is_none()
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.rect = Some(rect);
+        self.scale = rect.w / gs.tracktile_blank.width();
+    }
+
+    pub fn render_trains(&self, gs: &GameSprites, progress: f32) {
+        let rect = self.rect.unwrap();
+        if let Some(color) = self.incoming_train {
+            render_train(gs, rect, self.scale, color, self.entry_dir, true, progress);
+        }
+        if let Some(color) = self.outgoing_train {
+            render_train(gs, rect, self.scale, color, self.exit_dir, false, progress);
+        }
+    }
+
+    pub fn render_countdown(&self) {
+        // the number of ticks until the next train leaves, on a dial in the color of that train.
+        // Each train waiting behind it is a dot under the dial. An empty station shows its delay.
+        let rect = self.rect.unwrap();
+        let (center_x, center_y) = (rect.x + rect.w / 2., rect.y + rect.h / 2.);
+        let radius = rect.w * 0.25;
+        let (text, dial_color) = match self.waiting_trains.front() {
+            Some(train) => (train.ticks_left.to_string(), train.color.get_color()),
+            None => (self.delay.to_string(), LIGHTGRAY),
+        };
+        draw_circle(center_x, center_y, radius, dial_color);
+        draw_circle_lines(center_x, center_y, radius, rect.w * 0.03, BLACK);
+        let font_size = rect.h * 0.35;
+        let text_size = measure_text(&text, None, font_size as u16, 1.);
+        draw_text(&text, center_x - text_size.width / 2., center_y + text_size.height / 2., font_size, BLACK);
+        if let Some(train) = self.waiting_trains.front() {
+            draw_glyph(train.color, center_x, center_y - radius, radius * 0.5, 1.);
+        }
+
+        let dot_radius = rect.w * 0.035;
+        let num_dots = self.waiting_trains.len().saturating_sub(1);
+        for (index, train) in self.waiting_trains.iter().skip(1).enumerate() {
+            let x = center_x + (index as f32 - (num_dots as f32 - 1.) / 2.) * dot_radius * 3.;
+            draw_circle(x, center_y + radius + dot_radius * 2., dot_radius, train.color.get_color());
+            draw_glyph(train.color, x, center_y + radius + dot_radius * 2., dot_radius * 1.2, 1.);
+        }
+    }
+}
@@ -3,20 +3,19 @@ use serde::{Serialize, Deserialize};
 use std::f32::consts::{PI, SQRT_2};
 
 use crate::color::Color;
+use crate::color::rules::ColorRules;
+use crate::color::glyph::draw_glyph;
 use crate::connection::Connection;
-use crate::particle::ParticleList;
-use crate::particle::fire::Fire;
+use crate::event::{EventKind, EventList};
 use crate::tile::BorderState;
-use crate::utils::direction_midpoint;
 use crate::sprites::GameSprites;
-use crate::sprites::SoundType;
 
 // used for storing a train in a Tracktile
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Train {
-    color: Color,
-    source: u8,
-    destination: u8,
+    pub color: Color,
+    pub source: u8,
+    pub destination: u8,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,19 +37,6 @@ pub enum ConnectionType {
     M,
     J,
 }
-impl ConnectionType {
-    pub fn get_char(&self) -> char {
-        match self {
-            ConnectionType::None => '_',
-            ConnectionType::S => 'S',
-            ConnectionType::B => 'B',
-            ConnectionType::H => 'H',
-            ConnectionType::Z => 'Z',
-            ConnectionType::M => 'M',
-            ConnectionType::J => 'J',
-        }
-    }
-}
 
 impl Tracktile {
     pub fn new(
@@ -79,17 +65,21 @@ impl Tracktile {
         }
         false
     }
-    pub fn switch_active_passive(&mut self, gs: &mut GameSprites) {
+    pub fn switch_active_passive(&mut self) -> bool {
         // this function is called whenever an odd number of trains rolls through a tracktile
         // or when a user double clicks a tile when drawing
         // if there is no passive connection, then we do nothing
-        let c = self.connection_type();
-        if c == ConnectionType::M || c == ConnectionType::J {
-            let temp = self.passive_connection;
-            self.passive_connection = self.active_connection;
-            self.active_connection = temp;
-            gs.add_sound(SoundType::SwitchTrack);
+        // returns true iff the connections were switched.
+        if self.is_switch() {
+            std::mem::swap(&mut self.passive_connection, &mut self.active_connection);
+            return true;
         }
+        false
+    }
+
+    pub fn is_switch(&self) -> bool {
+        // only M and J tracktiles switch. H and Z tracktiles have two connections too, but they just cross.
+        matches!(self.connection_type(), ConnectionType::M | ConnectionType::J)
     }
 
     fn has_connections(&self, c1: Connection, c2: Connection) -> bool {
@@ -237,9 +227,8 @@ impl Tracktile {
         unreachable!()
     }
 
-    pub fn process_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
+    pub fn process_tick(&mut self, events: &mut EventList, rules: &ColorRules) {
         // This function mixes any train colors (happens when trains are halfway through the tile)
-        let scale = self.scale;
         let my_type = self.connection_type();
         if self.trains.len() >= 2 {
             if my_type == ConnectionType::H
@@ -248,15 +237,14 @@ impl Tracktile {
             {
                 // simply mix all the trains in these connection types
                 let new_color =
-                    Color::mix_many(self.trains.iter().map(|train| train.color).collect());
-                for i in 0..self.trains.len() {
-                    self.trains[i].color = new_color;
+                    rules.mix_many(&self.trains.iter().map(|train| train.color).collect::<Vec<_>>());
+                for train in &mut self.trains {
+                    train.color = new_color;
                 }
-                gs.play_train_sound(new_color);
-                let (x, y) = get_midpoint_of_conn(self.active_connection.unwrap(), self.rect.unwrap());
-                p.push(Box::new(Fire::new(
-                    x, y, new_color, scale
-                )));
+                events.push(EventKind::TrackMix {
+                    conn: self.active_connection.unwrap(),
+                    color: new_color,
+                });
                 return;
             }
 
@@ -266,28 +254,26 @@ impl Tracktile {
                 // first do mixing on Active Connection
                 if self.indices_of_trains_along(self.active_connection.unwrap(), &mut i1, &mut i2) {
                     let new_color =
-                        Color::mix_many(vec![self.trains[i1].color, self.trains[i2].color]);
+                        rules.mix_many(&[self.trains[i1].color, self.trains[i2].color]);
                     self.trains[i1].color = new_color;
                     self.trains[i2].color = new_color;
-                    gs.play_train_sound(new_color);
-                    let (x, y) = get_midpoint_of_conn(self.active_connection.unwrap(), self.rect.unwrap());
-                    p.push(Box::new(Fire::new(
-                        x, y, new_color, scale
-                    )));
+                    events.push(EventKind::TrackMix {
+                        conn: self.active_connection.unwrap(),
+                        color: new_color,
+                    });
 
                 }
                 // then do mixing on Passive Connection
                 if self.indices_of_trains_along(self.passive_connection.unwrap(), &mut i1, &mut i2)
                 {
                     let new_color =
-                        Color::mix_many(vec![self.trains[i1].color, self.trains[i2].color]);
+                        rules.mix_many(&[self.trains[i1].color, self.trains[i2].color]);
                     self.trains[i1].color = new_color;
                     self.trains[i2].color = new_color;
-                    gs.play_train_sound(new_color);
-                    let (x, y) = get_midpoint_of_conn(self.passive_connection.unwrap(), self.rect.unwrap());
-                    p.push(Box::new(Fire::new(
-                        x, y, new_color, scale
-                    )));
+                    events.push(EventKind::TrackMix {
+                        conn: self.passive_connection.unwrap(),
+                        color: new_color,
+                    });
                 }
                 return;
             }
@@ -296,20 +282,19 @@ impl Tracktile {
                 let mut i1: usize = 0;
                 let mut i2: usize = 0;
                 if self.indices_of_trains_along(self.active_connection.unwrap(), &mut i1, &mut i2) {
-                    let new_color = Color::mix_many(vec![self.trains[i1].color, self.trains[i2].color]);
+                    let new_color = rules.mix_many(&[self.trains[i1].color, self.trains[i2].color]);
                     self.trains[i1].color = new_color;
                     self.trains[i2].color = new_color;
-                    gs.play_train_sound(new_color);
-                    let (x, y) = get_midpoint_of_conn(self.active_connection.unwrap(), self.rect.unwrap());
-                    p.push(Box::new(Fire::new(
-                        x, y, new_color, scale
-                    )));
+                    events.push(EventKind::TrackMix {
+                        conn: self.active_connection.unwrap(),
+                        color: new_color,
+                    });
                 }
             }
         }
     }
 
-    pub fn interact_trains(&mut self, gs:&mut GameSprites, p: &mut ParticleList) {
+    pub fn interact_trains(&mut self, events: &mut EventList, rules: &ColorRules) {
         // This function merges trains (happens at the moment trains are exiting the tile)
         let my_type = self.connection_type();
 
@@ -336,16 +321,11 @@ impl Tracktile {
 
                     if self.trains[i1].destination == self.trains[i2].destination {
                         let new_color =
-                            Color::mix_many(vec![self.trains[i1].color, self.trains[i2].color]);
+                            rules.mix_many(&[self.trains[i1].color, self.trains[i2].color]);
                         self.trains[i1].color = new_color;
-                        self.trains.remove(i2);
-                        gs.play_train_sound(new_color);
-
                         let dir = self.trains[i1].destination;
-                        let (x, y) = direction_midpoint(self.rect.unwrap(), dir);
-                        p.push(Box::new(Fire::new(
-                            x, y, new_color, self.scale
-                        )));
+                        self.trains.remove(i2);
+                        events.push(EventKind::TrackMerge { dir, color: new_color });
 
                         break 'outer;
                     }
@@ -353,8 +333,8 @@ impl Tracktile {
             }
         }
 
-        if need_to_switch_active_passive {
-            self.switch_active_passive(gs);
+        if need_to_switch_active_passive && self.switch_active_passive() {
+            events.push(EventKind::SwitchFlipped);
         }
     }
 
@@ -416,6 +396,19 @@ impl Tracktile {
         [None, None, None, None]
     }
 
+    pub fn exit_dir(&self, dir: u8) -> u8 {
+        // the direction a train entering through dir leaves in, or dir itself if the train would crash.
+        for conn in [self.active_connection, self.passive_connection].into_iter().flatten() {
+            if conn.dir1 == dir {
+                return conn.dir2;
+            }
+            if conn.dir2 == dir {
+                return conn.dir1;
+            }
+        }
+        dir
+    }
+
     pub fn dispatch_trains(&mut self) -> BorderState {
         // we panic if two trains have the same destination, since we should have dealt with that already,
         let mut res = [None, None, None, None];
@@ -429,19 +422,45 @@ impl Tracktile {
         res
     }
 
-    pub fn add_connection(&mut self, conn: Connection, gs: &mut GameSprites) {
+    pub fn add_connection(&mut self, conn: Connection) {
         self.passive_connection = self.active_connection;
         self.active_connection = Some(conn);
         if self.active_connection == self.passive_connection {
             self.passive_connection = None;
         }
-        gs.add_sound(SoundType::DrawTrack);
     }
 
     pub fn clear_trains(&mut self) {
         self.trains = vec![];
     }
 
+    pub fn trains(&self) -> &[Train] {
+        &self.trains
+    }
+
+    pub fn active_connection(&self) -> Option<Connection> {
+        self.active_connection
+    }
+
+    pub fn passive_connection(&self) -> Option<Connection> {
+        self.passive_connection
+    }
+
+    pub fn set_connections(&mut self, active: Option<Connection>, passive: Option<Connection>) {
+        self.active_connection = active;
+        self.passive_connection = passive;
+    }
+
+    pub fn has_same_connections(&self, other: &Tracktile) -> bool {
+        // H and Z tracktiles never switch, so the order of their connections does not matter.
+        match (other.active_connection, other.passive_connection) {
+            (Some(active), Some(passive)) if matches!(self.connection_type(), ConnectionType::H | ConnectionType::Z) => {
+                self.has_connections(active, passive)
+            }
+            _ => self.active_connection == other.active_connection && self.passive_connection == other.passive_connection,
+        }
+    }
+
     pub fn clear_connections(&mut self) {
         self.active_connection = None;
         self.passive_connection = None;
@@ -542,6 +561,7 @@ impl Tracktile {
                     pivot: None
                 }
              );
+            draw_glyph(train.color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
         }
     }
 }
@@ -572,4 +592,30 @@ pub fn get_midpoint_of_conn(conn: Connection, rect: Rect) -> (f32, f32) {
     }
 
     unreachable!()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracktile(active: (u8, u8), passive: (u8, u8)) -> Tracktile {
+        Tracktile::new(
+            Some(Connection { dir1: active.0, dir2: active.1 }),
+            Some(Connection { dir1: passive.0, dir2: passive.1 }),
+        )
+    }
+
+    #[test]
+    fn only_m_and_j_tracktiles_are_switches() {
+        let crossings = [tracktile((0, 2), (1, 3)), tracktile((0, 1), (2, 3))];
+        let switches = [tracktile((0, 2), (0, 1)), tracktile((0, 1), (0, 3))];
+        for tracktile in crossings {
+            assert!(matches!(tracktile.connection_type(), ConnectionType::H | ConnectionType::Z));
+            assert!(!tracktile.is_switch());
+        }
+        for tracktile in switches {
+            assert!(matches!(tracktile.connection_type(), ConnectionType::M | ConnectionType::J));
+            assert!(tracktile.is_switch());
+        }
+        assert!(!Tracktile::new(Some(Connection { dir1: 0, dir2: 2 }), None).is_switch());
+    }
+}
@@ -1,12 +1,11 @@
 use macroquad::prelude::*;
 use serde::{Serialize, Deserialize};
 use crate::color::Color;
+use crate::color::glyph::draw_glyph;
 
-use crate::particle::sparkle::Sparkle;
 use crate::tile::BorderState;
 use crate::sprites::GameSprites;
-use crate::particle::ParticleList;
-use crate::particle::shrinking_circle::ShrinkingCircle;
+use crate::event::{EventKind, EventList};
 
 use std::f32::consts::PI;
 
@@ -68,31 +67,16 @@ impl Trainsink {
         border_state
     }
 
-    pub fn process_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
+    pub fn process_tick(&mut self, events: &mut EventList) {
         // when processing the tick, we update our public desires
         // this happens when the trains reach the center of the trainsink
         for index in 0..self.desires.len() {
-            if self.desires[index].is_some() && self.private_desires[index].is_none() {
-                let color = self.desires[index].unwrap();
-                p.push(Box::new(ShrinkingCircle::new(
-                    self.icon_rects[index], 
-                    color,
-                )));
-                let rect = self.rect.unwrap();
-                let center_x = rect.x + rect.w/2.;
-                let center_y = rect.y + rect.h/2.;
-                p.push(Box::new(Sparkle::new(
-                    center_x, center_y, color, self.scale
-                )));
+            if let (Some(color), None) = (self.desires[index], self.private_desires[index]) {
+                events.push(EventKind::SinkSatisfied { index, color });
             }
         }
         
         self.desires = self.private_desires.clone();
-        for train in self.incoming_trains {
-            if let Some(color) = train {
-                gs.play_train_sound(color);
-            }
-        }
         self.incoming_trains = [None,None,None,None]
     }
 
@@ -101,12 +85,7 @@ impl Trainsink {
     }
 
     pub fn is_satisfied(&self) -> bool {
-        for possible_desire in &self.desires {
-            if possible_desire.is_some() {
-                return false;
-            }
-        }
-        return true;
+        self.desires.iter().all(|desire| desire.is_none())
     }
 
     pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
@@ -190,6 +169,7 @@ impl Trainsink {
                         pivot: None,
                     },
                 );
+                draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
             }
 
         }
@@ -1,11 +1,11 @@
 use macroquad::prelude::*;
 use serde::{Serialize, Deserialize};
 use crate::color::Color;
+use crate::color::glyph::draw_glyph;
 
 use crate::tile::BorderState;
 use crate::sprites::GameSprites;
-use crate::particle::ParticleList;
-use crate::particle::shrinking_plus::ShrinkingPlus;
+use crate::event::{EventKind, EventList};
 
 use std::f32::consts::PI;
 
@@ -34,18 +34,15 @@ impl Trainsource {
     }
 
     pub fn accept_trains(&self, trains: BorderState) -> BorderState {
-        return trains;
+        trains
     }
 
-    pub fn process_tick(&mut self, p: &mut ParticleList) {
+    pub fn process_tick(&mut self, events: &mut EventList) {
         for (index, train) in self.trains.iter().enumerate() {
             if let Some(color) = *train {
                 self.outgoing_train = Some(color);
                 self.trains[index] = None;
-                p.push(Box::new(ShrinkingPlus::new(
-                    self.icon_rects[index], 
-                    color,
-                )));
+                events.push(EventKind::TrainReleased { index, color });
                 return;
             }
         }
@@ -59,12 +56,7 @@ impl Trainsource {
     }
 
     pub fn is_empty(&self) -> bool {
-        for train in &self.trains {
-            if train.is_some() {
-                return false;
-            }
-        }
-        return true;
+        self.trains.iter().all(|train| train.is_none())
     }
 
     pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
@@ -149,6 +141,7 @@ impl Trainsource {
                     pivot: None
                 }
             );
+            draw_glyph(color, train_center_x, train_center_y, train_width.min(train_height) * 0.6, 1.);
 
         }
     }
@@ -0,0 +1,64 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::color::Color;
+
+use crate::tile::{render_train, BorderState};
+use crate::sprites::GameSprites;
+
+// Tunnels come in pairs that share an id. A train that enters a tunnel through its open side comes out of
+// the other tunnel of the pair on the next tick, through that tunnel's open side. The simulation moves the
+// trains from one tunnel to the other, since a tile only knows about itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tunnel {
+    pub id: u8,
+    pub dir: u8,
+    pub incoming_train: Option<Color>,
+    pub outgoing_train: Option<Color>,
+    #[serde(skip)]
+    pub rect: Option<Rect>,
+    pub scale: f32,
+}
+
+impl Tunnel {
+    pub fn new(id: u8, dir: u8) -> Tunnel {
+        Tunnel {
+            id,
+            dir,
+            incoming_train: None,
+            outgoing_train: None,
+            rect: None,
+            scale: 1.,
+        }
+    }
+
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        let mut border_state = trains;
+        self.incoming_train = border_state[self.dir as usize].take();
+        border_state
+    }
+
+    pub fn dispatch_trains(&mut self) -> BorderState {
+        let mut border_state = [None, None, None, None];
+        border_state[self.dir as usize] = self.outgoing_train.take();
+        border_state
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.incoming_train.is_none() && self.outgoing_train.is_none()
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.rect = Some(rect);
+        self.scale = rect.w / gs.tracktile_blank.width();
+    }
+
+    pub fn render_trains(&self, gs: &GameSprites, progress: f32) {
+        let rect = self.rect.unwrap();
+        if let Some(color) = self.incoming_train {
+            render_train(gs, rect, self.scale, color, self.dir, true, progress);
+        }
+        if let Some(color) = self.outgoing_train {
+            render_train(gs, rect, self.scale, color, self.dir, false, progress);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+use crate::color::rules::ColorRules;
+
+use crate::event::{EventKind, EventList};
+use crate::tile::{render_train, BorderState};
+use crate::sprites::GameSprites;
+
+use std::f32::consts::PI;
+
+// A turntable sends the trains that reach its middle out through its exits in turn, whichever side they
+// came in from. After the last exit of the list, it starts over from the first one. Trains that enter at
+// the same time leave through successive exits, and if two of them are sent through the same side,
+// they merge like on a tracktile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turntable {
+    pub exits: Vec<u8>,
+    // the index in exits of the side the next train leaves through
+    pub next_exit: usize,
+    pub incoming_trains: BorderState,
+    pub outgoing_trains: BorderState,
+    // the side the turntable pointed to before it last turned, for the animation
+    pub turned_from: Option<u8>,
+    #[serde(skip)]
+    pub rect: Option<Rect>,
+    scale: f32,
+}
+
+impl Turntable {
+    pub fn new(exits: Vec<u8>) -> Turntable {
+        Turntable {
+            exits,
+            next_exit: 0,
+            incoming_trains: [None, None, None, None],
+            outgoing_trains: [None, None, None, None],
+            turned_from: None,
+            rect: None,
+            scale: 1.,
+        }
+    }
+
+    pub fn current_exit(&self) -> u8 {
+        self.exits[self.next_exit]
+    }
+
+    pub fn accept_trains(&mut self, trains: BorderState) -> BorderState {
+        self.incoming_trains = trains;
+        [None, None, None, None]
+    }
+
+    pub fn process_tick(&mut self, events: &mut EventList, rules: &ColorRules) {
+        let before = self.current_exit();
+        self.turned_from = None;
+        for dir in 0..4 {
+            let Some(color) = self.incoming_trains[dir].take() else {
+                continue;
+            };
+            let exit = self.current_exit() as usize;
+            self.outgoing_trains[exit] = match self.outgoing_trains[exit] {
+                Some(other) => {
+                    let new_color = rules.mix_many(&[color, other]);
+                    events.push(EventKind::TrackMerge { dir: exit as u8, color: new_color });
+                    Some(new_color)
+                }
+                None => Some(color),
+            };
+            self.next_exit = (self.next_exit + 1) % self.exits.len();
+            self.turned_from = Some(before);
+        }
+    }
+
+    pub fn dispatch_trains(&mut self) -> BorderState {
+        std::mem::take(&mut self.outgoing_trains)
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
+        self.rect = Some(rect);
+        self.scale = rect.w / gs.tracktile_blank.width();
+    }
+
+    pub fn render_trains(&self, gs: &GameSprites, progress: f32) {
+        // the turntable itself is drawn here, since it turns while the trains leave it.
+        let rect = self.rect.unwrap();
+        let (center_x, center_y) = (rect.x + rect.w / 2., rect.y + rect.h / 2.);
+        let angle = match self.turned_from {
+            Some(from) if progress > 1.0 => {
+                // always turns clockwise, by as many quarter turns as it takes
+                let quarter_turns = (self.current_exit() + 4 - from) % 4;
+                (from as f32 + quarter_turns as f32 * (progress - 1.0).min(1.0)) * PI / 2.
+            }
+            _ => self.current_exit() as f32 * PI / 2.,
+        };
+        draw_circle(center_x, center_y, rect.w * 0.4, GRAY);
+        draw_circle_lines(center_x, center_y, rect.w * 0.4, rect.w * 0.03, DARKGRAY);
+        let point = |forward: f32, side: f32| Vec2::new(
+            center_x + rect.w * (forward * angle.sin() + side * angle.cos()),
+            center_y + rect.w * (-forward * angle.cos() + side * angle.sin()),
+        );
+        draw_line(point(-0.3, 0.).x, point(-0.3, 0.).y, point(0.2, 0.).x, point(0.2, 0.).y, rect.w * 0.08, DARKGRAY);
+        draw_triangle(point(0.35, 0.), point(0.15, -0.12), point(0.15, 0.12), DARKGRAY);
+
+        for (dir, train) in self.incoming_trains.iter().enumerate() {
+            if let Some(color) = *train {
+                render_train(gs, rect, self.scale, color, dir as u8, true, progress);
+            }
+        }
+        for (dir, train) in self.outgoing_trains.iter().enumerate() {
+            if let Some(color) = *train {
+                render_train(gs, rect, self.scale, color, dir as u8, false, progress);
+            }
+        }
+    }
+}
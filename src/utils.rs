@@ -85,4 +85,37 @@ pub fn find_min_f32(v: &[f32]) -> f32 {
     }
 
     *min
-}
\ No newline at end of file
+}
+pub fn draw_star(x: f32, y: f32, radius: f32, color: Color) {
+    // a filled five pointed star centered on (x, y), with a point facing up.
+    let point = |index: usize, radius: f32| {
+        let angle = index as f32 * std::f32::consts::PI / 5.;
+        Vec2::new(x + radius * angle.sin(), y - radius * angle.cos())
+    };
+    let center = Vec2::new(x, y);
+    for index in 0..10 {
+        let (r1, r2) = if index % 2 == 0 {(radius, radius * 0.4)} else {(radius * 0.4, radius)};
+        draw_triangle(center, point(index, r1), point(index + 1, r2), color);
+    }
+}
+
+pub fn draw_text_button(label: &str, rect: Rect, bg_color: Color) {
+    // for buttons that don't have a texture in the atlas.
+    draw_rectangle(rect.x, rect.y, rect.w, rect.h, bg_color);
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2., BLACK);
+
+    // long labels get a smaller font so that they stay inside the button
+    let mut font_size = rect.h * 0.6;
+    let label_width = measure_text(label, None, font_size as u16, 1.).width;
+    if label_width > rect.w * 0.9 {
+        font_size *= rect.w * 0.9 / label_width;
+    }
+    let TextDimensions {height: text_height, width: text_width, ..} = measure_text(label, None, font_size as u16, 1.);
+    draw_text(
+        label,
+        rect.x + (rect.w - text_width) / 2.,
+        rect.y + rect.h - (rect.h - text_height) / 2.,
+        font_size,
+        DARKGRAY,
+    );
+}
@@ -1,22 +1,37 @@
 use macroquad::prelude::*;
+use crate::color::glyph::draw_glyph;
+use crate::color::rules::ColorRules;
 use crate::connection::Connection;
-use crate::edge::Edge;
+use crate::event::{EventKind, SimEvent};
 use crate::levels::{LevelInfo, Level, PositionedTile, LevelProgress};
 use crate::particle::ParticleList;
 use crate::particle::drawn_arrow::DrawnArrow;
-use crate::particle::smoke::Smoke;
+use crate::particle::fire::{self, Fire};
+use crate::particle::hint_glow::HintGlow;
+use crate::particle::painter_particle::PainterParticle;
+use crate::particle::shrinking_circle::ShrinkingCircle;
+use crate::particle::shrinking_plus::ShrinkingPlus;
+use crate::particle::smoke::{self, Smoke};
+use crate::particle::sparkle::{self, Sparkle};
+use crate::particle::splitter_particle::SplitterParticle;
+use crate::score::Score;
+use crate::simulation::{Simulation, SimStatus, SimulationError};
 use crate::sprites::GameSprites;
-use crate::sprites::SoundType::{EraseTrack, Crash, WinLevel};
-use crate::tile::tracktile::ConnectionType;
-use crate::tile::tracktile::Tracktile;
-use crate::tile::BorderState;
+use crate::sprites::SoundType::{self, EraseTrack, Crash, WinLevel, DrawTrack, SwitchTrack};
+use crate::tile::tracktile::{ConnectionType, Tracktile, get_midpoint_of_conn};
 use crate::tile::Tile;
+use crate::utils::direction_midpoint;
 
-use std::io::Write;
+use std::collections::VecDeque;
 use std::f32::consts::PI;
 
-pub const NUM_ROWS: usize = 7;
-pub const NUM_COLS: usize = 7;
+// the size of the yard in the original game. Levels that don't declare a size use this one.
+pub const DEFAULT_NUM_ROWS: usize = 7;
+pub const DEFAULT_NUM_COLS: usize = 7;
+pub const MAX_NUM_ROWS: usize = 16;
+pub const MAX_NUM_COLS: usize = 16;
+// how many actions of the simulation can be stepped back while debugging a run.
+const MAX_SNAPSHOTS: usize = 1000;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum NextAction {
@@ -36,113 +51,51 @@ pub enum YardState {
     Won,
 }
 
-// In the Yard struct, we keep a copy of tiles and drawn_tiles. When the user is drawing, we update both.
-// When the yard is in the Playing state, we only update tiles (and keep drawn_tiles static) when trains switch
-// active/passive connections. This way we can use drawn_tiles to revert the tiles if the user 
+// In the Yard struct, we keep a copy of the simulation's tiles in drawn_tiles. When the user is drawing, we update both.
+// When the yard is in the Playing state, only the simulation changes (e.g. when trains switch
+// active/passive connections). This way we can use drawn_tiles to rebuild the simulation if the user 
 // returns to the drawing board.
+// The Yard is also responsible for turning the events reported by the simulation into particles and sounds.
 pub struct Yard {
-    tiles: Vec<Vec<Tile>>,
+    sim: Simulation,
     drawn_tiles: Vec<Vec<Tile>>,
-    h_edges: Vec<Vec<Edge>>,
-    v_edges: Vec<Vec<Edge>>,
     pub state: YardState,
     level_info: LevelInfo,
+    pub num_rows: usize,
+    pub num_cols: usize,
     pub rect: Rect,
+    // the simulation before each of the last actions of the current run, used to step back.
+    snapshots: VecDeque<(Simulation, YardState)>,
+    pub is_paused: bool,
+    // set whenever something that is saved as progress changes: the drawn tracks, or the level being won.
+    progress_changed: bool,
 }
 
 impl Yard {
-    pub fn new_blank(rect: Rect) -> Yard {
-        let mut tiles: Vec<Vec<Tile>> = Vec::new();
-        for _ in 0..NUM_ROWS {
-            let mut row: Vec<Tile> = Vec::new();
-            for _ in 0..NUM_COLS {
-                row.push(Tile::Tracktile(Tracktile::new(None, None)));
-            }
-            tiles.push(row);
-        }
-        let drawn_tiles = tiles.clone();
-
-        let w = rect.w/NUM_COLS as f32;
-        let h = rect.h/NUM_ROWS as f32;
-        let half_w = w/2.;
-        let half_h = h/2.;
-
-        let mut h_edges: Vec<Vec<Edge>> = Vec::new();
-        for r in 0..(NUM_ROWS + 1) {
-            let mut row: Vec<Edge> = Vec::new();
-            for c in 0..NUM_COLS {
-                let x_pos = rect.x + half_w + c as f32 * w;
-                let y_pos = rect.y + r as f32 * h;
-                row.push(Edge::new(x_pos, y_pos));
-            }
-            h_edges.push(row);
-        }
-
-        let mut v_edges: Vec<Vec<Edge>> = Vec::new();
-        for r in 0..NUM_ROWS {
-            let mut row: Vec<Edge> = Vec::new();
-            for c in 0..(NUM_COLS + 1) {
-                let x_pos = rect.x + c as f32 * w;
-                let y_pos = rect.y + half_h + r as f32 * h;
-                row.push(Edge::new(x_pos, y_pos));
-            }
-            v_edges.push(row);
-        }
-
-        Yard {
-            tiles,
+    pub fn new(level: &Level, color_rules: &ColorRules, rect: Rect, gs: &GameSprites) -> Result<Yard, SimulationError> {
+        let sim = Simulation::new(&level.level_info, &level.current_progress.0, level.num_rows, level.num_cols, color_rules)?;
+        let drawn_tiles = sim.tiles.clone();
+        let mut yard = Yard {
+            sim,
             drawn_tiles,
-            h_edges,
-            v_edges,
             state: YardState::Drawing,
-            level_info: vec![],
+            level_info: level.level_info.clone(),
+            num_rows: level.num_rows,
+            num_cols: level.num_cols,
             rect,
-        }
-    }
-    pub fn new(level: &Level, rect: Rect, gs: &GameSprites) -> Yard {
-        let mut yard = Yard::new_blank(rect);
-        let level_info = &level.level_info;
-        for i in 0..level_info.len() {
-            let tile = &level_info[i];
-            yard.tiles[tile.y as usize][tile.x as usize] = tile.tile.clone();
-        }
-        let current_progress = &level.current_progress;
-        for i in 0..current_progress.0.len() {
-            let tile = &current_progress.0[i];
-            match yard.tiles[tile.y as usize][tile.x as usize] {
-                Tile::Tracktile(_) => {},
-                _ => {
-                    println!("Warning, overriding a non tracktile tile at column {}, row {} when loading level progress.", tile.x, tile.y)
-                }
-            }
-            yard.tiles[tile.y as usize][tile.x as usize] = tile.tile.clone();
-        }
-        yard.drawn_tiles = yard.tiles.clone();
-
-        let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
-        let new_w = w/(NUM_COLS as f32);
-        let new_h = h/(NUM_ROWS as f32);
-        for row in 0..NUM_ROWS {
-            for col in 0..NUM_COLS {
-                yard.tiles[row][col].set_rect(
-                    Rect::new(
-                        x + (new_w*col as f32),
-                        y + (new_h*row as f32),
-                        new_w,
-                        new_h,
-                    ),
-                    gs,
-                )
-            }
-        }
-        yard.level_info = level_info.clone();
-        yard
+            snapshots: VecDeque::new(),
+            is_paused: false,
+            progress_changed: false,
+        };
+        yard.set_rect(rect, gs);
+        Ok(yard)
     }
 
     pub fn clear_connections (&mut self, r: usize, c: usize, gs:&mut GameSprites) {
-        if let Tile::Tracktile(tracktile) = &mut self.tiles[r][c] {
+        if let Tile::Tracktile(tracktile) = &mut self.sim.tiles[r][c] {
             if tracktile.connection_type() != ConnectionType::None {
                 gs.add_sound(EraseTrack);
+                self.progress_changed = true;
             }
             tracktile.clear_connections();
         }
@@ -151,41 +104,15 @@ impl Yard {
         }
     }
 
-    pub fn display(&self) {
-        // deprecated, used for displaying stuff to the terminal.
-        for r in 0..NUM_ROWS {
-            print!(" ");
-            for c in 0..NUM_COLS {
-                print!("{}", self.h_edges[r][c].get_char().to_string() + " ");
-            }
-            println!("");
-            for c in 0..NUM_COLS {
-                print!(
-                    "{}",
-                    self.v_edges[r][c].get_char().to_string()
-                        + &self.tiles[r][c].get_char().to_string()
-                );
-            }
-            println!("{}", self.v_edges[r][NUM_ROWS].get_char());
-        }
-
-        print!(" ");
-        for c in 0..NUM_COLS {
-            print!("{}", self.h_edges[NUM_ROWS][c].get_char().to_string() + " ");
-        }
-        println!("");
-
-        std::io::stdout().flush().unwrap();
-    }
-
     pub fn add_connection(&mut self, r: usize, c: usize, conn: Connection, gs: &mut GameSprites, p: &mut ParticleList) {
         // we only allow a yard to add_connection during the drawing state.
         assert!(matches!(self.state, YardState::Drawing));
-        if let Tile::Tracktile(tt) = &mut self.tiles[r][c] {
-            tt.add_connection(conn, gs);
+        if let Tile::Tracktile(tt) = &mut self.sim.tiles[r][c] {
+            tt.add_connection(conn);
+            gs.add_sound(DrawTrack);
 
-            let tracktile_width = self.rect.w/NUM_COLS as f32;
-            let tracktile_height = self.rect.h/NUM_ROWS as f32;
+            let tracktile_width = self.rect.w/self.num_cols as f32;
+            let tracktile_height = self.rect.h/self.num_rows as f32;
 
             let center_x = self.rect.x + tracktile_width/2. + tracktile_width * c as f32;
             let center_y = self.rect.y + tracktile_height/2. + tracktile_height * r as f32;
@@ -225,8 +152,9 @@ impl Yard {
             }
 
             if let Tile::Tracktile(tt_drawn) = &mut self.drawn_tiles[r][c] {
-                tt_drawn.add_connection(conn, gs);
+                tt_drawn.add_connection(conn);
             }
+            self.progress_changed = true;
         }
     }
 
@@ -234,278 +162,308 @@ impl Yard {
         // we only allow a yard to manually switch connections during the drawing state.
         // during a playing state each tracktile is responsible for switching itself.
         assert!(matches!(self.state, YardState::Drawing));
-        if let Tile::Tracktile(tt) = &mut self.tiles[r][c] {
-            tt.switch_active_passive(gs);
+        if let Tile::Tracktile(tt) = &mut self.sim.tiles[r][c] {
+            if tt.switch_active_passive() {
+                gs.add_sound(SwitchTrack);
+                self.progress_changed = true;
+            }
             if let Tile::Tracktile(tt_drawn) = &mut self.drawn_tiles[r][c]{
-                tt_drawn.switch_active_passive(gs);
+                tt_drawn.switch_active_passive();
             }
         }
     }
     
-    pub fn reset_self(&mut self, gs: &GameSprites) {
-        // used to recover from a crashed state back to a drawing state.
-        // also used when the user presses "back to drawing board".
-
-        for tile in &self.level_info {
-            self.tiles[tile.y as usize][tile.x as usize] = tile.tile.clone();
-        }
-
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                if let Tile::Tracktile(tracktile) = &mut self.tiles[r][c] {
-                    tracktile.clear_trains();
-                }
+    pub fn find_hint(&self, solution: &LevelInfo) -> Option<PositionedTile> {
+        // returns the first tracktile of the solution that the user hasn't drawn yet.
+        solution.iter().find(|solution_tile| {
+            match (&solution_tile.tile, &self.drawn_tiles[solution_tile.y as usize][solution_tile.x as usize]) {
+                (Tile::Tracktile(solution_tt), Tile::Tracktile(drawn_tt)) => !drawn_tt.has_same_connections(solution_tt),
+                _ => false,
             }
-        }
+        }).cloned()
+    }
 
-        for r in 0..(NUM_ROWS + 1) {
-            for c in 0..NUM_COLS {
-                self.h_edges[r][c].clear_trains();
-            }
-        }
-        for r in 0..NUM_ROWS {
-            for c in 0..(NUM_COLS + 1) {
-                self.v_edges[r][c].clear_trains();
-            }
+    pub fn reveal_tile(&mut self, hint: &PositionedTile, gs: &mut GameSprites, p: &mut ParticleList) {
+        // replaces whatever the user drew on the tile of the hint with the tracktile of the hint.
+        assert!(matches!(self.state, YardState::Drawing));
+        if let Tile::Tracktile(hint_tt) = &hint.tile {
+            let (r, c) = (hint.y as usize, hint.x as usize);
+            self.set_tracktile(r, c, hint_tt);
+            gs.add_sound(DrawTrack);
+            p.push(Box::new(HintGlow::new(self.tile_rect(r, c))));
         }
+    }
 
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                if let Tile::Tracktile(tracktile) = &self.drawn_tiles[r][c] {
-                    if tracktile.connection_type() != ConnectionType::None {
-                        // we only do this if the connection_type is not none because otherwise we would
-                        // overwrite other tiles like trainsources.
-                        self.tiles[r][c] = Tile::Tracktile(tracktile.clone());
-                    }
-                }
+    pub fn set_tracktile(&mut self, r: usize, c: usize, tracktile: &Tracktile) {
+        // copies the connections of tracktile onto the tile at (r, c), which must be a tracktile.
+        for tiles in [&mut self.sim.tiles, &mut self.drawn_tiles] {
+            if let Tile::Tracktile(tt) = &mut tiles[r][c] {
+                tt.set_connections(tracktile.active_connection(), tracktile.passive_connection());
             }
         }
+        self.progress_changed = true;
+    }
 
-        let rect = self.rect;
-        let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
-        let new_w = w/(NUM_COLS as f32);
-        let new_h = h/(NUM_ROWS as f32);
-        for row in 0..NUM_ROWS {
-            for col in 0..NUM_COLS {
-                self.tiles[row][col].set_rect(
-                    Rect::new(
-                        x + (new_w*col as f32),
-                        y + (new_h*row as f32),
-                        new_w,
-                        new_h,
-                    ),
-                    gs,
-                )
-            }
-        }
+    pub fn take_progress_changed(&mut self) -> bool {
+        // returns whether the progress changed since the last call.
+        std::mem::take(&mut self.progress_changed)
+    }
+
+    pub fn get_drawn_tiles(&self) -> &Vec<Vec<Tile>> {
+        &self.drawn_tiles
     }
 
-    pub fn process_edges(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
-        assert!(matches!(
-            self.state,
-            YardState::Playing {..}
-        ));
+    pub fn get_level_info(&self) -> &LevelInfo {
+        &self.level_info
+    }
 
-        let tracktile_width = self.rect.w/NUM_COLS as f32;
-        let tracktile_height = self.rect.h/NUM_ROWS as f32;
-        let scale = tracktile_width / gs.tracktile_blank.width();
+    pub fn reset_self(&mut self, gs: &GameSprites) {
+        // used to recover from a crashed state back to a drawing state.
+        // also used when the user presses "back to drawing board".
+        let layout = self.get_current_progress().0;
+        self.sim = Simulation::new(&self.level_info, &layout, self.num_rows, self.num_cols, &self.sim.color_rules)
+            .expect("the drawn tracktiles come from a simulation of the same level");
+        self.snapshots.clear();
+        self.is_paused = false;
+        self.set_rect(self.rect, gs);
+    }
 
-        // merge all trains that are still in tiles
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                self.tiles[r][c].process_end_of_tick(gs, p);
+    pub fn update(&mut self, speed: f32, gs: &mut GameSprites, p: &mut ParticleList) -> bool {
+        // returns true if the simulation advanced by one action during this frame.
+        let mut advanced = false;
+        if let YardState::Playing {
+            num_ticks_elapsed,
+            mut progress,
+            next_step,
+        } = self.state
+        {
+            if !self.is_paused {
+                progress += speed;
+            }
+            self.state = YardState::Playing {
+                num_ticks_elapsed,
+                progress,
+                next_step,
+            };
+            if progress > 1.0 {
+                advanced = self.step(gs, p);
+            }
+        } else if self.state == YardState::Crashed {
+            for event in &self.sim.settle_after_crash() {
+                self.play_event(event, gs, p);
             }
         }
+        advanced
+    }
 
+    pub fn step(&mut self, gs: &mut GameSprites, p: &mut ParticleList) -> bool {
+        // advances the simulation by exactly one action, even if the trains haven't finished moving yet.
+        // returns false if the yard is not playing.
+        let YardState::Playing {
+            mut num_ticks_elapsed,
+            progress,
+            mut next_step,
+        } = self.state else {
+            return false;
+        };
 
-        // dispatch all trains and store them in edges.
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                [
-                    self.h_edges[r][c].train_to_a,
-                    self.v_edges[r][c + 1].train_to_b,
-                    self.h_edges[r + 1][c].train_to_b,
-                    self.v_edges[r][c].train_to_a,
-                ] = self.tiles[r][c].dispatch_trains();
-            }
-        }
-        // mix edges
-        for r in 0..(NUM_ROWS + 1) {
-            for c in 0..NUM_COLS {
-                self.h_edges[r][c].interact_trains(gs, p, scale);
-            }
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
         }
-        for r in 0..NUM_ROWS {
-            for c in 0..(NUM_COLS + 1) {
-                self.v_edges[r][c].interact_trains(gs, p, scale);
-            }
+        self.snapshots.push_back((self.sim.clone(), self.state));
+
+        let events = self.sim.advance(next_step);
+        next_step = match next_step {
+            NextAction::ProcessEdges => NextAction::ProcessTick,
+            NextAction::ProcessTick => NextAction::ProcessEdges,
+        };
+        for event in &events {
+            self.play_event(event, gs, p);
         }
+        num_ticks_elapsed += 1;
 
-        // detect crashes on boundaries of yard (i.e. if a train is about to crash by going
-        // too far up where there is no tile left to catch it)
-        for c in 0..NUM_COLS {
-            if let Some(train) = self.h_edges[0][c].train_to_a {
-                self.state = YardState::Crashed;
-                p.push(Box::new(Smoke::new(
-                    self.rect.x + tracktile_width /2. + (tracktile_width * c as f32),
-                    self.rect.y,
-                    train,
-                    scale,
-                )));
-                gs.add_sound(Crash);
-            }
-            if let Some(train) = self.h_edges[NUM_ROWS][c].train_to_b {
-                self.state = YardState::Crashed;
-                p.push(Box::new(Smoke::new(
-                    self.rect.x + tracktile_width /2. + (tracktile_width * c as f32),
-                    self.rect.y + self.rect.h,
-                    train,
-                    scale,
-                )));
-                gs.add_sound(Crash);
+        match self.sim.status {
+            SimStatus::Running => {
+                self.state = YardState::Playing {
+                    num_ticks_elapsed,
+                    progress: (progress - 1.0).max(0.0),
+                    next_step,
+                }
             }
-        }
-        for r in 0..NUM_ROWS {
-            if let Some(train) = self.v_edges[r][0].train_to_a {
+            SimStatus::Crashed => {
                 self.state = YardState::Crashed;
-                p.push(Box::new(Smoke::new(
-                    self.rect.x,
-                    self.rect.y + tracktile_height /2. + (tracktile_height * r as f32),
-                    train,
-                    scale,
-                )));
-                gs.add_sound(Crash);
             }
-            if let Some(train) = self.v_edges[r][NUM_COLS].train_to_b {
-                self.state = YardState::Crashed;
-                p.push(Box::new(Smoke::new(
-                    self.rect.x + self.rect.w,
-                    self.rect.y + tracktile_height /2. + (tracktile_height * r as f32),
-                    train,
-                    scale,
-                )));
-                gs.add_sound(Crash);
+            SimStatus::Won => {
+                self.state = YardState::Won;
+                self.progress_changed = true;
+                gs.add_sound(WinLevel);
             }
         }
+        true
+    }
+
+    pub fn step_back(&mut self, gs: &GameSprites) -> bool {
+        // undoes the last action of the simulation and pauses the run, also after a crash or a win.
+        // returns false if there is nothing to step back to.
+        let Some((sim, state)) = self.snapshots.pop_back() else {
+            return false;
+        };
+        self.sim = sim;
+        self.state = match state {
+            YardState::Playing {num_ticks_elapsed, next_step, ..} => YardState::Playing {
+                num_ticks_elapsed,
+                progress: 0.0,
+                next_step,
+            },
+            _ => state,
+        };
+        self.is_paused = true;
+        self.set_rect(self.rect, gs);
+        true
+    }
 
-        // all tiles pull in trains from the edges. A crash occurs if there is a
-        // train entering a tile but the tile does not pull it in.
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                let border_state: BorderState = [
-                    self.h_edges[r][c].train_to_b,
-                    self.v_edges[r][c + 1].train_to_a,
-                    self.h_edges[r + 1][c].train_to_a,
-                    self.v_edges[r][c].train_to_b,
-                ];
-                let not_crashed = self.tiles[r][c].accept_trains(border_state, p, scale);
-                if !not_crashed {
-                    self.state = YardState::Crashed;
-                    gs.add_sound(Crash);
+    pub fn get_score(&self) -> Score {
+        let mut score = Score { tracks: 0, switches: 0, ticks: self.sim.num_ticks };
+        for tile in self.drawn_tiles.iter().flatten() {
+            if let Tile::Tracktile(tracktile) = tile {
+                if tracktile.active_connection().is_some() {
+                    score.tracks += 1;
+                }
+                if tracktile.is_switch() {
+                    score.switches += 1;
                 }
             }
         }
-
-        
+        score
     }
 
-    pub fn process_tick(&mut self, gs: &mut GameSprites, p: &mut ParticleList) {
-        assert!(matches!(
-            self.state,
-            YardState::Playing {..}
-        ));
-        
-
-        // then process tick in all tiles
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                self.tiles[r][c].process_tick(gs, p);
-            }
-        }
+    pub fn get_sim(&self) -> &Simulation {
+        &self.sim
+    }
 
-        if self.has_won() {
-            self.state = YardState::Won;
-            gs.add_sound(WinLevel);
-        }
+    pub fn show_sim(&mut self, sim: &Simulation, state: YardState, gs: &GameSprites) {
+        // displays a state of the simulation that was recorded earlier, e.g. by a replay.
+        self.sim = sim.clone();
+        self.state = state;
+        self.set_rect(self.rect, gs);
     }
 
-    pub fn update(&mut self, speed: f32, gs: &mut GameSprites, p: &mut ParticleList) {
-        if let YardState::Playing {
-            mut num_ticks_elapsed,
-            mut progress,
-            mut next_step,
-        } = self.state
-        {
-            progress += speed;
-            if progress > 1.0 {
-                progress -= 1.0;
-                match next_step {
-                    NextAction::ProcessEdges => {
-                        self.process_edges(gs, p);
-                        next_step = NextAction::ProcessTick;
-                    }
-                    NextAction::ProcessTick => {
-                        self.process_tick(gs, p);
-                        next_step = NextAction::ProcessEdges;
+    pub fn render_debug_overlay(&self) {
+        // shows what the simulation holds right now: an arrow for every train on an edge, pointing
+        // where it is heading, and a line through every tracktile train from its source to its destination.
+        let tile_w = self.rect.w / self.num_cols as f32;
+        let tile_h = self.rect.h / self.num_rows as f32;
+        let size = tile_w / 8.;
 
-                    }
+        // train_to_a heads up on horizontal edges and left on vertical edges, train_to_b the other way.
+        for (r, row) in self.sim.h_edges.iter().enumerate() {
+            for (c, edge) in row.iter().enumerate() {
+                let (x, y) = (self.rect.x + (c as f32 + 0.5) * tile_w, self.rect.y + r as f32 * tile_h);
+                if let Some(color) = edge.train_to_a {
+                    draw_debug_arrow(x - size, y, 0, size, color.get_color());
                 }
-
-                num_ticks_elapsed += 1;
-            }
-            if self.state != YardState::Crashed && self.state != YardState::Won {
-                self.state = YardState::Playing {
-                    num_ticks_elapsed,
-                    progress,
-                    next_step,
+                if let Some(color) = edge.train_to_b {
+                    draw_debug_arrow(x + size, y, 2, size, color.get_color());
                 }
             }
-        } else if self.state == YardState::Crashed {
-            for r in 0..NUM_ROWS {
-                for c in 0..NUM_COLS {
-                    if let Tile::Trainsink(trainsink) = &mut self.tiles[r][c] {
-                        // this only exists for the edge case where two trains simultaneously enter a trainsink with only 1 desire.
-                        // in that case, one train enters, the other crashes.
-                        trainsink.process_tick(gs, p);
-                    }
+        }
+        for (r, row) in self.sim.v_edges.iter().enumerate() {
+            for (c, edge) in row.iter().enumerate() {
+                let (x, y) = (self.rect.x + c as f32 * tile_w, self.rect.y + (r as f32 + 0.5) * tile_h);
+                if let Some(color) = edge.train_to_a {
+                    draw_debug_arrow(x, y - size, 3, size, color.get_color());
+                }
+                if let Some(color) = edge.train_to_b {
+                    draw_debug_arrow(x, y + size, 1, size, color.get_color());
                 }
             }
         }
-    }
 
-    pub fn has_won(&self) -> bool {
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                match &self.tiles[r][c] {
-                    Tile::Trainsink(trainsink) => {
-                        if !trainsink.is_satisfied() {
-                            return false;
-                        }
-                    }
-                    Tile::Trainsource(trainsource) => {
-                        if !trainsource.is_empty() {
-                            return false;
-                        }
-                    }
-                    _ => {}
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                let Tile::Tracktile(tracktile) = &self.sim.tiles[r][c] else {
+                    continue;
+                };
+                let tile_rect = self.tile_rect(r, c);
+                let (center_x, center_y) = (tile_rect.x + tile_rect.w / 2., tile_rect.y + tile_rect.h / 2.);
+                for train in tracktile.trains() {
+                    let (source_x, source_y) = direction_midpoint(tile_rect, train.source);
+                    let (dest_x, dest_y) = direction_midpoint(tile_rect, train.destination);
+                    let color = train.color.get_color();
+                    draw_line(source_x, source_y, center_x, center_y, 3., color);
+                    draw_line(center_x, center_y, dest_x, dest_y, 3., color);
+                    draw_debug_arrow(
+                        (center_x + dest_x) / 2.,
+                        (center_y + dest_y) / 2.,
+                        train.destination,
+                        size,
+                        color,
+                    );
+                }
+                if !tracktile.trains().is_empty() {
+                    draw_text(
+                        &tracktile.trains().len().to_string(),
+                        tile_rect.x + 4.,
+                        tile_rect.y + tile_rect.h / 4.,
+                        tile_rect.h / 4.,
+                        BLACK,
+                    );
                 }
             }
         }
-        for r in 0..(NUM_ROWS + 1) {
-            for c in 0..NUM_COLS {
-                if !self.h_edges[r][c].is_empty() {
-                    return false;
+    }
+
+    pub fn tile_rect(&self, r: usize, c: usize) -> Rect {
+        let w = self.rect.w/(self.num_cols as f32);
+        let h = self.rect.h/(self.num_rows as f32);
+        Rect::new(self.rect.x + w*c as f32, self.rect.y + h*r as f32, w, h)
+    }
+
+    fn play_event(&self, event: &SimEvent, gs: &mut GameSprites, p: &mut ParticleList) {
+        // turns an event reported by the simulation into particles and sounds.
+        let rect = self.tile_rect(event.r, event.c);
+        let scale = rect.w / gs.tracktile_blank.width();
+        match event.kind {
+            EventKind::EdgeMix { dir, color } | EventKind::TrackMerge { dir, color } => {
+                let (x, y) = direction_midpoint(rect, dir);
+                p.push(Box::new(Fire::new(x, y, color, scale, gs.particle_density.particle_count(fire::NUM_FIRES))));
+                gs.play_train_sound(color);
+            }
+            EventKind::TrackMix { conn, color } => {
+                let (x, y) = get_midpoint_of_conn(conn, rect);
+                p.push(Box::new(Fire::new(x, y, color, scale, gs.particle_density.particle_count(fire::NUM_FIRES))));
+                gs.play_train_sound(color);
+            }
+            EventKind::Painted { color } => {
+                p.push(Box::new(PainterParticle::new(rect, color)));
+                gs.add_sound(SoundType::Painter);
+            }
+            EventKind::Split { dir } => {
+                p.push(Box::new(SplitterParticle::new(rect, dir)));
+                gs.add_sound(SoundType::Splitter);
+            }
+            EventKind::TrainReleased { index, color } => {
+                if let Tile::Trainsource(trainsource) = &self.sim.tiles[event.r][event.c] {
+                    p.push(Box::new(ShrinkingPlus::new(trainsource.icon_rects[index], color)));
                 }
             }
-        }
-        for r in 0..NUM_ROWS {
-            for c in 0..(NUM_COLS + 1) {
-                if !self.v_edges[r][c].is_empty() {
-                    return false;
+            EventKind::SinkSatisfied { index, color } => {
+                if let Tile::Trainsink(trainsink) = &self.sim.tiles[event.r][event.c] {
+                    p.push(Box::new(ShrinkingCircle::new(trainsink.icon_rects[index], color)));
                 }
+                let (center_x, center_y) = (rect.x + rect.w/2., rect.y + rect.h/2.);
+                p.push(Box::new(Sparkle::new(center_x, center_y, color, scale, gs.particle_density.particle_count(sparkle::NUM_STARS))));
+                gs.play_train_sound(color);
+            }
+            EventKind::Crash { dir, color } => {
+                let (x, y) = direction_midpoint(rect, dir);
+                p.push(Box::new(Smoke::new(x, y, color, scale, gs.particle_density.particle_count(smoke::NUM_SMOKES))));
+                gs.add_sound(Crash);
+            }
+            EventKind::SwitchFlipped => {
+                gs.add_sound(SwitchTrack);
             }
         }
-        true
     }
 
     pub fn render(
@@ -514,8 +472,8 @@ impl Yard {
     ) {
         let rect = self.rect;
 
-        let block_width = rect.w / (NUM_COLS as f32);
-        let block_height = rect.h / (NUM_ROWS as f32);
+        let block_width = rect.w / (self.num_cols as f32);
+        let block_height = rect.h / (self.num_rows as f32);
 
         let dest_size = Some(Vec2::new(block_width, block_height));
 
@@ -523,8 +481,8 @@ impl Yard {
         let y0 = rect.y;
 
         //render all tracktiles
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
                 let x_pos = x0 + c as f32 * block_width;
                 let y_pos = y0 + r as f32 * block_height;
 
@@ -532,7 +490,7 @@ impl Yard {
                 let mut h_flip = false;
                 let mut rot = 0;
 
-                match &self.tiles[r][c] {
+                match &self.sim.tiles[r][c] {
                     Tile::Tracktile(tracktile) => {
                         match tracktile.connection_type() {
                             ConnectionType::None => {}
@@ -750,6 +708,158 @@ impl Yard {
                             }
                         );
                     }
+                    Tile::Bridge(bridge) => {
+                        // the lower track. The deck of the bridge is drawn with the trains.
+                        draw_texture_ex(
+                            gs.tracktile_s,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: (bridge.upper_dir + 1) as f32 * PI/2.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                    }
+                    Tile::OneWay(oneway) => {
+                        // the same textures as a tracktile with the same connection, the arrows come later
+                        let connection = oneway.connection.undirected();
+                        let (texture, base) = if connection.dir1 % 2 == connection.dir2 % 2 {
+                            (gs.tracktile_s, Connection { dir1: 0, dir2: 2 })
+                        } else {
+                            (gs.tracktile_b, Connection { dir1: 2, dir2: 3 })
+                        };
+                        let rot = (0..4).find(|rot| base.rot(*rot) == connection).unwrap_or(0);
+                        draw_texture_ex(
+                            texture,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: rot as f32 * PI/2.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                    }
+                    Tile::Turntable(_) => {
+                        // the turntable itself is drawn with the trains, since it moves
+                        draw_texture_ex(
+                            gs.tracktile_blank,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: 0.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                    }
+                    Tile::Station(station) => {
+                        // the exit texture covers the whole tile, so it goes first
+                        draw_texture_ex(
+                            gs.trainsource_exit,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: station.exit_dir as f32 * PI/2.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                        draw_texture_ex(
+                            gs.trainsink_entry,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: station.entry_dir as f32 * PI/2.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                    }
+                    Tile::Filter(filter) => {
+                        draw_texture_ex(
+                            gs.tracktile_blank,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: 0.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                        // the entries take the color of the trains that can go through
+                        for dir in [filter.connection.dir1, filter.connection.dir2] {
+                            draw_texture_ex(
+                                gs.trainsink_entry,
+                                x_pos,
+                                y_pos,
+                                filter.color.get_color(),
+                                DrawTextureParams { 
+                                    dest_size,
+                                    source: None,
+                                    rotation: dir as f32 * PI/2.,
+                                    flip_x: false,
+                                    flip_y: false,
+                                    pivot: None
+                                }
+                            );
+                        }
+                    }
+                    Tile::Tunnel(tunnel) => {
+                        draw_texture_ex(
+                            gs.tracktile_blank,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: 0.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                        draw_texture_ex(
+                            gs.trainsink_entry,
+                            x_pos,
+                            y_pos,
+                            WHITE,
+                            DrawTextureParams { 
+                                dest_size,
+                                source: None,
+                                rotation: tunnel.dir as f32 * PI/2.,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None
+                            }
+                        );
+                    }
                 }
             }
         }
@@ -770,20 +880,20 @@ impl Yard {
         }
 
         //render all trains on tracktiles
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
-                self.tiles[r][c].render_trains(gs, current_progress);
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
+                self.sim.tiles[r][c].render_trains(gs, current_progress);
             }
         }
 
 
         //render non tracktile tiles
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
                 let x_pos = x0 + c as f32 * block_width;
                 let y_pos = y0 + r as f32 * block_height;
 
-                match &self.tiles[r][c] {
+                match &self.sim.tiles[r][c] {
                     Tile::Tracktile(_) => {}
                     Tile::Trainsource(trainsource) => {
                         draw_texture_ex(
@@ -818,6 +928,8 @@ impl Yard {
                                         pivot: None,
                                     }
                                 );
+                                let icon_rect = trainsource.icon_rects[i];
+                                draw_glyph(color, icon_rect.x + icon_rect.w / 2., icon_rect.y + icon_rect.h / 2., icon_rect.w * 0.35, 1.);
                             }
                         }
                     }
@@ -855,6 +967,8 @@ impl Yard {
                                             pivot: None,
                                         }
                                     );
+                                    let icon_rect = trainsink.icon_rects[i];
+                                    draw_glyph(color, icon_rect.x + icon_rect.w / 2., icon_rect.y + icon_rect.h / 2., icon_rect.w * 0.45, 1.);
                                 }
                             }
                         } else {
@@ -904,6 +1018,7 @@ impl Yard {
                                 pivot: None,
                             }
                         );
+                        draw_glyph(painter.color, x_pos + block_width/2., y_pos + block_height/2., block_width * 0.2, 1.);
                     }
                     Tile::Splitter(splitter) => {
                         draw_texture_ex(
@@ -921,53 +1036,64 @@ impl Yard {
                             }
                         );
                     }
+                    Tile::Filter(filter) => {
+                        let size = block_width * 0.3;
+                        draw_texture_ex(
+                            gs.circle, 
+                            x_pos + (block_width - size)/2.,
+                            y_pos + (block_height - size)/2.,
+                            filter.color.get_color(),
+                            DrawTextureParams { 
+                                dest_size: Some(Vec2::new(size, size)),
+                                source: None,
+                                rotation: 0.0,
+                                flip_x: false,
+                                flip_y: false,
+                                pivot: None,
+                            }
+                        );
+                        draw_glyph(filter.color, x_pos + block_width/2., y_pos + block_height/2., size * 0.5, 1.);
+                    }
+                    Tile::Bridge(_) | Tile::Turntable(_) => {}
+                    Tile::Station(station) => {
+                        station.render_countdown();
+                    }
+                    Tile::OneWay(oneway) => {
+                        oneway.render_arrows();
+                    }
+                    Tile::Tunnel(tunnel) => {
+                        // the trains disappear into the portal, and the id shows which portals are linked.
+                        let (center_x, center_y) = (x_pos + block_width/2., y_pos + block_height/2.);
+                        draw_circle(center_x, center_y, block_width * 0.3, DARKGRAY);
+                        draw_circle_lines(center_x, center_y, block_width * 0.3, block_width * 0.04, BLACK);
+                        let id = tunnel.id.to_string();
+                        let font_size = block_height * 0.35;
+                        let text_size = measure_text(&id, None, font_size as u16, 1.);
+                        draw_text(&id, center_x - text_size.width/2., center_y + text_size.height/2., font_size, WHITE);
+                    }
                 }
             }
         }
     }
 
-    pub fn set_rect(&mut self, rect: Rect, gs: &GameSprites) {
-        self.rect = rect;
-        let (x, y, w, h) = (rect.x, rect.y, rect.w, rect.h);
-        let w = w/(NUM_COLS as f32);
-        let h = h/(NUM_ROWS as f32);
-        let half_w = w/2.;
-        let half_h = h/2.;
-        for row in 0..NUM_ROWS {
-            for col in 0..NUM_COLS {
-                self.tiles[row][col].set_rect(
-                    Rect::new(
-                        x + (w*col as f32),
-                        y + (h*row as f32),
-                        w,
-                        h,
-                    ),
-                    gs,
-                )
-            }
-        }
-
-        for r in 0..(NUM_ROWS + 1) {
-            for c in 0..NUM_COLS {
-                let x_pos = rect.x + half_w + c as f32 * w;
-                let y_pos = rect.y + r as f32 * h;
-                self.h_edges[r][c].set_pos(x_pos, y_pos);
-            }
-        }
-
-        for r in 0..NUM_ROWS {
-            for c in 0..(NUM_COLS + 1) {
-                let x_pos = rect.x + c as f32 * w;
-                let y_pos = rect.y + half_h + r as f32 * h;
-                self.v_edges[r][c].set_pos(x_pos, y_pos);
+    pub fn set_rect(&mut self, area: Rect, gs: &GameSprites) {
+        // tiles are always square, so a yard that isn't square only uses part of the area it is given.
+        // it is centered inside that area.
+        let tile_size = (area.w / self.num_cols as f32).min(area.h / self.num_rows as f32);
+        let (w, h) = (tile_size * self.num_cols as f32, tile_size * self.num_rows as f32);
+        self.rect = Rect::new(area.x + (area.w - w) / 2., area.y + (area.h - h) / 2., w, h);
+        for row in 0..self.num_rows {
+            for col in 0..self.num_cols {
+                let tile_rect = self.tile_rect(row, col);
+                self.sim.tiles[row][col].set_rect(tile_rect, gs);
             }
         }
     }
 
     pub fn get_current_progress(&self) -> LevelProgress {
         let mut connection_vec = vec![];
-        for r in 0..NUM_ROWS {
-            for c in 0..NUM_COLS {
+        for r in 0..self.num_rows {
+            for c in 0..self.num_cols {
                 if let Tile::Tracktile(tracktile) = &self.drawn_tiles[r][c] {
                     if tracktile.connection_type() != ConnectionType::None {
                         connection_vec.push(PositionedTile{
@@ -983,3 +1109,15 @@ impl Yard {
         (connection_vec, has_won)
     }
 }
+
+fn draw_debug_arrow(x: f32, y: f32, dir: u8, size: f32, color: Color) {
+    // a triangle centered on (x, y) pointing in the direction dir.
+    let angle = dir as f32 * PI / 2.;
+    let point = |forward: f32, side: f32| Vec2::new(
+        x + forward * angle.sin() + side * angle.cos(),
+        y - forward * angle.cos() + side * angle.sin(),
+    );
+    let (tip, left, right) = (point(size, 0.), point(-size, -size), point(-size, size));
+    draw_triangle(tip, left, right, color);
+    draw_triangle_lines(tip, left, right, 1., BLACK);
+}